@@ -0,0 +1 @@
+ALTER TABLE screenshots DROP COLUMN project_path;
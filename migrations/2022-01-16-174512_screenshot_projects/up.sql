@@ -0,0 +1 @@
+ALTER TABLE screenshots ADD COLUMN project_path TEXT;
@@ -0,0 +1,9 @@
+ALTER TABLE screenshots DROP COLUMN monitor;
+ALTER TABLE screenshots DROP COLUMN window_class;
+ALTER TABLE screenshots DROP COLUMN window_title;
+ALTER TABLE screenshots DROP COLUMN capture_mode;
+ALTER TABLE screenshots DROP COLUMN hash;
+ALTER TABLE screenshots DROP COLUMN format;
+ALTER TABLE screenshots DROP COLUMN size;
+ALTER TABLE screenshots DROP COLUMN height;
+ALTER TABLE screenshots DROP COLUMN width;
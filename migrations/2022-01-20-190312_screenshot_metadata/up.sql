@@ -0,0 +1,9 @@
+ALTER TABLE screenshots ADD COLUMN width INTEGER;
+ALTER TABLE screenshots ADD COLUMN height INTEGER;
+ALTER TABLE screenshots ADD COLUMN size BIGINT;
+ALTER TABLE screenshots ADD COLUMN format TEXT;
+ALTER TABLE screenshots ADD COLUMN hash TEXT;
+ALTER TABLE screenshots ADD COLUMN capture_mode TEXT;
+ALTER TABLE screenshots ADD COLUMN window_title TEXT;
+ALTER TABLE screenshots ADD COLUMN window_class TEXT;
+ALTER TABLE screenshots ADD COLUMN monitor TEXT;
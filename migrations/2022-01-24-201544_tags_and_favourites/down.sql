@@ -0,0 +1,3 @@
+DROP TABLE screenshot_tags;
+DROP TABLE tags;
+ALTER TABLE screenshots DROP COLUMN is_favourite;
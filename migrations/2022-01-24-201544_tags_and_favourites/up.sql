@@ -0,0 +1,13 @@
+ALTER TABLE screenshots ADD COLUMN is_favourite BOOLEAN NOT NULL DEFAULT 0;
+
+CREATE TABLE tags (
+    id INTEGER UNIQUE NOT NULL,
+    name TEXT UNIQUE NOT NULL,
+    PRIMARY KEY(id AUTOINCREMENT)
+);
+
+CREATE TABLE screenshot_tags (
+    screenshot_id INTEGER NOT NULL REFERENCES screenshots(id) ON DELETE CASCADE,
+    tag_id INTEGER NOT NULL REFERENCES tags(id) ON DELETE CASCADE,
+    PRIMARY KEY(screenshot_id, tag_id)
+);
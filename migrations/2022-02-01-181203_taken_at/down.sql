@@ -0,0 +1,2 @@
+DROP INDEX screenshots_by_taken_at;
+ALTER TABLE screenshots DROP COLUMN taken_at;
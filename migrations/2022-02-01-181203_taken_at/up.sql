@@ -0,0 +1,4 @@
+-- `time` keeps the offset from UTC the screenshot was taken at, so it can't be sorted as a string
+ALTER TABLE screenshots ADD COLUMN taken_at TEXT NOT NULL DEFAULT '';
+UPDATE screenshots SET taken_at = coalesce(strftime('%Y-%m-%dT%H:%M:%fZ', time), '');
+CREATE INDEX screenshots_by_taken_at ON screenshots(taken_at, id);
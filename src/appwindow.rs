@@ -1,12 +1,23 @@
-use gtk4::glib;
+use gtk4::{gio, glib};
 
 use crate::kcshot::KCShot;
 
 use crate::historymodel::HistoryModel;
 
+mod actions;
+mod details;
+mod export;
+mod import;
+mod integrity;
+mod retention;
+mod search;
+mod tags;
+mod thumbnails;
+
 glib::wrapper! {
     pub struct AppWindow(ObjectSubclass<underlying::AppWindow>)
-    @extends gtk4::Widget, gtk4::Window, gtk4::ApplicationWindow;
+    @extends gtk4::Widget, gtk4::Window, gtk4::ApplicationWindow,
+    @implements gio::ActionGroup, gio::ActionMap;
 }
 
 impl AppWindow {
@@ -17,10 +28,10 @@ impl AppWindow {
 }
 
 mod underlying {
-    use std::{ffi::OsString, process::Command};
+    use std::{cell::Cell, ffi::OsString, path::PathBuf, process::Command, rc::Rc};
 
     use gtk4::{
-        gio,
+        gdk, gio,
         glib::{self, clone, signal::Inhibit, ParamSpec},
         prelude::*,
         subclass::{
@@ -31,9 +42,16 @@ mod underlying {
     };
     use once_cell::sync::{Lazy, OnceCell};
 
-    use crate::{editor::EditorWindow, kcshot::KCShot};
+    use crate::{
+        editor::{self, EditorWindow},
+        kcshot::KCShot,
+    };
 
-    use crate::historymodel::RowData;
+    use super::{
+        actions::{self, RowDataGetter},
+        details, thumbnails,
+    };
+    use crate::historymodel::{DeletionMode, RowData};
 
     #[derive(Default, Debug)]
     pub struct AppWindow {
@@ -55,6 +73,30 @@ mod underlying {
 
             let list_model = self.history_model.get().unwrap();
 
+            let capture_last_region = gio::SimpleAction::new("capture-last-region", None);
+            capture_last_region.connect_activate(
+                clone!(@weak obj, @weak list_model => move |_, _| {
+                    match obj.application().and_then(|app| app.downcast::<KCShot>().ok()) {
+                        Some(app) => editor::capture_last_region(&app, &list_model),
+                        None => tracing::error!("AppWindow has no KCShot application"),
+                    }
+                }),
+            );
+            obj.add_action(&capture_last_region);
+
+            let search_bar = super::search::build_search_bar(list_model);
+            search_bar.set_key_capture_widget(Some(obj));
+            let search = gio::SimpleAction::new("search", None);
+            search.connect_activate(clone!(@weak search_bar => move |_, _| {
+                search_bar.set_search_mode(true);
+            }));
+            obj.add_action(&search);
+
+            if let Some(app) = obj.application() {
+                app.set_accels_for_action("win.capture-last-region", &["<Ctrl><Shift>r"]);
+                app.set_accels_for_action("win.search", &["<Ctrl>f"]);
+            }
+
             let (settings_window, button_list) =
                 build_button_pane(&dbg!(obj.application()).unwrap(), list_model);
             self.window
@@ -64,9 +106,62 @@ mod underlying {
             left_frame.set_child(Some(&button_list));
             hbox.append(&left_frame);
 
-            let factory = build_item_factory();
+            let factory = build_item_factory(&obj.application().unwrap(), list_model);
+
+            let selection_model = gtk4::MultiSelection::new(Some(list_model));
+
+            let show_details = gio::SimpleAction::new("show-details", None);
+            show_details.connect_activate(
+                clone!(@weak obj, @weak list_model, @weak selection_model => move |_, _| {
+                    match selected_rows(&selection_model).into_iter().next() {
+                        Some(row_data) => {
+                            details::build_details_window(&obj.application().unwrap(), &list_model, &row_data)
+                                .show()
+                        }
+                        None => tracing::info!("No screenshot is selected"),
+                    }
+                }),
+            );
+            obj.add_action(&show_details);
+
+            let trash_selected = gio::SimpleAction::new("trash-selected", None);
+            trash_selected.connect_activate(
+                clone!(@weak list_model, @weak selection_model => move |_, _| {
+                    list_model.delete(&selected_rows(&selection_model), DeletionMode::Trash);
+                }),
+            );
+            obj.add_action(&trash_selected);
+
+            let delete_selected = gio::SimpleAction::new("delete-selected", None);
+            delete_selected.connect_activate(
+                clone!(@weak obj, @weak list_model, @weak selection_model => move |_, _| {
+                    actions::confirm_permanent_deletion(
+                        Some(obj.upcast_ref()),
+                        &list_model,
+                        selected_rows(&selection_model),
+                    );
+                }),
+            );
+            obj.add_action(&delete_selected);
+
+            // Without a selection, everything that's shown gets exported
+            let export = gio::SimpleAction::new("export", None);
+            export.connect_activate(
+                clone!(@weak obj, @weak list_model, @weak selection_model => move |_, _| {
+                    super::export::export(
+                        Some(obj.upcast_ref()),
+                        &list_model,
+                        selected_rows(&selection_model),
+                    );
+                }),
+            );
+            obj.add_action(&export);
 
-            let selection_model = gtk4::SingleSelection::new(Some(list_model));
+            let undo_deletion = gio::SimpleAction::new("undo-deletion", None);
+            undo_deletion.connect_activate(clone!(@weak list_model => move |_, _| {
+                list_model.undo_last_deletion();
+            }));
+            obj.add_action(&undo_deletion);
 
             let stack = gtk4::Stack::new();
 
@@ -98,11 +193,37 @@ mod underlying {
 
             let image_grid = gtk4::GridView::new(Some(&selection_model), Some(&factory));
             image_grid.set_min_columns(3);
+            // These only work while the grid has the focus, application wide accelerators would
+            // take the keys away from the search and tag entries
+            let shortcuts = gtk4::ShortcutController::new();
+            for (trigger, action) in [
+                ("Delete", "win.trash-selected"),
+                ("<Shift>Delete", "win.delete-selected"),
+                ("<Ctrl>z", "win.undo-deletion"),
+                ("<Ctrl>e", "win.export"),
+            ] {
+                shortcuts.add_shortcut(&gtk4::Shortcut::new(
+                    gtk4::ShortcutTrigger::parse_string(trigger).as_ref(),
+                    Some(&gtk4::NamedAction::new(action)),
+                ));
+            }
+            image_grid.add_controller(&shortcuts);
+            image_grid.connect_activate(clone!(@weak obj, @weak list_model => move |_, position| {
+                if let Some(item) = list_model.item(position) {
+                    let row_data = item.downcast::<RowData>().unwrap();
+                    actions::open_in_editor(&obj.application().unwrap(), &list_model, &row_data);
+                }
+            }));
             let history_view = gtk4::ScrolledWindow::new();
             history_view.set_child(Some(&image_grid));
             history_view.set_propagate_natural_width(true);
             history_view.set_min_content_height(600);
-            stack.add_named(&history_view, Some("image-grid"));
+            history_view.set_vexpand(true);
+            let history_page = gtk4::Box::new(gtk4::Orientation::Vertical, 0);
+            history_page.append(&build_undo_bar(list_model));
+            history_page.append(&search_bar);
+            history_page.append(&history_view);
+            stack.add_named(&history_page, Some("image-grid"));
             stack.add_named(&message, Some("message"));
 
             self.settings
@@ -133,6 +254,29 @@ mod underlying {
                 }),
             );
 
+            let drop_target =
+                gtk4::DropTarget::new(gio::File::static_type(), gdk::DragAction::COPY);
+            drop_target.connect_drop(
+                clone!(@weak obj, @weak list_model => @default-return false, move |_, value, _, _| {
+                    let path = match value.get::<gio::File>().ok().and_then(|file| file.path()) {
+                        Some(path) => path,
+                        None => return false,
+                    };
+
+                    match EditorWindow::from_file(&obj.application().unwrap(), &list_model, &path) {
+                        Ok(editor_window) => {
+                            editor_window.show();
+                            true
+                        }
+                        Err(why) => {
+                            tracing::error!("Failed to open dropped file {:?}: {}", path, why);
+                            false
+                        }
+                    }
+                }),
+            );
+            obj.add_controller(&drop_target);
+
             let right_frame = gtk4::Frame::new(None);
             right_frame.set_child(Some(&stack));
 
@@ -206,17 +350,91 @@ mod underlying {
         }
     }
 
-    fn build_item_factory() -> SignalListItemFactory {
+    fn build_item_factory(
+        application: &gtk4::Application,
+        history_model: &super::HistoryModel,
+    ) -> SignalListItemFactory {
         let factory = gtk4::SignalListItemFactory::new();
 
-        factory.connect_setup(|_this, list_item| {
+        factory.connect_setup(clone!(@weak application, @weak history_model => move |_this, list_item| {
             let picture = gtk4::Picture::builder()
                 .height_request(400)
                 .width_request(300)
                 .build();
 
-            list_item.set_child(Some(&picture));
-        });
+            let row_data: RowDataGetter = Rc::new(clone!(@weak list_item => @default-return None, move || {
+                list_item.item().and_then(|item| item.downcast::<RowData>().ok())
+            }));
+
+            let favourite_button = gtk4::ToggleButton::builder()
+                .icon_name("starred-symbolic")
+                .tooltip_text("Favourite")
+                .build();
+            // Unlike `toggled`, `clicked` isn't emitted when the state is set while binding
+            favourite_button.connect_clicked(
+                clone!(@weak history_model, @strong row_data => move |button| {
+                    if let Some(row_data) = row_data() {
+                        history_model.set_favourite(&row_data, button.is_active());
+                    }
+                }),
+            );
+
+            let tags_button = gtk4::MenuButton::builder().label("Tags").build();
+            tags_button.set_popover(Some(&super::tags::build_tag_popover(
+                &history_model,
+                row_data.clone(),
+            )));
+
+            let buttons = gtk4::Box::new(gtk4::Orientation::Horizontal, 2);
+            buttons.set_halign(gtk4::Align::End);
+            buttons.set_valign(gtk4::Align::Start);
+            buttons.append(&favourite_button);
+            buttons.append(&tags_button);
+
+            // Shown instead of the picture when the screenshot's file isn't there anymore
+            let missing_marker = gtk4::Box::new(gtk4::Orientation::Vertical, 6);
+            missing_marker.set_halign(gtk4::Align::Center);
+            missing_marker.set_valign(gtk4::Align::Center);
+            let missing_icon = gtk4::Image::from_icon_name(Some("image-missing"));
+            missing_icon.set_pixel_size(64);
+            missing_marker.append(&missing_icon);
+            missing_marker.append(&gtk4::Label::new(Some("File missing")));
+            missing_marker.set_visible(false);
+
+            let overlay = gtk4::Overlay::new();
+            overlay.set_child(Some(&picture));
+            overlay.add_overlay(&missing_marker);
+            overlay.add_overlay(&buttons);
+
+            let action_group = actions::build_action_group(&application, &history_model, row_data);
+            overlay.insert_action_group("item", Some(&action_group));
+
+            let context_menu = gtk4::PopoverMenu::from_model(Some(&actions::build_menu()));
+            context_menu.set_parent(&overlay);
+            context_menu.set_has_arrow(false);
+            // Popovers have to be unparented by hand, otherwise GTK complains when the list item
+            // is destroyed
+            overlay.connect_destroy(clone!(@weak context_menu => move |_| context_menu.unparent()));
+            let right_click = gtk4::GestureClick::new();
+            right_click.set_button(gdk::BUTTON_SECONDARY);
+            right_click.connect_pressed(
+                clone!(@weak context_menu, @weak action_group, @weak list_item => move |_, _, x, y| {
+                    if let Some(row_data) = list_item.item().and_then(|item| item.downcast::<RowData>().ok()) {
+                        actions::update_action_group(&action_group, &row_data);
+                    }
+                    context_menu.set_pointing_to(&gdk::Rectangle {
+                        x: x as i32,
+                        y: y as i32,
+                        width: 1,
+                        height: 1,
+                    });
+                    context_menu.popup();
+                }),
+            );
+            overlay.add_controller(&right_click);
+
+            list_item.set_child(Some(&overlay));
+        }));
 
         factory.connect_bind(|_this, list_item| {
             let object = list_item
@@ -225,20 +443,90 @@ mod underlying {
                 .downcast::<RowData>()
                 .unwrap();
 
-            let picture = list_item
+            let overlay = list_item
                 .child()
                 .expect("The child has to exist")
-                .downcast::<gtk4::Picture>()
-                .expect("The child has to be a gtk4::Picture");
-
+                .downcast::<gtk4::Overlay>()
+                .expect("The child has to be a gtk4::Overlay");
+            let picture = overlay
+                .child()
+                .and_then(|child| child.downcast::<gtk4::Picture>().ok())
+                .expect("The overlay's child has to be a gtk4::Picture");
+            let favourite_button = overlay
+                .last_child()
+                .and_then(|buttons| buttons.first_child())
+                .and_then(|button| button.downcast::<gtk4::ToggleButton>().ok())
+                .expect("The first overlaid button has to be a gtk4::ToggleButton");
+            let missing_marker = overlay
+                .last_child()
+                .and_then(|buttons| buttons.prev_sibling())
+                .expect("The missing file marker has to come before the buttons");
+
+            // List items get reused, so whatever the last item showed has to go. Whether the file
+            // is missing is found out while loading the thumbnail.
+            picture.set_paintable(None::<&gdk::Paintable>);
+            missing_marker.set_visible(false);
             if let Some(path) = object.path() {
-                picture.set_filename(&path);
+                load_thumbnail(list_item, &picture, &missing_marker, &object, path.into());
             }
+            picture.set_tooltip_text(Some(&tooltip_for(&object, false)));
+            favourite_button.set_active(object.is_favourite());
         });
 
         factory
     }
 
+    /// Shows the thumbnail of the screenshot at `path` in `picture` once it's ready, or
+    /// `missing_marker` if its file is missing, unless `list_item` has been bound to another
+    /// screenshot in the meantime
+    fn load_thumbnail(
+        list_item: &gtk4::ListItem,
+        picture: &gtk4::Picture,
+        missing_marker: &gtk4::Widget,
+        row_data: &RowData,
+        path: PathBuf,
+    ) {
+        let list_item = list_item.downgrade();
+        let picture = picture.downgrade();
+        let missing_marker = missing_marker.downgrade();
+        let id = row_data.id();
+        let thumbnail = thumbnails::thumbnail_for(path.clone(), row_data.hash());
+
+        glib::MainContext::default().spawn_local(async move {
+            let thumbnail = thumbnail.await;
+
+            let (list_item, picture, missing_marker) = match (
+                list_item.upgrade(),
+                picture.upgrade(),
+                missing_marker.upgrade(),
+            ) {
+                (Some(list_item), Some(picture), Some(missing_marker)) => {
+                    (list_item, picture, missing_marker)
+                }
+                _ => return,
+            };
+            let row_data = match list_item
+                .item()
+                .and_then(|item| item.downcast::<RowData>().ok())
+            {
+                Some(row_data) if row_data.id() == id => row_data,
+                _ => return,
+            };
+
+            match thumbnail {
+                Ok(thumbnail) => picture.set_filename(&thumbnail),
+                Err(thumbnails::Error::Missing) => {
+                    missing_marker.set_visible(true);
+                    picture.set_tooltip_text(Some(&tooltip_for(&row_data, true)));
+                }
+                Err(why) => {
+                    tracing::warn!("Failed to get a thumbnail for {:?}: {}", path, why);
+                    picture.set_filename(&path);
+                }
+            }
+        });
+    }
+
     fn build_button_pane(
         application: &gtk4::Application,
         history_model: &super::HistoryModel,
@@ -258,9 +546,23 @@ mod underlying {
         );
         buttons.append(&capture_button);
 
+        let capture_last_region_button = gtk4::Button::new();
+        capture_last_region_button.set_child(Some(&make_label("Capture last region")));
+        capture_last_region_button.set_action_name(Some("win.capture-last-region"));
+        buttons.append(&capture_last_region_button);
+
+        let annotate_clipboard_button = gtk4::Button::new();
+        annotate_clipboard_button.set_child(Some(&make_label("Annotate clipboard")));
+        annotate_clipboard_button.connect_clicked(
+            glib::clone!(@weak application, @weak history_model => move |_| {
+                editor::edit_clipboard_image(&application, &history_model);
+            }),
+        );
+        buttons.append(&annotate_clipboard_button);
+
         let settings_button = gtk4::Button::new();
         settings_button.set_child(Some(&make_label("Settings")));
-        let settings_window = build_settings_window();
+        let settings_window = build_settings_window(history_model);
         let settings_window_ = settings_window.clone();
         settings_button.connect_clicked(move |_| settings_window_.show());
         buttons.append(&settings_button);
@@ -279,13 +581,14 @@ mod underlying {
 
         let history_button = gtk4::Button::new();
         history_button.set_child(Some(&make_label("History")));
-        history_button.connect_clicked(|_| tracing::error!("TODO: Implement history button"));
+        history_button.set_tooltip_text(Some("Show the details of the selected screenshot"));
+        history_button.set_action_name(Some("win.show-details"));
         buttons.append(&history_button);
 
         (settings_window, buttons)
     }
 
-    fn build_settings_window() -> gtk4::Window {
+    fn build_settings_window(history_model: &super::HistoryModel) -> gtk4::Window {
         let window = gtk4::Window::new();
         window.set_title(Some("kcshot - Settings"));
         let settings = gio::Settings::new("kc.kcshot");
@@ -352,6 +655,8 @@ mod underlying {
         history_enabled.append(&history_enabled_button);
 
         content_area.append(&history_enabled);
+        content_area.append(&super::import::build_import_button(history_model));
+        content_area.append(&super::integrity::build_integrity_button(history_model));
         content_area.set_margin_top(5);
         content_area.set_margin_bottom(10);
         content_area.set_margin_start(10);
@@ -359,6 +664,10 @@ mod underlying {
 
         let notebook = gtk4::Notebook::new();
         notebook.append_page(&content_area, Some(&gtk4::Label::new(Some("General"))));
+        notebook.append_page(
+            &super::retention::build_retention_page(history_model),
+            Some(&gtk4::Label::new(Some("History cleanup"))),
+        );
 
         window.set_child(Some(&notebook));
 
@@ -370,6 +679,98 @@ mod underlying {
         std::env::current_dir().unwrap().into_os_string()
     }
 
+    /// Returns the screenshots which are selected in the history grid
+    fn selected_rows(selection_model: &gtk4::MultiSelection) -> Vec<RowData> {
+        let selection = selection_model.selection();
+
+        (0..selection.size() as u32)
+            .filter_map(|nth| selection_model.item(selection.nth(nth)))
+            .filter_map(|item| item.downcast::<RowData>().ok())
+            .collect()
+    }
+
+    /// Builds the bar which offers to undo deleting screenshots for a little while after they were
+    /// deleted
+    fn build_undo_bar(history_model: &super::HistoryModel) -> gtk4::InfoBar {
+        const UNDO_TIMEOUT_SECONDS: u32 = 10;
+
+        let label = gtk4::Label::new(None);
+        let undo_bar = gtk4::InfoBar::new();
+        undo_bar.add_child(&label);
+        undo_bar.add_button("Undo", gtk4::ResponseType::Accept);
+        undo_bar.set_show_close_button(true);
+        undo_bar.set_revealed(false);
+
+        undo_bar.connect_response(clone!(@weak history_model => move |undo_bar, response| {
+            if response == gtk4::ResponseType::Accept {
+                history_model.undo_last_deletion();
+            }
+            undo_bar.set_revealed(false);
+        }));
+
+        // Every deletion hides the bar after the timeout, unless another deletion happened since
+        let deletions = Rc::new(Cell::new(0u32));
+        history_model.connect_screenshots_deleted(
+            clone!(@weak undo_bar, @weak label => move |_, n_deleted, can_undo| {
+                if !can_undo {
+                    undo_bar.set_revealed(false);
+                    return;
+                }
+
+                label.set_text(&match n_deleted {
+                    1 => "Deleted a screenshot".to_owned(),
+                    n => format!("Deleted {} screenshots", n),
+                });
+                undo_bar.set_revealed(true);
+
+                let deletion = deletions.get().wrapping_add(1);
+                deletions.set(deletion);
+                glib::timeout_add_seconds_local(
+                    UNDO_TIMEOUT_SECONDS,
+                    clone!(@weak undo_bar, @strong deletions => @default-return glib::Continue(false), move || {
+                        if deletions.get() == deletion {
+                            undo_bar.set_revealed(false);
+                        }
+                        glib::Continue(false)
+                    }),
+                );
+            }),
+        );
+
+        undo_bar
+    }
+
+    /// Describes the screenshot in `row_data`, leaving out whatever we don't know about it
+    fn tooltip_for(row_data: &RowData, is_missing: bool) -> String {
+        let mut lines = vec![row_data.time()];
+
+        if let (Some(path), true) = (row_data.path(), is_missing) {
+            lines.push(format!("The file is missing, it was at {}", path));
+        }
+
+        if let Some((width, height)) = row_data.dimensions() {
+            lines.push(format!("{}×{}", width, height));
+        }
+        if let Some(size) = row_data.size() {
+            lines.push(glib::format_size(size as u64).into());
+        }
+        if let Some(capture_mode) = row_data.capture_mode() {
+            lines.push(format!("Captured from: {}", capture_mode));
+        }
+        match (row_data.window_title(), row_data.window_class()) {
+            (Some(title), Some(class)) => lines.push(format!("Window: {} ({})", title, class)),
+            (Some(window), None) | (None, Some(window)) => {
+                lines.push(format!("Window: {}", window))
+            }
+            (None, None) => {}
+        }
+        if let Some(monitor) = row_data.monitor() {
+            lines.push(format!("Monitor: {}", monitor));
+        }
+
+        lines.join("\n")
+    }
+
     fn make_label(text: &str) -> gtk4::Label {
         let label = gtk4::Label::new(Some(text));
         label.set_halign(gtk4::Align::Start);
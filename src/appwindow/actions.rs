@@ -0,0 +1,364 @@
+//! The actions which can be taken on a screenshot from the history, they're grouped under the
+//! `item` prefix and shared by the history grid's context menu and the details window
+
+use std::{path::Path, process::Command, rc::Rc};
+
+use gtk4::{
+    gdk::{self, prelude::*},
+    gdk_pixbuf::Pixbuf,
+    gio,
+    glib::{self, clone, signal::Inhibit, ToVariant},
+    prelude::*,
+};
+
+use crate::{
+    editor::EditorWindow,
+    historymodel::{DeletionMode, HistoryModel, RowData},
+};
+
+/// Returns the screenshot the actions should act upon, if there is one
+pub type RowDataGetter = Rc<dyn Fn() -> Option<RowData>>;
+
+/// Builds the menu shown when right-clicking a screenshot in the history
+pub fn build_menu() -> gio::Menu {
+    let menu = gio::Menu::new();
+
+    let copy_section = gio::Menu::new();
+    copy_section.append(Some("Copy image"), Some("item.copy-image"));
+    copy_section.append(Some("Copy path"), Some("item.copy-path"));
+    copy_section.append(Some("Copy URL"), Some("item.copy-url"));
+    menu.append_section(None, &copy_section);
+
+    let open_section = gio::Menu::new();
+    open_section.append(Some("Open in editor"), Some("item.open-in-editor"));
+    open_section.append(Some("Open with default application"), Some("item.open"));
+    open_section.append(Some("Show in folder"), Some("item.reveal"));
+    menu.append_section(None, &open_section);
+
+    let other_section = gio::Menu::new();
+    other_section.append(Some("Details"), Some("item.details"));
+    other_section.append(Some("Pin to screen"), Some("item.pin"));
+    menu.append_section(None, &other_section);
+
+    let delete_section = gio::Menu::new();
+    delete_section.append(Some("Remove from history"), Some("item.remove"));
+    delete_section.append(Some("Move to trash"), Some("item.trash"));
+    delete_section.append(Some("Delete permanently"), Some("item.delete"));
+    menu.append_section(None, &delete_section);
+
+    let selection_section = gio::Menu::new();
+    selection_section.append(Some("Move selected to trash"), Some("win.trash-selected"));
+    selection_section.append(
+        Some("Delete selected permanently"),
+        Some("win.delete-selected"),
+    );
+    selection_section.append(Some("Export selected…"), Some("win.export"));
+    menu.append_section(None, &selection_section);
+
+    menu
+}
+
+/// Builds the `item` action group, the actions act on the screenshot returned by `row_data`
+pub fn build_action_group(
+    application: &gtk4::Application,
+    history_model: &HistoryModel,
+    row_data: RowDataGetter,
+) -> gio::SimpleActionGroup {
+    let group = gio::SimpleActionGroup::new();
+
+    let add_action = |name: &str, activate: Box<dyn Fn(&RowData)>| {
+        let action = gio::SimpleAction::new(name, None);
+        action.connect_activate(clone!(@strong row_data => move |_, _| {
+            if let Some(row_data) = row_data() {
+                activate(&row_data);
+            }
+        }));
+        group.add_action(&action);
+    };
+
+    add_action("copy-image", Box::new(copy_image));
+    add_action(
+        "copy-path",
+        Box::new(|row_data| {
+            if let Some(path) = row_data.path() {
+                copy_text(&path);
+            }
+        }),
+    );
+    add_action(
+        "copy-url",
+        Box::new(|row_data| {
+            if let Some(url) = row_data.url() {
+                copy_text(&url);
+            }
+        }),
+    );
+    add_action(
+        "open-in-editor",
+        Box::new(
+            clone!(@weak application, @weak history_model => move |row_data| {
+                open_in_editor(&application, &history_model, row_data);
+            }),
+        ),
+    );
+    add_action("open", Box::new(open_with_default_application));
+    add_action("reveal", Box::new(reveal_in_folder));
+    add_action(
+        "details",
+        Box::new(
+            clone!(@weak application, @weak history_model => move |row_data| {
+                super::details::build_details_window(&application, &history_model, row_data)
+                    .show();
+            }),
+        ),
+    );
+    add_action(
+        "pin",
+        Box::new(clone!(@weak application => move |row_data| {
+            pin_to_screen(&application, row_data);
+        })),
+    );
+    add_action(
+        "remove",
+        Box::new(clone!(@weak history_model => move |row_data| {
+            history_model.delete(std::slice::from_ref(row_data), DeletionMode::KeepFiles);
+        })),
+    );
+    add_action(
+        "trash",
+        Box::new(clone!(@weak history_model => move |row_data| {
+            history_model.delete(std::slice::from_ref(row_data), DeletionMode::Trash);
+        })),
+    );
+    add_action(
+        "delete",
+        Box::new(
+            clone!(@weak application, @weak history_model => move |row_data| {
+                let rows = vec![row_data.clone()];
+                confirm_permanent_deletion(application.active_window().as_ref(), &history_model, rows);
+            }),
+        ),
+    );
+
+    group
+}
+
+/// Enables only the actions which make sense for the screenshot in `row_data`
+pub fn update_action_group(group: &gio::SimpleActionGroup, row_data: &RowData) {
+    let has_file = matches!(row_data.path(), Some(path) if Path::new(&path).exists());
+
+    for (name, enabled) in [
+        ("copy-image", has_file),
+        ("copy-path", row_data.path().is_some()),
+        ("copy-url", row_data.url().is_some()),
+        (
+            "open-in-editor",
+            has_file || row_data.project_path().is_some(),
+        ),
+        ("open", has_file),
+        ("reveal", has_file),
+        ("pin", has_file),
+        ("trash", has_file),
+        ("delete", has_file),
+    ] {
+        if let Some(action) = group
+            .lookup_action(name)
+            .and_then(|action| action.downcast::<gio::SimpleAction>().ok())
+        {
+            action.set_enabled(enabled);
+        }
+    }
+}
+
+/// Opens a screenshot from the history in the editor, with its annotations still editable if
+/// it has a project, or just the image otherwise
+pub fn open_in_editor(
+    application: &gtk4::Application,
+    history_model: &HistoryModel,
+    row_data: &RowData,
+) {
+    let editor_window = match (row_data.project_path(), row_data.path()) {
+        (Some(project_path), _) if Path::new(&project_path).exists() => {
+            EditorWindow::from_project(application, history_model, Path::new(&project_path))
+        }
+        (_, Some(path)) => EditorWindow::from_file(application, history_model, Path::new(&path)),
+        (_, None) => {
+            tracing::info!("The screenshot has neither a path nor a project");
+            return;
+        }
+    };
+
+    match editor_window {
+        Ok(editor_window) => editor_window.show(),
+        Err(why) => tracing::error!("Failed to open screenshot in the editor: {}", why),
+    }
+}
+
+/// Asks whether the files of the screenshots in `rows` should really be deleted, and deletes
+/// them if so
+pub fn confirm_permanent_deletion(
+    parent: Option<&gtk4::Window>,
+    history_model: &HistoryModel,
+    rows: Vec<RowData>,
+) {
+    if rows.is_empty() {
+        return;
+    }
+
+    let text = match rows.len() {
+        1 => "Delete this screenshot permanently?".to_owned(),
+        n => format!("Delete {} screenshots permanently?", n),
+    };
+    let dialog = gtk4::MessageDialog::builder()
+        .modal(true)
+        .message_type(gtk4::MessageType::Warning)
+        .text(&text)
+        .secondary_text("Their files will be deleted as well, this can't be undone.")
+        .build();
+    dialog.set_transient_for(parent);
+    dialog.add_button("Cancel", gtk4::ResponseType::Cancel);
+    dialog
+        .add_button("Delete", gtk4::ResponseType::Accept)
+        .add_css_class("destructive-action");
+
+    dialog.connect_response(clone!(@weak history_model => move |dialog, response| {
+        if response == gtk4::ResponseType::Accept {
+            history_model.delete(&rows, DeletionMode::Permanent);
+        }
+        dialog.close();
+    }));
+    dialog.show();
+}
+
+fn clipboard() -> Option<gdk::Clipboard> {
+    match gdk::Display::default() {
+        Some(display) => Some(display.clipboard()),
+        None => {
+            tracing::error!("Failed to fetch gdk::Display, bailing...");
+            None
+        }
+    }
+}
+
+fn copy_image(row_data: &RowData) {
+    let path = match row_data.path() {
+        Some(path) => path,
+        None => return,
+    };
+
+    match Pixbuf::from_file(&path) {
+        Ok(pixbuf) => {
+            if let Some(clipboard) = clipboard() {
+                clipboard.set_texture(&gdk::Texture::for_pixbuf(&pixbuf));
+            }
+        }
+        Err(why) => tracing::error!("Failed to load {} to copy it: {}", path, why),
+    }
+}
+
+fn copy_text(text: &str) {
+    if let Some(clipboard) = clipboard() {
+        clipboard.set_text(text);
+    }
+}
+
+fn open_with_default_application(row_data: &RowData) {
+    let path = match row_data.path() {
+        Some(path) => path,
+        None => return,
+    };
+
+    let uri = gio::File::for_path(&path).uri();
+    if let Err(why) = gio::AppInfo::launch_default_for_uri(&uri, None::<&gio::AppLaunchContext>) {
+        tracing::error!("Failed to open {}: {}", path, why);
+    }
+}
+
+/// Shows the screenshot in the file manager, if the file manager doesn't let us select the file
+/// we settle for opening the folder it's in
+fn reveal_in_folder(row_data: &RowData) {
+    let path = match row_data.path() {
+        Some(path) => path,
+        None => return,
+    };
+
+    // See https://www.freedesktop.org/wiki/Specifications/file-manager-interface/
+    let uri = gio::File::for_path(&path).uri();
+    let res = gio::bus_get_sync(gio::BusType::Session, gio::NONE_CANCELLABLE).and_then(|bus| {
+        bus.call_sync(
+            Some("org.freedesktop.FileManager1"),
+            "/org/freedesktop/FileManager1",
+            "org.freedesktop.FileManager1",
+            "ShowItems",
+            Some(&(vec![uri.to_string()], "").to_variant()),
+            None,
+            gio::DBusCallFlags::NONE,
+            -1,
+            gio::NONE_CANCELLABLE,
+        )
+    });
+
+    if let Err(why) = res {
+        tracing::info!("The file manager couldn't show {}: {}", path, why);
+
+        let folder = Path::new(&path).parent().unwrap_or_else(|| Path::new("/"));
+        if let Err(why) = Command::new("xdg-open").arg(folder).spawn() {
+            tracing::error!("Failed to spawn xdg-open: {:?}", why);
+        }
+    }
+}
+
+/// Shows the screenshot in a borderless window which can be dragged around and is closed by
+/// double-clicking it or pressing escape
+fn pin_to_screen(application: &gtk4::Application, row_data: &RowData) {
+    let path = match row_data.path() {
+        Some(path) => path,
+        None => return,
+    };
+
+    let picture = gtk4::Picture::for_filename(&path);
+    let window = gtk4::ApplicationWindow::builder()
+        .application(application)
+        .title("Pinned screenshot")
+        .decorated(false)
+        .resizable(false)
+        .child(&picture)
+        .build();
+
+    let click = gtk4::GestureClick::new();
+    click.connect_pressed(clone!(@weak window => move |click, n_press, x, y| {
+        if n_press == 2 {
+            window.close();
+            return;
+        }
+
+        let toplevel = window
+            .surface()
+            .and_then(|surface| surface.downcast::<gdk::Toplevel>().ok());
+        if let (Some(toplevel), Some(device)) = (toplevel, click.current_event_device()) {
+            toplevel.begin_move(
+                &device,
+                click.current_button() as i32,
+                x,
+                y,
+                click.current_event_time(),
+            );
+        }
+    }));
+    window.add_controller(&click);
+
+    let key_controller = gtk4::EventControllerKey::new();
+    key_controller.connect_key_pressed(
+        clone!(@weak window => @default-return Inhibit(false), move |_, key, _, _| {
+            if key == gdk::keys::constants::Escape {
+                window.close();
+                Inhibit(true)
+            } else {
+                Inhibit(false)
+            }
+        }),
+    );
+    window.add_controller(&key_controller);
+
+    window.show();
+}
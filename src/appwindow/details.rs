@@ -0,0 +1,181 @@
+use std::{cell::Cell, rc::Rc};
+
+use gtk4::{
+    glib::{self, clone},
+    prelude::*,
+};
+
+use super::actions;
+use crate::historymodel::{HistoryModel, RowData};
+
+/// Builds a window which shows the screenshot at its full size, everything the history knows
+/// about it, and buttons for the actions from [`actions`]
+pub fn build_details_window(
+    application: &gtk4::Application,
+    history_model: &HistoryModel,
+    row_data: &RowData,
+) -> gtk4::Window {
+    let window = gtk4::Window::builder()
+        .application(application)
+        .title("Screenshot details")
+        .default_width(1000)
+        .default_height(700)
+        .build();
+    window.set_transient_for(application.active_window().as_ref());
+
+    let getter: actions::RowDataGetter =
+        Rc::new(clone!(@strong row_data => move || Some(row_data.clone())));
+    let action_group = actions::build_action_group(application, history_model, getter.clone());
+    actions::update_action_group(&action_group, row_data);
+    window.insert_action_group("item", Some(&action_group));
+
+    // Once the screenshot is gone from the history there's nothing left to show details for
+    let handler_id = history_model.connect_screenshots_deleted(
+        clone!(@weak window, @weak row_data => move |history_model, _, _| {
+            if !history_model.contains(&row_data) {
+                window.close();
+            }
+        }),
+    );
+    let handler_id = Cell::new(Some(handler_id));
+    window.connect_destroy(clone!(@weak history_model => move |_| {
+        if let Some(handler_id) = handler_id.take() {
+            history_model.disconnect(handler_id);
+        }
+    }));
+
+    let picture = gtk4::Picture::new();
+    if let Some(path) = row_data.path() {
+        picture.set_filename(&path);
+    }
+    let picture_view = gtk4::ScrolledWindow::builder()
+        .child(&picture)
+        .hexpand(true)
+        .vexpand(true)
+        .build();
+
+    let tags_label = make_value_label(&history_model.tags_of(row_data).join(", "));
+    let tags_popover = super::tags::build_tag_popover(history_model, getter);
+    tags_popover.connect_closed(
+        clone!(@weak history_model, @weak row_data, @weak tags_label => move |_| {
+            tags_label.set_text(&history_model.tags_of(&row_data).join(", "));
+        }),
+    );
+    let tags_button = gtk4::MenuButton::builder()
+        .label("Edit tags")
+        .popover(&tags_popover)
+        .build();
+
+    let rows = metadata_rows(row_data);
+    let tags_row = rows.len() as i32;
+    let metadata = build_metadata_grid(rows);
+    metadata.attach(&make_key_label("Tags"), 0, tags_row, 1, 1);
+    metadata.attach(&tags_label, 1, tags_row, 1, 1);
+    metadata.attach(&tags_button, 1, tags_row + 1, 1, 1);
+
+    let sidebar = gtk4::Box::new(gtk4::Orientation::Vertical, 12);
+    sidebar.set_margin_start(12);
+    sidebar.set_margin_end(12);
+    sidebar.append(&metadata);
+    sidebar.append(&build_action_buttons());
+
+    let hbox = gtk4::Box::new(gtk4::Orientation::Horizontal, 0);
+    hbox.append(&picture_view);
+    hbox.append(&sidebar);
+    window.set_child(Some(&hbox));
+
+    window
+}
+
+/// Returns the pieces of metadata to show as (name, value) pairs, those we don't know are skipped
+fn metadata_rows(row_data: &RowData) -> Vec<(&'static str, String)> {
+    let mut rows = vec![("Taken", row_data.time())];
+
+    let optional_rows = vec![
+        ("Path", row_data.path()),
+        ("URL", row_data.url()),
+        ("Project", row_data.project_path()),
+        (
+            "Dimensions",
+            row_data
+                .dimensions()
+                .map(|(width, height)| format!("{}×{}", width, height)),
+        ),
+        (
+            "Size",
+            row_data
+                .size()
+                .map(|size| glib::format_size(size as u64).into()),
+        ),
+        ("Format", row_data.format()),
+        ("Captured from", row_data.capture_mode()),
+        ("Window", row_data.window_title()),
+        ("Window class", row_data.window_class()),
+        ("Monitor", row_data.monitor()),
+        ("SHA-256", row_data.hash()),
+    ];
+    rows.extend(
+        optional_rows
+            .into_iter()
+            .filter_map(|(name, value)| Some((name, value?))),
+    );
+
+    rows
+}
+
+fn build_metadata_grid(rows: Vec<(&str, String)>) -> gtk4::Grid {
+    let grid = gtk4::Grid::builder()
+        .row_spacing(6)
+        .column_spacing(12)
+        .margin_top(12)
+        .build();
+
+    for (row, (name, value)) in rows.into_iter().enumerate() {
+        grid.attach(&make_key_label(name), 0, row as i32, 1, 1);
+        grid.attach(&make_value_label(&value), 1, row as i32, 1, 1);
+    }
+
+    grid
+}
+
+fn build_action_buttons() -> gtk4::Box {
+    let buttons = gtk4::Box::new(gtk4::Orientation::Vertical, 2);
+
+    for (label, action_name) in [
+        ("Copy image", "item.copy-image"),
+        ("Copy path", "item.copy-path"),
+        ("Copy URL", "item.copy-url"),
+        ("Open in editor", "item.open-in-editor"),
+        ("Open with default application", "item.open"),
+        ("Show in folder", "item.reveal"),
+        ("Pin to screen", "item.pin"),
+        ("Remove from history", "item.remove"),
+        ("Move to trash", "item.trash"),
+        ("Delete permanently", "item.delete"),
+    ] {
+        let button = gtk4::Button::with_label(label);
+        button.set_action_name(Some(action_name));
+        buttons.append(&button);
+    }
+
+    buttons
+}
+
+fn make_key_label(text: &str) -> gtk4::Label {
+    let label = gtk4::Label::new(Some(text));
+    label.set_halign(gtk4::Align::End);
+    label.set_valign(gtk4::Align::Start);
+    label.add_css_class("dim-label");
+    label
+}
+
+fn make_value_label(text: &str) -> gtk4::Label {
+    let label = gtk4::Label::new(Some(text));
+    label.set_halign(gtk4::Align::Start);
+    label.set_selectable(true);
+    label.set_wrap(true);
+    label.set_wrap_mode(gtk4::pango::WrapMode::WordChar);
+    label.set_max_width_chars(40);
+    label.set_xalign(0.0);
+    label
+}
@@ -0,0 +1,128 @@
+use std::path::{Path, PathBuf};
+
+use gtk4::{
+    glib::{self, clone},
+    prelude::*,
+};
+
+use crate::{
+    export::{self, Format},
+    historymodel::{HistoryModel, RowData},
+};
+
+/// Asks where to export the screenshots in `rows` to and exports them there, all the screenshots
+/// the filter lets through are exported if `rows` is empty. The format is picked by the
+/// extension of the chosen file, or by the chosen file filter if it doesn't have one we know.
+pub fn export(parent: Option<&gtk4::Window>, history_model: &HistoryModel, rows: Vec<RowData>) {
+    let title = match rows.len() {
+        0 => "Export the shown screenshots".to_owned(),
+        1 => "Export the selected screenshot".to_owned(),
+        n => format!("Export the {} selected screenshots", n),
+    };
+    let dialog = gtk4::FileChooserDialog::new(
+        Some(&title),
+        parent,
+        gtk4::FileChooserAction::Save,
+        &[
+            ("Cancel", gtk4::ResponseType::Cancel),
+            ("Export", gtk4::ResponseType::Accept),
+        ],
+    );
+    dialog.set_modal(true);
+    dialog.set_current_name("screenshots.zip");
+
+    let filters = [
+        (Format::Zip, "Zip archive with the images (*.zip)", "*.zip"),
+        (Format::Json, "JSON (*.json)", "*.json"),
+        (Format::Csv, "CSV (*.csv)", "*.csv"),
+    ]
+    .iter()
+    .map(|(format, name, pattern)| {
+        let filter = gtk4::FileFilter::new();
+        filter.set_name(Some(name));
+        filter.add_pattern(pattern);
+        dialog.add_filter(&filter);
+        (*format, filter)
+    })
+    .collect::<Vec<_>>();
+
+    dialog.connect_response(clone!(@weak history_model => move |dialog, response| {
+        let path = dialog.file().and_then(|file| file.path());
+        let chosen_format = dialog.filter().and_then(|chosen| {
+            filters
+                .iter()
+                .find(|(_, filter)| *filter == chosen)
+                .map(|(format, _)| *format)
+        });
+        let parent = dialog.transient_for();
+        dialog.destroy();
+
+        if let (Some(path), gtk4::ResponseType::Accept) = (path, response) {
+            let path = with_extension_for(path, chosen_format);
+            let entries = if rows.is_empty() {
+                history_model.filtered_export_entries()
+            } else {
+                history_model.export_entries(&rows)
+            };
+
+            match entries {
+                Ok(entries) => export_in_background(parent, entries, path),
+                Err(why) => show_result(parent.as_ref(), &path, Err(why.into())),
+            }
+        }
+    }));
+    dialog.show();
+}
+
+/// Writes the export on another thread, since archives copy every image, and tells how it went
+/// once it's done
+fn export_in_background(parent: Option<gtk4::Window>, entries: Vec<export::Entry>, path: PathBuf) {
+    let (sender, receiver) =
+        glib::MainContext::channel::<Result<usize, export::Error>>(glib::PRIORITY_DEFAULT);
+    let exported_path = path.clone();
+    std::thread::spawn(move || {
+        let _ = sender.send(export::export(entries, &exported_path));
+    });
+
+    receiver.attach(None, move |res| {
+        show_result(parent.as_ref(), &path, res);
+        glib::Continue(false)
+    });
+}
+
+fn show_result(parent: Option<&gtk4::Window>, path: &Path, res: Result<usize, export::Error>) {
+    let (message_type, text) = match res {
+        Ok(n_exported) => (
+            gtk4::MessageType::Info,
+            format!("Exported {} screenshots to {}", n_exported, path.display()),
+        ),
+        Err(why) => {
+            tracing::error!("Failed to export to {:?}: {}", path, why);
+            (gtk4::MessageType::Error, why.to_string())
+        }
+    };
+    let message = gtk4::MessageDialog::builder()
+        .modal(true)
+        .message_type(message_type)
+        .buttons(gtk4::ButtonsType::Ok)
+        .text(&text)
+        .build();
+    message.set_transient_for(parent);
+    message.connect_response(|message, _| message.close());
+    message.show();
+}
+
+/// Gives `path` the extension of `format` unless it already has one we know
+fn with_extension_for(path: PathBuf, format: Option<Format>) -> PathBuf {
+    let extension = match format {
+        _ if Format::for_path(&path).is_ok() => return path,
+        Some(Format::Json) => "json",
+        Some(Format::Csv) => "csv",
+        Some(Format::Zip) | None => "zip",
+    };
+
+    let mut path = path.into_os_string();
+    path.push(".");
+    path.push(extension);
+    path.into()
+}
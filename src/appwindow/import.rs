@@ -0,0 +1,90 @@
+use std::{io, path::PathBuf};
+
+use gtk4::{
+    glib::{self, clone},
+    prelude::*,
+};
+
+use crate::{historymodel::HistoryModel, import};
+
+const LABEL: &str = "Import a folder of screenshots…";
+
+/// Builds the button through which screenshots which are already on disk get imported into the
+/// history. Scanning the folder happens on another thread, since it hashes every image.
+pub fn build_import_button(history_model: &HistoryModel) -> gtk4::Button {
+    let button = gtk4::Button::with_label(LABEL);
+    button.set_tooltip_text(Some(
+        "Adds the images in a folder and its subfolders to the history, together with what \
+         ShareX's History.json says about them if the folder has one",
+    ));
+
+    button.connect_clicked(clone!(@weak history_model => move |button| {
+        let parent = button.root().and_then(|root| root.downcast::<gtk4::Window>().ok());
+        let folder_chooser = gtk4::FileChooserDialog::new(
+            Some("Choose a folder to import screenshots from"),
+            parent.as_ref(),
+            gtk4::FileChooserAction::SelectFolder,
+            &[
+                ("Cancel", gtk4::ResponseType::Cancel),
+                ("Import", gtk4::ResponseType::Accept),
+            ],
+        );
+        folder_chooser.set_modal(true);
+
+        folder_chooser.connect_response(clone!(@weak history_model, @weak button => move |this, response| {
+            let folder = this.file().and_then(|file| file.path());
+            this.destroy();
+
+            match folder {
+                Some(folder) if response == gtk4::ResponseType::Accept => {
+                    import_in_background(&history_model, &button, folder)
+                }
+                _ => {}
+            }
+        }));
+        folder_chooser.show();
+    }));
+
+    button
+}
+
+fn import_in_background(history_model: &HistoryModel, button: &gtk4::Button, folder: PathBuf) {
+    button.set_sensitive(false);
+    button.set_label("Importing…");
+
+    let (sender, receiver) =
+        glib::MainContext::channel::<io::Result<import::Scan>>(glib::PRIORITY_DEFAULT);
+    let scanned_folder = folder.clone();
+    std::thread::spawn(move || {
+        let _ = sender.send(import::scan(&scanned_folder));
+    });
+
+    receiver.attach(
+        None,
+        clone!(@weak history_model, @weak button => @default-return glib::Continue(false), move |scan| {
+            button.set_sensitive(true);
+            button.set_label(LABEL);
+
+            let text = match scan {
+                Ok(scan) => history_model.import(scan).to_string(),
+                Err(why) => {
+                    tracing::error!("Failed to import {:?}: {}", folder, why);
+                    format!("Failed to import {}: {}", folder.display(), why)
+                }
+            };
+            let dialog = gtk4::MessageDialog::builder()
+                .modal(true)
+                .message_type(gtk4::MessageType::Info)
+                .buttons(gtk4::ButtonsType::Ok)
+                .text(&text)
+                .build();
+            dialog.set_transient_for(
+                button.root().and_then(|root| root.downcast::<gtk4::Window>().ok()).as_ref(),
+            );
+            dialog.connect_response(|dialog, _| dialog.close());
+            dialog.show();
+
+            glib::Continue(false)
+        }),
+    );
+}
@@ -0,0 +1,195 @@
+use std::{cell::RefCell, io, path::PathBuf, rc::Rc};
+
+use gtk4::{
+    glib::{self, clone},
+    prelude::*,
+};
+
+use crate::{
+    historymodel::{DeletionMode, HistoryModel, RowData},
+    integrity,
+};
+
+/// Builds the button which opens the window through which screenshots whose files went missing
+/// are found again or removed from the history
+pub fn build_integrity_button(history_model: &HistoryModel) -> gtk4::Button {
+    let button = gtk4::Button::with_label("Check for missing files…");
+
+    button.connect_clicked(clone!(@weak history_model => move |button| {
+        let parent = button.root().and_then(|root| root.downcast::<gtk4::Window>().ok());
+        build_integrity_window(parent.as_ref(), &history_model).show();
+    }));
+
+    button
+}
+
+fn build_integrity_window(
+    parent: Option<&gtk4::Window>,
+    history_model: &HistoryModel,
+) -> gtk4::Window {
+    let window = gtk4::Window::builder()
+        .title("kcshot - Missing files")
+        .default_width(600)
+        .default_height(400)
+        .build();
+    window.set_transient_for(parent);
+
+    let summary = gtk4::Label::new(None);
+    summary.set_halign(gtk4::Align::Start);
+    summary.set_wrap(true);
+
+    let missing_list = gtk4::ListBox::new();
+    missing_list.set_selection_mode(gtk4::SelectionMode::None);
+    let missing_view = gtk4::ScrolledWindow::builder()
+        .child(&missing_list)
+        .vexpand(true)
+        .build();
+
+    let check_button = gtk4::Button::with_label("Check again");
+    let relink_button = gtk4::Button::with_label("Find them in a folder…");
+    relink_button.set_tooltip_text(Some(
+        "Looks for images with the same contents in a folder and its subfolders",
+    ));
+    let prune_button = gtk4::Button::with_label("Remove them from the history");
+    prune_button.add_css_class("destructive-action");
+
+    let missing = Rc::new(RefCell::new(Vec::<RowData>::new()));
+    let check = Rc::new(
+        clone!(@weak history_model, @weak summary, @weak missing_list, @weak relink_button, @weak prune_button, @strong missing => move || {
+            let rows = history_model.missing_screenshots();
+            fill_missing_list(&missing_list, &rows);
+            summary.set_text(&match rows.len() {
+                0 => "The files of all screenshots are where the history says they are.".to_owned(),
+                1 => "1 screenshot is missing its file.".to_owned(),
+                n => format!("{} screenshots are missing their files.", n),
+            });
+            relink_button.set_sensitive(!rows.is_empty());
+            prune_button.set_sensitive(!rows.is_empty());
+            missing.replace(rows);
+        }),
+    );
+    check();
+
+    check_button.connect_clicked(clone!(@strong check => move |_| check()));
+    prune_button.connect_clicked(
+        clone!(@weak history_model, @strong missing, @strong check => move |_| {
+            // The files are gone already, so this can still be undone from the history
+            history_model.delete(&missing.borrow(), DeletionMode::KeepFiles);
+            check();
+        }),
+    );
+    relink_button.connect_clicked(
+        clone!(@weak window, @weak history_model, @weak summary, @strong missing, @strong check => move |relink_button| {
+            let folder_chooser = gtk4::FileChooserDialog::new(
+                Some("Choose a folder to look for the missing screenshots in"),
+                Some(&window),
+                gtk4::FileChooserAction::SelectFolder,
+                &[
+                    ("Cancel", gtk4::ResponseType::Cancel),
+                    ("Search", gtk4::ResponseType::Accept),
+                ],
+            );
+            folder_chooser.set_modal(true);
+
+            folder_chooser.connect_response(
+                clone!(@weak history_model, @weak summary, @weak relink_button, @strong missing, @strong check => move |this, response| {
+                    let folder = this.file().and_then(|file| file.path());
+                    this.destroy();
+
+                    if let (Some(folder), gtk4::ResponseType::Accept) = (folder, response) {
+                        relink_button.set_sensitive(false);
+                        summary.set_text("Looking for the missing screenshots…");
+                        relink_in_background(&history_model, missing.clone(), check.clone(), summary, folder);
+                    }
+                }),
+            );
+            folder_chooser.show();
+        }),
+    );
+
+    let buttons = gtk4::Box::new(gtk4::Orientation::Horizontal, 6);
+    buttons.append(&check_button);
+    buttons.append(&relink_button);
+    buttons.append(&prune_button);
+
+    let content_area = gtk4::Box::new(gtk4::Orientation::Vertical, 8);
+    content_area.append(&summary);
+    content_area.append(&missing_view);
+    content_area.append(&buttons);
+    content_area.set_margin_top(10);
+    content_area.set_margin_bottom(10);
+    content_area.set_margin_start(10);
+    content_area.set_margin_end(10);
+    window.set_child(Some(&content_area));
+
+    window
+}
+
+/// Indexes the images in `folder` on another thread, and then points the screenshots in `missing`
+/// at those which are theirs
+fn relink_in_background(
+    history_model: &HistoryModel,
+    missing: Rc<RefCell<Vec<RowData>>>,
+    check: Rc<dyn Fn()>,
+    summary: gtk4::Label,
+    folder: PathBuf,
+) {
+    let (sender, receiver) =
+        glib::MainContext::channel::<io::Result<integrity::Index>>(glib::PRIORITY_DEFAULT);
+    let indexed_folder = folder.clone();
+    std::thread::spawn(move || {
+        let _ = sender.send(integrity::index(&indexed_folder));
+    });
+
+    receiver.attach(
+        None,
+        clone!(@weak history_model => @default-return glib::Continue(false), move |index| {
+            let n_missing = missing.borrow().len();
+            let text = match index {
+                Ok(index) => {
+                    let relinked = history_model.relink(&missing.borrow(), &index);
+                    let mut text = format!(
+                        "Found {} of the {} missing screenshots in {}.",
+                        relinked.relinked, n_missing, folder.display()
+                    );
+                    if relinked.unmatchable > 0 {
+                        text.push_str(&format!(
+                            " {} can't be found anywhere, the history knows nothing but their file names.",
+                            relinked.unmatchable
+                        ));
+                    }
+                    text
+                }
+                Err(why) => {
+                    tracing::error!("Failed to look for screenshots in {:?}: {}", folder, why);
+                    format!("Failed to look for screenshots in {}: {}", folder.display(), why)
+                }
+            };
+
+            check();
+            // Checking overwrites the summary, but what was found is more interesting right now
+            summary.set_text(&format!("{} {}", text, summary.text()));
+
+            glib::Continue(false)
+        }),
+    );
+}
+
+fn fill_missing_list(missing_list: &gtk4::ListBox, rows: &[RowData]) {
+    while let Some(child) = missing_list.first_child() {
+        missing_list.remove(&child);
+    }
+
+    for row_data in rows {
+        let text = format!(
+            "{}  {}",
+            row_data.time(),
+            row_data.path().unwrap_or_default()
+        );
+        let label = gtk4::Label::new(Some(&text));
+        label.set_halign(gtk4::Align::Start);
+        label.set_ellipsize(gtk4::pango::EllipsizeMode::Middle);
+        label.set_tooltip_text(row_data.path().as_deref());
+        missing_list.append(&label);
+    }
+}
@@ -0,0 +1,170 @@
+use std::{cell::RefCell, rc::Rc};
+
+use gtk4::{
+    gio,
+    glib::{self, clone},
+    prelude::*,
+};
+
+use super::actions;
+use crate::historymodel::{HistoryModel, RetentionPolicy, RowData};
+
+/// Builds the settings page for the rules by which old screenshots get deleted, it can preview
+/// what the rules would delete without deleting anything
+pub fn build_retention_page(history_model: &HistoryModel) -> gtk4::Box {
+    let settings = gio::Settings::new("kc.kcshot");
+
+    let rules = gtk4::Grid::builder()
+        .row_spacing(6)
+        .column_spacing(12)
+        .build();
+    for (row, (label, key, max)) in [
+        (
+            "Keep the newest (0 keeps all)",
+            "retention-keep-last",
+            1_000_000.0,
+        ),
+        (
+            "Delete after days (0 never)",
+            "retention-max-age-days",
+            36_500.0,
+        ),
+        (
+            "Maximum total size in MiB (0 unlimited)",
+            "retention-max-total-size-mb",
+            1_000_000.0,
+        ),
+    ]
+    .iter()
+    .enumerate()
+    {
+        let spin_button = gtk4::SpinButton::with_range(0.0, *max, 1.0);
+        settings
+            .bind(key, &spin_button, "value")
+            .flags(gio::SettingsBindFlags::DEFAULT)
+            .build();
+        rules.attach(&make_label(label), 0, row as i32, 1, 1);
+        rules.attach(&spin_button, 1, row as i32, 1, 1);
+    }
+
+    let keep_favourites = gtk4::Switch::new();
+    keep_favourites.set_halign(gtk4::Align::Start);
+    settings
+        .bind("retention-keep-favourites", &keep_favourites, "active")
+        .flags(gio::SettingsBindFlags::DEFAULT)
+        .build();
+    rules.attach(&make_label("Never delete favourites"), 0, 3, 1, 1);
+    rules.attach(&keep_favourites, 1, 3, 1, 1);
+
+    let summary = gtk4::Label::new(Some(
+        "Screenshots are cleaned up when kcshot starts and every hour after that.",
+    ));
+    summary.set_halign(gtk4::Align::Start);
+    summary.set_wrap(true);
+
+    let preview_list = gtk4::ListBox::new();
+    preview_list.set_selection_mode(gtk4::SelectionMode::None);
+    let preview_view = gtk4::ScrolledWindow::builder()
+        .child(&preview_list)
+        .min_content_height(200)
+        .vexpand(true)
+        .build();
+
+    let expired = Rc::new(RefCell::new(Vec::<RowData>::new()));
+    let preview_button = gtk4::Button::with_label("Preview");
+    preview_button.set_tooltip_text(Some(
+        "Show what would be deleted, without deleting anything",
+    ));
+    let clean_up_button = gtk4::Button::with_label("Clean up now");
+    clean_up_button.add_css_class("destructive-action");
+    clean_up_button.set_sensitive(false);
+
+    let update_preview = Rc::new(
+        clone!(@weak history_model, @weak summary, @weak preview_list, @weak clean_up_button, @strong expired, @strong settings => move || {
+            let policy = RetentionPolicy::from_settings(&settings);
+            let rows = history_model.expired_screenshots(&policy);
+            fill_preview(&summary, &preview_list, &policy, &rows);
+            clean_up_button.set_sensitive(!rows.is_empty());
+            expired.replace(rows);
+        }),
+    );
+
+    preview_button.connect_clicked(clone!(@strong update_preview => move |_| update_preview()));
+    clean_up_button.connect_clicked(
+        clone!(@weak history_model, @strong expired => move |button| {
+            let parent = button.root().and_then(|root| root.downcast::<gtk4::Window>().ok());
+            actions::confirm_permanent_deletion(
+                parent.as_ref(),
+                &history_model,
+                expired.borrow().clone(),
+            );
+        }),
+    );
+    // Whatever was previewed may be gone by now
+    history_model.connect_screenshots_deleted(clone!(@weak clean_up_button => move |_, _, _| {
+        if clean_up_button.is_sensitive() {
+            update_preview();
+        }
+    }));
+
+    let buttons = gtk4::Box::new(gtk4::Orientation::Horizontal, 6);
+    buttons.append(&preview_button);
+    buttons.append(&clean_up_button);
+
+    let content_area = gtk4::Box::new(gtk4::Orientation::Vertical, 8);
+    content_area.append(&rules);
+    content_area.append(&buttons);
+    content_area.append(&summary);
+    content_area.append(&preview_view);
+    content_area.set_margin_top(5);
+    content_area.set_margin_bottom(10);
+    content_area.set_margin_start(10);
+    content_area.set_margin_end(10);
+
+    content_area
+}
+
+/// Lists the screenshots in `rows`, which are those `policy` would delete, in `preview_list`
+fn fill_preview(
+    summary: &gtk4::Label,
+    preview_list: &gtk4::ListBox,
+    policy: &RetentionPolicy,
+    rows: &[RowData],
+) {
+    while let Some(child) = preview_list.first_child() {
+        preview_list.remove(&child);
+    }
+
+    let total_size = rows.iter().filter_map(RowData::size).sum::<i64>();
+    summary.set_text(&match rows.len() {
+        _ if !policy.is_enabled() => "No rules are set, nothing would be deleted.".to_owned(),
+        0 => "Nothing would be deleted.".to_owned(),
+        1 => format!(
+            "1 screenshot ({}) would be deleted.",
+            glib::format_size(total_size as u64)
+        ),
+        n => format!(
+            "{} screenshots ({}) would be deleted.",
+            n,
+            glib::format_size(total_size as u64)
+        ),
+    });
+
+    for row_data in rows {
+        let text = format!(
+            "{}  {}",
+            row_data.time(),
+            row_data.path().unwrap_or_else(|| "(no file)".to_owned())
+        );
+        let label = gtk4::Label::new(Some(&text));
+        label.set_halign(gtk4::Align::Start);
+        label.set_ellipsize(gtk4::pango::EllipsizeMode::Middle);
+        preview_list.append(&label);
+    }
+}
+
+fn make_label(text: &str) -> gtk4::Label {
+    let label = gtk4::Label::new(Some(text));
+    label.set_halign(gtk4::Align::Start);
+    label
+}
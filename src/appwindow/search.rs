@@ -0,0 +1,147 @@
+use std::rc::Rc;
+
+use chrono::NaiveDate;
+use gtk4::prelude::*;
+
+use crate::{db::Filter, historymodel::HistoryModel};
+
+/// The widgets of the search bar, from which the filter for the history is built
+struct SearchFields {
+    filename: gtk4::SearchEntry,
+    window_title: gtk4::Entry,
+    since: gtk4::Entry,
+    until: gtk4::Entry,
+    has_url: gtk4::DropDown,
+    tags: gtk4::Entry,
+    favourites_only: gtk4::ToggleButton,
+}
+
+impl SearchFields {
+    fn filter(&self) -> Filter {
+        Filter {
+            since: parse_date(&self.since),
+            until: parse_date(&self.until),
+            filename: non_empty(self.filename.text().as_str()),
+            // The order of these has to match the strings the drop down was made from
+            has_url: match self.has_url.selected() {
+                1 => Some(true),
+                2 => Some(false),
+                _ => None,
+            },
+            window_title: non_empty(self.window_title.text().as_str()),
+            tags: self.tags.text().split(',').filter_map(non_empty).collect(),
+            favourites_only: self.favourites_only.is_active(),
+        }
+    }
+}
+
+/// Builds the bar through which the screenshots shown by `history_model` get filtered
+pub fn build_search_bar(history_model: &HistoryModel) -> gtk4::SearchBar {
+    let fields = Rc::new(SearchFields {
+        filename: gtk4::SearchEntry::builder()
+            .placeholder_text("File name")
+            .build(),
+        window_title: gtk4::Entry::builder()
+            .placeholder_text("Window title")
+            .build(),
+        since: gtk4::Entry::builder()
+            .placeholder_text("From (YYYY-MM-DD)")
+            .build(),
+        until: gtk4::Entry::builder()
+            .placeholder_text("To (YYYY-MM-DD)")
+            .build(),
+        has_url: gtk4::DropDown::from_strings(&["Uploaded or not", "Uploaded", "Not uploaded"]),
+        tags: gtk4::Entry::builder()
+            .placeholder_text("Tags, separated by commas")
+            .build(),
+        favourites_only: gtk4::ToggleButton::builder()
+            .icon_name("starred-symbolic")
+            .tooltip_text("Only show favourites")
+            .build(),
+    });
+
+    let update_filter = {
+        let fields = Rc::downgrade(&fields);
+        let history_model = history_model.downgrade();
+        Rc::new(move || {
+            if let (Some(fields), Some(history_model)) = (fields.upgrade(), history_model.upgrade())
+            {
+                history_model.set_filter(fields.filter());
+            }
+        })
+    };
+
+    fields.filename.connect_search_changed({
+        let update_filter = update_filter.clone();
+        move |_| update_filter()
+    });
+    for entry in [
+        &fields.window_title,
+        &fields.since,
+        &fields.until,
+        &fields.tags,
+    ] {
+        let update_filter = update_filter.clone();
+        entry.connect_changed(move |_| update_filter());
+    }
+    fields.has_url.connect_selected_notify({
+        let update_filter = update_filter.clone();
+        move |_| update_filter()
+    });
+    fields
+        .favourites_only
+        .connect_toggled(move |_| update_filter());
+
+    let hbox = gtk4::Box::new(gtk4::Orientation::Horizontal, 6);
+    hbox.append(&fields.filename);
+    hbox.append(&fields.window_title);
+    hbox.append(&fields.since);
+    hbox.append(&fields.until);
+    hbox.append(&fields.has_url);
+    hbox.append(&fields.tags);
+    hbox.append(&fields.favourites_only);
+
+    let search_bar = gtk4::SearchBar::new();
+    search_bar.set_child(Some(&hbox));
+    search_bar.connect_entry(&fields.filename);
+    search_bar.set_show_close_button(true);
+
+    // Closing the search bar should show the whole history again
+    search_bar.connect_search_mode_enabled_notify(move |search_bar| {
+        if !search_bar.is_search_mode() {
+            fields.filename.set_text("");
+            fields.window_title.set_text("");
+            fields.since.set_text("");
+            fields.until.set_text("");
+            fields.has_url.set_selected(0);
+            fields.tags.set_text("");
+            fields.favourites_only.set_active(false);
+        }
+    });
+
+    search_bar
+}
+
+/// Parses the date in `entry`, the entry gets marked as erroneous if it's not empty and doesn't
+/// contain a valid date
+fn parse_date(entry: &gtk4::Entry) -> Option<NaiveDate> {
+    let text = entry.text();
+    let date = NaiveDate::parse_from_str(text.trim(), "%Y-%m-%d").ok();
+
+    if date.is_none() && !text.trim().is_empty() {
+        entry.add_css_class("error");
+    } else {
+        entry.remove_css_class("error");
+    }
+
+    date
+}
+
+fn non_empty(text: &str) -> Option<String> {
+    let text = text.trim();
+    if text.is_empty() {
+        None
+    } else {
+        Some(text.to_owned())
+    }
+}
@@ -0,0 +1,98 @@
+use gtk4::{
+    glib::{self, clone},
+    prelude::*,
+};
+
+use super::actions::RowDataGetter;
+use crate::historymodel::{HistoryModel, RowData};
+
+/// Builds a popover through which the tags of a screenshot are edited, `row_data` is called each
+/// time the popover is shown to find out which screenshot that is, since list items get reused
+pub fn build_tag_popover(history_model: &HistoryModel, row_data: RowDataGetter) -> gtk4::Popover {
+    let tag_list = gtk4::Box::new(gtk4::Orientation::Vertical, 2);
+    let entry = gtk4::Entry::builder().placeholder_text("Add a tag").build();
+    // The tags other screenshots have are suggested while typing
+    let completions = gtk4::ListStore::new(&[glib::Type::STRING]);
+    let completion = gtk4::EntryCompletion::new();
+    completion.set_model(Some(&completions));
+    completion.set_text_column(0);
+    completion.set_minimum_key_length(1);
+    entry.set_completion(Some(&completion));
+
+    let vbox = gtk4::Box::new(gtk4::Orientation::Vertical, 6);
+    vbox.append(&tag_list);
+    vbox.append(&entry);
+
+    let popover = gtk4::Popover::new();
+    popover.set_child(Some(&vbox));
+
+    popover.connect_show(
+        clone!(@weak history_model, @weak tag_list, @weak completions, @strong row_data => move |_| {
+            if let Some(row_data) = row_data() {
+                fill_tag_list(&tag_list, &completions, &history_model, &row_data);
+            }
+        }),
+    );
+    entry.connect_activate(
+        clone!(@weak history_model, @weak tag_list, @weak completions, @strong row_data => move |entry| {
+            let tag = entry.text();
+            let tag = tag.trim();
+            if tag.is_empty() {
+                return;
+            }
+
+            if let Some(row_data) = row_data() {
+                history_model.add_tag(&row_data, tag);
+                fill_tag_list(&tag_list, &completions, &history_model, &row_data);
+            }
+            entry.set_text("");
+        }),
+    );
+
+    popover
+}
+
+/// Replaces the contents of `tag_list` with a row for each of the screenshot's tags, and those of
+/// `completions` with the tags the screenshot doesn't have
+fn fill_tag_list(
+    tag_list: &gtk4::Box,
+    completions: &gtk4::ListStore,
+    history_model: &HistoryModel,
+    row_data: &RowData,
+) {
+    while let Some(child) = tag_list.first_child() {
+        tag_list.remove(&child);
+    }
+
+    let tags = history_model.tags_of(row_data);
+    completions.clear();
+    for tag in history_model.all_tags() {
+        if !tags.contains(&tag) {
+            completions.set(&completions.append(), &[(0, &tag)]);
+        }
+    }
+
+    if tags.is_empty() {
+        tag_list.append(&gtk4::Label::new(Some("No tags")));
+    }
+
+    for tag in tags {
+        let label = gtk4::Label::new(Some(&tag));
+        label.set_halign(gtk4::Align::Start);
+        label.set_hexpand(true);
+
+        let remove_button = gtk4::Button::from_icon_name(Some("list-remove-symbolic"));
+        remove_button.set_tooltip_text(Some("Remove tag"));
+        remove_button.connect_clicked(
+            clone!(@weak tag_list, @weak completions, @weak history_model, @weak row_data => move |_| {
+                history_model.remove_tag(&row_data, &tag);
+                fill_tag_list(&tag_list, &completions, &history_model, &row_data);
+            }),
+        );
+
+        let row = gtk4::Box::new(gtk4::Orientation::Horizontal, 6);
+        row.append(&label);
+        row.append(&remove_button);
+        tag_list.append(&row);
+    }
+}
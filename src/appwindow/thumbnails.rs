@@ -0,0 +1,84 @@
+//! The history grid shows thumbnails instead of the screenshots themselves, since decoding every
+//! screenshot at full size makes scrolling through a big history sluggish. Thumbnails are made on
+//! a thread pool and cached in `$XDG_CACHE_HOME/kcshot/thumbnails/`, named after the hash of the
+//! screenshot (or of its path, if we don't know the hash), and they are made anew whenever the
+//! screenshot was changed after its thumbnail was made.
+
+use std::{
+    future::Future,
+    io,
+    path::{Path, PathBuf},
+};
+
+use gtk4::{gdk_pixbuf::Pixbuf, glib};
+use once_cell::sync::Lazy;
+
+/// The longer side of a thumbnail is at most this long, in pixels
+const THUMBNAIL_SIZE: i32 = 400;
+
+static THREAD_POOL: Lazy<glib::ThreadPool> = Lazy::new(|| {
+    glib::ThreadPool::new_shared(Some(4)).expect("Failed to create the thumbnail thread pool")
+});
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("The screenshot's file is missing")]
+    Missing,
+    #[error("Failed to find the cache directory: {0}")]
+    CacheDirectory(#[from] xdg::BaseDirectoriesError),
+    #[error("Failed to access the thumbnail: {0}")]
+    Io(#[from] io::Error),
+    #[error("Failed to make the thumbnail: {0}")]
+    Pixbuf(#[from] glib::Error),
+    #[error("Failed to push the thumbnail onto the thread pool: {0}")]
+    ThreadPool(glib::Error),
+}
+
+/// Returns the path of the thumbnail of the screenshot at `path`, whose SHA-256 is `hash` if we
+/// know it. The thumbnail is made first if it isn't cached or is out of date.
+pub fn thumbnail_for(
+    path: PathBuf,
+    hash: Option<String>,
+) -> impl Future<Output = Result<PathBuf, Error>> {
+    let thumbnail = THREAD_POOL
+        .push_future(move || cached_thumbnail(&path, hash.as_deref()))
+        .map_err(Error::ThreadPool);
+
+    async move { thumbnail?.await }
+}
+
+fn cached_thumbnail(path: &Path, hash: Option<&str>) -> Result<PathBuf, Error> {
+    let key = match hash {
+        Some(hash) => hash.to_owned(),
+        None => {
+            glib::compute_checksum_for_string(glib::ChecksumType::Sha256, &path.to_string_lossy())
+                .expect("SHA-256 is always supported")
+                .into()
+        }
+    };
+    let thumbnail_path = xdg::BaseDirectories::with_prefix("kcshot")?
+        .place_cache_file(format!("thumbnails/{}.png", key))?;
+
+    // This is the only place the file is looked at before the thumbnail is shown, so it's where
+    // the grid finds out whether the file is still there
+    let modified = match std::fs::metadata(path) {
+        Err(why) if why.kind() == io::ErrorKind::NotFound => return Err(Error::Missing),
+        metadata => metadata?.modified()?,
+    };
+    let thumbnail_modified =
+        std::fs::metadata(&thumbnail_path).and_then(|metadata| metadata.modified());
+    let is_up_to_date =
+        matches!(thumbnail_modified, Ok(thumbnail_modified) if thumbnail_modified >= modified);
+    if is_up_to_date {
+        return Ok(thumbnail_path);
+    }
+
+    // The thumbnail is written under another name first, so that nobody gets to see it half
+    // written
+    let pixbuf = Pixbuf::from_file_at_scale(path, THUMBNAIL_SIZE, THUMBNAIL_SIZE, true)?;
+    let partial_path = thumbnail_path.with_extension(format!("{}.part", rand::random::<u32>()));
+    pixbuf.savev(&partial_path, "png", &[])?;
+    std::fs::rename(&partial_path, &thumbnail_path)?;
+
+    Ok(thumbnail_path)
+}
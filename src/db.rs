@@ -1,44 +1,428 @@
-use diesel::{prelude::*, result::ConnectionResult, SqliteConnection};
+use std::{collections::HashSet, io, path::PathBuf};
 
-use self::models::Screenshot;
+use chrono::{DateTime, NaiveDate, Utc};
+use diesel::{prelude::*, result::ConnectionError, sqlite::Sqlite, SqliteConnection};
+use diesel_migrations::{MigrationConnection, RunMigrationsError};
+
+use self::models::{NewScreenshot, Screenshot};
 
 pub mod models;
 pub mod schema;
 
-pub fn open(path: &str) -> ConnectionResult<SqliteConnection> {
-    SqliteConnection::establish(path)
+embed_migrations!();
+
+/// The version of the newest migration in `migrations/`, this has to be updated whenever a new
+/// migration is added
+const SCHEMA_VERSION: &str = "20220201181203";
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("Failed to find the data directory: {0}")]
+    DataDirectory(#[from] xdg::BaseDirectoriesError),
+    #[error("Failed to create the data directory: {0}")]
+    Io(#[from] io::Error),
+    #[error("Failed to open the database: {0}")]
+    Connection(#[from] ConnectionError),
+    #[error("Failed to query the database: {0}")]
+    Query(#[from] diesel::result::Error),
+    #[error("Failed to run the database migrations: {0}")]
+    Migrations(#[from] RunMigrationsError),
+    #[error("The database's schema version ({found}) is newer than the newest one this version of kcshot knows about ({supported}), was kcshot downgraded?", supported = SCHEMA_VERSION)]
+    SchemaTooNew { found: String },
+}
+
+/// Returns the path of the history database, which is `$XDG_DATA_HOME/kcshot/history.db`, the
+/// directories leading up to it are created if they don't exist
+pub fn database_path() -> Result<PathBuf, Error> {
+    Ok(xdg::BaseDirectories::with_prefix("kcshot")?.place_data_file("history.db")?)
+}
+
+/// Opens the database at `path` and brings its schema up to date
+pub fn open(path: &str) -> Result<SqliteConnection, Error> {
+    let conn = SqliteConnection::establish(path)?;
+    // SQLite doesn't enforce foreign keys unless asked to, we need it to so that deleting a
+    // screenshot also deletes its tags
+    conn.execute("PRAGMA foreign_keys = ON")?;
+
+    // Creates the table in which diesel keeps track of migrations, if it doesn't exist yet
+    diesel_migrations::setup_database(&conn)?;
+    if let Some(found) = conn.latest_run_migration_version()? {
+        if found.as_str() > SCHEMA_VERSION {
+            return Err(Error::SchemaTooNew { found });
+        }
+    }
+
+    embedded_migrations::run(&conn)?;
+
+    Ok(conn)
 }
 
+/// Adds the screenshot to the history
+///
+/// # Returns
+/// The id of the screenshot
 pub fn add_screenshot_to_history(
     conn: &SqliteConnection,
-    path_: Option<String>,
-    time_: String,
-    url_: Option<String>,
+    screenshot: &NewScreenshot,
+) -> QueryResult<i32> {
+    use schema::screenshots::dsl::*;
+
+    conn.transaction(|| {
+        diesel::insert_into(screenshots)
+            .values(screenshot)
+            .execute(conn)?;
+        // SQLite gives out ids in increasing order, so the newest row is the one we inserted
+        screenshots.select(id).order(id.desc()).first(conn)
+    })
+}
+
+/// Adds all of `new_screenshots` to the history at once, in the order they're in
+pub fn add_screenshots_to_history(
+    conn: &SqliteConnection,
+    new_screenshots: &[NewScreenshot],
 ) -> QueryResult<()> {
-    use schema::screenshots::{self, dsl::*};
+    use schema::screenshots;
 
-    diesel::insert_into(screenshots::table)
-        .values((path.eq(path_), time.eq(time_), url.eq(url_)))
-        .execute(conn)
-        .map(|_| ())
+    conn.transaction(|| {
+        diesel::insert_into(screenshots::table)
+            .values(new_screenshots)
+            .execute(conn)
+            .map(|_| ())
+    })
+}
+
+/// Returns the hashes of all screenshots in the history which have one
+pub fn all_hashes(conn: &SqliteConnection) -> QueryResult<HashSet<String>> {
+    use schema::screenshots::dsl::*;
+
+    let hashes = screenshots
+        .select(hash)
+        .filter(hash.is_not_null())
+        .load::<Option<String>>(conn)?;
+
+    Ok(hashes.into_iter().flatten().collect())
+}
+
+/// Returns the paths of the screenshots from before we hashed them
+pub fn paths_without_hash(conn: &SqliteConnection) -> QueryResult<HashSet<String>> {
+    use schema::screenshots::dsl::*;
+
+    let paths = screenshots
+        .select(path)
+        .filter(hash.is_null())
+        .load::<Option<String>>(conn)?;
+
+    Ok(paths.into_iter().flatten().collect())
+}
+
+/// Restricts which screenshots are returned by [`fetch_screenshots`], fields which are `None`
+/// don't restrict anything
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Filter {
+    /// Only screenshots taken on or after this day
+    pub since: Option<NaiveDate>,
+    /// Only screenshots taken on or before this day
+    pub until: Option<NaiveDate>,
+    /// Only screenshots whose path contains this string
+    pub filename: Option<String>,
+    /// `Some(true)` keeps only screenshots that have a URL, `Some(false)` only those that don't
+    pub has_url: Option<bool>,
+    /// Only screenshots taken while a window whose title contains this string had focus
+    pub window_title: Option<String>,
+    /// Only screenshots which have all of these tags
+    pub tags: Vec<String>,
+    /// Only screenshots which were marked as favourites
+    pub favourites_only: bool,
+}
+
+impl Filter {
+    fn apply<'a>(&self, mut query: BoxedScreenshotsQuery<'a>) -> BoxedScreenshotsQuery<'a> {
+        use schema::screenshots::dsl::*;
+
+        // `time` is stored as RFC 3339, so comparing it with a date as strings orders it correctly
+        if let Some(since) = self.since {
+            query = query.filter(time.ge(since.format("%Y-%m-%d").to_string()));
+        }
+        if let Some(until) = self.until.and_then(|until| until.succ_opt()) {
+            query = query.filter(time.lt(until.format("%Y-%m-%d").to_string()));
+        }
+        if let Some(filename) = &self.filename {
+            query = query.filter(path.like(like_pattern(filename)).escape('\\'));
+        }
+        match self.has_url {
+            Some(true) => query = query.filter(url.is_not_null()),
+            Some(false) => query = query.filter(url.is_null()),
+            None => {}
+        }
+        if let Some(title) = &self.window_title {
+            query = query.filter(window_title.like(like_pattern(title)).escape('\\'));
+        }
+        for tag in &self.tags {
+            let tagged = schema::screenshot_tags::table
+                .inner_join(schema::tags::table)
+                .filter(schema::tags::name.eq(tag.clone()))
+                .select(schema::screenshot_tags::screenshot_id);
+            query = query.filter(id.eq_any(tagged));
+        }
+        if self.favourites_only {
+            query = query.filter(is_favourite.eq(true));
+        }
+
+        query
+    }
+}
+
+type BoxedScreenshotsQuery<'a> = schema::screenshots::BoxedQuery<'a, Sqlite>;
+
+/// Makes a `LIKE` pattern which matches strings containing `needle`
+fn like_pattern(needle: &str) -> String {
+    let escaped = needle
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+
+    format!("%{}%", escaped)
 }
 
 pub fn fetch_screenshots(
     conn: &SqliteConnection,
+    filter: &Filter,
     start_at: i64,
     count: i64,
 ) -> QueryResult<Vec<Screenshot>> {
     use schema::screenshots::dsl::*;
 
-    screenshots
+    filter
+        .apply(screenshots.into_boxed())
         .limit(count)
         .offset(start_at)
-        .order(id.desc())
+        .order((taken_at.desc(), id.desc()))
         .load::<Screenshot>(conn)
 }
 
-pub fn number_of_history_itms(conn: &SqliteConnection) -> QueryResult<i64> {
+/// Like [`fetch_screenshots`], but starts at the screenshot which comes right after the one with
+/// the id `previous_id`, which is quicker than skipping over all the screenshots before it
+pub fn fetch_screenshots_after(
+    conn: &SqliteConnection,
+    filter: &Filter,
+    previous_id: i32,
+    count: i64,
+) -> QueryResult<Vec<Screenshot>> {
     use schema::screenshots::dsl::*;
 
-    screenshots.count().get_result(conn)
+    let previous_taken_at = screenshots
+        .find(previous_id)
+        .select(taken_at)
+        .first::<String>(conn)?;
+
+    // Screenshots are sorted by when they were taken and then by their id, in descending order
+    filter
+        .apply(screenshots.into_boxed())
+        .filter(
+            taken_at
+                .lt(previous_taken_at.clone())
+                .or(taken_at.eq(previous_taken_at).and(id.lt(previous_id))),
+        )
+        .limit(count)
+        .order((taken_at.desc(), id.desc()))
+        .load::<Screenshot>(conn)
+}
+
+pub fn number_of_history_itms(conn: &SqliteConnection, filter: &Filter) -> QueryResult<i64> {
+    use schema::screenshots::dsl::*;
+
+    filter
+        .apply(screenshots.into_boxed())
+        .count()
+        .get_result(conn)
+}
+
+/// Returns the position of the screenshot with the id `screenshot_id` among those returned by
+/// [`fetch_screenshots`] for `filter`, or `None` if `filter` doesn't let it through
+pub fn position_of(
+    conn: &SqliteConnection,
+    filter: &Filter,
+    screenshot_id: i32,
+) -> QueryResult<Option<i64>> {
+    use schema::screenshots::dsl::*;
+
+    let screenshot_taken_at = filter
+        .apply(screenshots.into_boxed())
+        .filter(id.eq(screenshot_id))
+        .select(taken_at)
+        .first::<String>(conn)
+        .optional()?;
+    let screenshot_taken_at = match screenshot_taken_at {
+        Some(screenshot_taken_at) => screenshot_taken_at,
+        None => return Ok(None),
+    };
+
+    // Screenshots are sorted by when they were taken and then by their id, in descending order
+    filter
+        .apply(screenshots.into_boxed())
+        .filter(
+            taken_at
+                .gt(screenshot_taken_at.clone())
+                .or(taken_at.eq(screenshot_taken_at).and(id.gt(screenshot_id))),
+        )
+        .count()
+        .get_result(conn)
+        .map(Some)
+}
+
+/// Returns every screenshot in the history, sorted from newest to oldest by when they were taken.
+/// The ids don't tell that, imported screenshots get theirs when they're imported.
+pub fn all_screenshots(conn: &SqliteConnection) -> QueryResult<Vec<Screenshot>> {
+    use schema::screenshots::dsl::*;
+
+    screenshots
+        .order((taken_at.desc(), id.desc()))
+        .load::<Screenshot>(conn)
+}
+
+/// Turns the RFC 3339 `time` of a screenshot into what goes in its `taken_at` column: the same time
+/// in UTC, written the way SQLite's `strftime('%Y-%m-%dT%H:%M:%fZ')` writes it so that the column
+/// sorts in the order the screenshots were taken. Times which can't be read are sorted as the oldest.
+pub fn taken_at(time: &str) -> String {
+    DateTime::parse_from_rfc3339(time).map_or_else(
+        |_| String::new(),
+        |time| {
+            time.with_timezone(&Utc)
+                .format("%Y-%m-%dT%H:%M:%S%.3fZ")
+                .to_string()
+        },
+    )
+}
+
+pub fn fetch_screenshot(conn: &SqliteConnection, screenshot_id: i32) -> QueryResult<Screenshot> {
+    use schema::screenshots::dsl::*;
+
+    screenshots.find(screenshot_id).first(conn)
+}
+
+/// Puts a screenshot which was deleted back into the history, with the same id it had before and
+/// with `tags`
+pub fn restore_screenshot(
+    conn: &SqliteConnection,
+    screenshot: &Screenshot,
+    tags: &[String],
+) -> QueryResult<()> {
+    use schema::screenshots;
+
+    conn.transaction(|| {
+        diesel::insert_into(screenshots::table)
+            .values(screenshot)
+            .execute(conn)?;
+        for tag in tags {
+            add_tag(conn, screenshot.id, tag)?;
+        }
+
+        Ok(())
+    })
+}
+
+/// Removes the screenshot with the id `screenshot_id` from the history, its files are left alone
+pub fn delete_screenshot(conn: &SqliteConnection, screenshot_id: i32) -> QueryResult<()> {
+    use schema::screenshots::dsl::*;
+
+    conn.transaction(|| {
+        diesel::delete(screenshots.find(screenshot_id)).execute(conn)?;
+        delete_unused_tags(conn)
+    })
+}
+
+/// Points the screenshot with the id `screenshot_id` at the image at `new_path`
+pub fn set_path(conn: &SqliteConnection, screenshot_id: i32, new_path: &str) -> QueryResult<()> {
+    use schema::screenshots::dsl::*;
+
+    diesel::update(screenshots.find(screenshot_id))
+        .set(path.eq(new_path))
+        .execute(conn)
+        .map(|_| ())
+}
+
+pub fn set_favourite(
+    conn: &SqliteConnection,
+    screenshot_id: i32,
+    favourite: bool,
+) -> QueryResult<()> {
+    use schema::screenshots::dsl::*;
+
+    diesel::update(screenshots.find(screenshot_id))
+        .set(is_favourite.eq(favourite))
+        .execute(conn)
+        .map(|_| ())
+}
+
+/// Returns the names of the tags of the screenshot with the id `screenshot_id`, sorted by name
+pub fn tags_of(conn: &SqliteConnection, screenshot_id: i32) -> QueryResult<Vec<String>> {
+    use schema::{screenshot_tags, tags};
+
+    screenshot_tags::table
+        .inner_join(tags::table)
+        .filter(screenshot_tags::screenshot_id.eq(screenshot_id))
+        .select(tags::name)
+        .order(tags::name)
+        .load(conn)
+}
+
+/// Returns the names of all tags that are in use, sorted by name
+pub fn all_tags(conn: &SqliteConnection) -> QueryResult<Vec<String>> {
+    use schema::tags::dsl::*;
+
+    tags.select(name).order(name).load(conn)
+}
+
+/// Tags the screenshot with the id `screenshot_id` with `tag`, the tag is created if it doesn't
+/// exist yet
+pub fn add_tag(conn: &SqliteConnection, screenshot_id: i32, tag: &str) -> QueryResult<()> {
+    use schema::{screenshot_tags, tags};
+
+    conn.transaction(|| {
+        diesel::insert_or_ignore_into(tags::table)
+            .values(tags::name.eq(tag))
+            .execute(conn)?;
+        let tag_id = tags::table
+            .filter(tags::name.eq(tag))
+            .select(tags::id)
+            .first::<i32>(conn)?;
+
+        diesel::insert_or_ignore_into(screenshot_tags::table)
+            .values((
+                screenshot_tags::screenshot_id.eq(screenshot_id),
+                screenshot_tags::tag_id.eq(tag_id),
+            ))
+            .execute(conn)
+            .map(|_| ())
+    })
+}
+
+/// Removes `tag` from the screenshot with the id `screenshot_id`, tags which end up not being
+/// used by any screenshot are deleted
+pub fn remove_tag(conn: &SqliteConnection, screenshot_id: i32, tag: &str) -> QueryResult<()> {
+    use schema::{screenshot_tags, tags};
+
+    conn.transaction(|| {
+        let tag_id = tags::table
+            .filter(tags::name.eq(tag))
+            .select(tags::id)
+            .first::<i32>(conn)
+            .optional()?;
+        let tag_id = match tag_id {
+            Some(tag_id) => tag_id,
+            None => return Ok(()),
+        };
+
+        diesel::delete(screenshot_tags::table.find((screenshot_id, tag_id))).execute(conn)?;
+        delete_unused_tags(conn)
+    })
+}
+
+fn delete_unused_tags(conn: &SqliteConnection) -> QueryResult<()> {
+    use schema::{screenshot_tags, tags};
+
+    let used_tags = screenshot_tags::table.select(screenshot_tags::tag_id);
+    diesel::delete(tags::table.filter(diesel::dsl::not(tags::id.eq_any(used_tags))))
+        .execute(conn)
+        .map(|_| ())
 }
@@ -1,7 +1,50 @@
-#[derive(Queryable, Clone)]
+use serde::Serialize;
+
+use super::schema::screenshots;
+
+#[derive(Queryable, Insertable, Serialize, Clone, Debug)]
+#[table_name = "screenshots"]
 pub struct Screenshot {
     pub id: i32,
     pub path: Option<String>,
     pub time: String,
     pub url: Option<String>,
+    pub project_path: Option<String>,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    /// The size of the file in bytes
+    pub size: Option<i64>,
+    pub format: Option<String>,
+    /// The SHA-256 of the file's contents, as a hex string
+    pub hash: Option<String>,
+    /// See [`crate::metadata::CaptureMode`]
+    pub capture_mode: Option<String>,
+    pub window_title: Option<String>,
+    pub window_class: Option<String>,
+    /// The connector name of the monitor the screenshot was taken on
+    pub monitor: Option<String>,
+    pub is_favourite: bool,
+    /// `time` in UTC, see [`crate::db::taken_at`]
+    #[serde(skip)]
+    pub taken_at: String,
+}
+
+/// A row which is about to be added to the history, see [`Screenshot`] for what the fields mean
+#[derive(Insertable, Default, Debug)]
+#[table_name = "screenshots"]
+pub struct NewScreenshot {
+    pub path: Option<String>,
+    pub time: String,
+    pub url: Option<String>,
+    pub project_path: Option<String>,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    pub size: Option<i64>,
+    pub format: Option<String>,
+    pub hash: Option<String>,
+    pub capture_mode: Option<String>,
+    pub window_title: Option<String>,
+    pub window_class: Option<String>,
+    pub monitor: Option<String>,
+    pub taken_at: String,
 }
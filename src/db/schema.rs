@@ -4,5 +4,36 @@ table! {
         path -> Nullable<Text>,
         time -> Text,
         url -> Nullable<Text>,
+        project_path -> Nullable<Text>,
+        width -> Nullable<Integer>,
+        height -> Nullable<Integer>,
+        size -> Nullable<BigInt>,
+        format -> Nullable<Text>,
+        hash -> Nullable<Text>,
+        capture_mode -> Nullable<Text>,
+        window_title -> Nullable<Text>,
+        window_class -> Nullable<Text>,
+        monitor -> Nullable<Text>,
+        is_favourite -> Bool,
+        taken_at -> Text,
     }
 }
+
+table! {
+    tags (id) {
+        id -> Integer,
+        name -> Text,
+    }
+}
+
+table! {
+    screenshot_tags (screenshot_id, tag_id) {
+        screenshot_id -> Integer,
+        tag_id -> Integer,
+    }
+}
+
+joinable!(screenshot_tags -> screenshots (screenshot_id));
+joinable!(screenshot_tags -> tags (tag_id));
+
+allow_tables_to_appear_in_same_query!(screenshots, tags, screenshot_tags);
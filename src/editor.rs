@@ -1,13 +1,37 @@
-use gtk4::glib;
+use std::path::Path;
 
-use crate::historymodel::HistoryModel;
+use gtk4::{gdk, gdk_pixbuf::Pixbuf, gio, glib, prelude::*, subclass::prelude::*};
+
+use crate::{
+    historymodel::HistoryModel,
+    metadata::{CaptureMode, WindowInfo},
+};
 
 mod data;
 mod display_server;
+mod last_region;
 mod operations;
-mod textdialog;
+mod project;
 mod underlying;
 mod utils;
+mod viewport;
+
+pub use last_region::capture as capture_last_region;
+pub use project::Project;
+
+use self::operations::OperationStack;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("Failed to load image: {0}")]
+    Pixbuf(#[from] glib::Error),
+    #[error("Encountered an error from cairo: {0}")]
+    Cairo(#[from] cairo::Error),
+    #[error("Failed to load project: {0}")]
+    Project(#[from] project::Error),
+    #[error("Failed to convert a gdk::Texture to a Pixbuf")]
+    Texture,
+}
 
 glib::wrapper! {
     pub struct EditorWindow(ObjectSubclass<underlying::EditorWindow>)
@@ -15,8 +39,179 @@ glib::wrapper! {
 }
 
 impl EditorWindow {
+    /// Takes a screenshot and creates an editor for it
     pub fn new(app: &gtk4::Application, history_model: &HistoryModel) -> Self {
-        glib::Object::new(&[("application", app), ("history-model", history_model)])
-            .expect("Failed to make an EditorWindow")
+        // This has to happen before the editor is shown, otherwise it'd be the one that has focus
+        let window = active_window();
+        let surface = display_server::take_screenshot().expect("Couldn't take a screenshot");
+
+        let mut operation_stack = OperationStack::new();
+        let settings = gio::Settings::new("kc.kcshot");
+        if settings.boolean("restore-tool-settings") {
+            operation_stack.restore_tool_settings(&settings);
+        }
+
+        Self::with_image(
+            app,
+            history_model,
+            surface,
+            operation_stack,
+            CaptureMode::Screen,
+            window,
+        )
+    }
+
+    /// Creates an editor for a project that was saved alongside a screenshot, with its
+    /// annotations still editable
+    pub fn from_project(
+        app: &gtk4::Application,
+        history_model: &HistoryModel,
+        project_path: &Path,
+    ) -> Result<Self, Error> {
+        let Project {
+            surface,
+            operations,
+            crop,
+        } = Project::load(project_path)?;
+        let (width, height) = default_size_for(&surface);
+
+        let window = Self::with_image(
+            app,
+            history_model,
+            surface,
+            OperationStack::from_operations(operations, crop),
+            CaptureMode::File,
+            None,
+        );
+        window.set_default_size(width, height);
+
+        Ok(window)
+    }
+
+    /// Creates an editor for the image file at `path`
+    pub fn from_file(
+        app: &gtk4::Application,
+        history_model: &HistoryModel,
+        path: &Path,
+    ) -> Result<Self, Error> {
+        let pixbuf = Pixbuf::from_file(path)?;
+
+        Self::from_pixbuf(app, history_model, &pixbuf, CaptureMode::File)
     }
+
+    /// Creates an editor whose base image is `pixbuf`, `mode` says where the pixbuf came from
+    pub fn from_pixbuf(
+        app: &gtk4::Application,
+        history_model: &HistoryModel,
+        pixbuf: &Pixbuf,
+        mode: CaptureMode,
+    ) -> Result<Self, Error> {
+        let surface = utils::surface_for(pixbuf)?;
+        let (width, height) = default_size_for(&surface);
+
+        let window = Self::with_image(
+            app,
+            history_model,
+            surface,
+            OperationStack::new(),
+            mode,
+            None,
+        );
+        window.set_default_size(width, height);
+
+        Ok(window)
+    }
+
+    fn with_image(
+        app: &gtk4::Application,
+        history_model: &HistoryModel,
+        surface: cairo::ImageSurface,
+        operation_stack: OperationStack,
+        mode: CaptureMode,
+        active_window: Option<WindowInfo>,
+    ) -> Self {
+        let window: Self =
+            glib::Object::new(&[("application", app), ("history-model", history_model)])
+                .expect("Failed to make an EditorWindow");
+
+        underlying::EditorWindow::from_instance(&window).set_image(
+            &window,
+            surface,
+            operation_stack,
+            mode,
+            active_window,
+        );
+
+        window
+    }
+}
+
+/// Reads an image from the clipboard and opens it in an editor, nothing happens if the clipboard
+/// doesn't contain an image
+pub fn edit_clipboard_image(app: &gtk4::Application, history_model: &HistoryModel) {
+    let display = match gdk::Display::default() {
+        Some(display) => display,
+        None => {
+            tracing::error!("Failed to fetch gdk::Display, bailing...");
+            return;
+        }
+    };
+
+    // Keep the application alive until the clipboard was read, in case no window is shown yet
+    app.hold();
+    display.clipboard().read_texture_async(
+        gio::NONE_CANCELLABLE,
+        glib::clone!(@strong app, @strong history_model => move |res| {
+            let texture = match res {
+                Ok(Some(texture)) => texture,
+                Ok(None) => {
+                    tracing::info!("The clipboard doesn't contain an image");
+                    app.release();
+                    return;
+                }
+                Err(why) => {
+                    tracing::error!("Failed to read an image from the clipboard: {}", why);
+                    app.release();
+                    return;
+                }
+            };
+
+            let editor_window = gdk::pixbuf_get_from_texture(&texture)
+                .ok_or(Error::Texture)
+                .and_then(|pixbuf| {
+                    EditorWindow::from_pixbuf(&app, &history_model, &pixbuf, CaptureMode::Clipboard)
+                });
+            match editor_window {
+                Ok(editor_window) => editor_window.show(),
+                Err(why) => tracing::error!("Failed to open the clipboard's image: {}", why),
+            }
+            app.release();
+        }),
+    );
+}
+
+/// Returns the window which has focus right now, errors are logged and treated as there being no
+/// such window
+fn active_window() -> Option<WindowInfo> {
+    display_server::get_active_window().unwrap_or_else(|why| {
+        tracing::warn!("Failed to find the active window: {}", why);
+        None
+    })
+}
+
+/// Returns a window size which fits `surface`, but isn't bigger than the screen
+fn default_size_for(surface: &cairo::ImageSurface) -> (i32, i32) {
+    let (screen_width, screen_height) =
+        display_server::get_screen_resolution().unwrap_or_else(|why| {
+            tracing::error!(
+                "Unable to retrieve screen resolution: {}\n\t\tgoing with 1920x1080",
+                why
+            );
+            (1920, 1080)
+        });
+
+    (
+        surface.width().min(screen_width),
+        surface.height().min(screen_height),
+    )
 }
@@ -1,9 +1,10 @@
-use gtk4::{gdk::RGBA, pango::FontDescription};
+use gtk4::gdk::RGBA;
+use serde::{Deserialize, Serialize};
 
 mod point;
 pub use point::Point;
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct Colour {
     pub red: u8,
     pub green: u8,
@@ -28,15 +29,35 @@ impl Colour {
         }
     }
 
+    pub fn from_tuple((red, green, blue, alpha): (u8, u8, u8, u8)) -> Self {
+        Self {
+            red,
+            green,
+            blue,
+            alpha,
+        }
+    }
+
+    pub fn to_tuple(self) -> (u8, u8, u8, u8) {
+        (self.red, self.green, self.blue, self.alpha)
+    }
+
     pub const BLACK: Self = Self {
         red: 0,
         green: 0,
         blue: 0,
         alpha: 255,
     };
+
+    pub const WHITE: Self = Self {
+        red: 255,
+        green: 255,
+        blue: 255,
+        alpha: 255,
+    };
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Rectangle {
     pub x: f64,
     pub y: f64,
@@ -65,7 +86,7 @@ impl Rectangle {
 /// * has a radius of w/2 (= a) in the x axis
 /// * has a radius of h/2 (= b) in the y axis
 /// * center is at (x + w/2, y + h/2) (= (x0, y0))
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct Ellipse {
     pub x: f64,
     pub y: f64,
@@ -73,9 +94,23 @@ pub struct Ellipse {
     pub h: f64,
 }
 
-#[derive(Debug)]
-pub struct Text {
-    pub string: String,
-    pub font_description: FontDescription,
-    pub colour: Colour,
+/// (De)serialises a [`FontDescription`] through its string representation, to be used with
+/// `#[serde(with = "font_description")]`
+pub mod font_description {
+    use gtk4::pango::FontDescription;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        font_description: &FontDescription,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&font_description.to_str())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<FontDescription, D::Error> {
+        let font_description = String::deserialize(deserializer)?;
+        Ok(FontDescription::from_string(&font_description))
+    }
 }
@@ -1,6 +1,8 @@
 use std::ops::{Add, Mul, Neg, Sub};
 
-#[derive(Clone, Copy, Debug)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct Point {
     pub x: f64,
     pub y: f64,
@@ -14,7 +14,9 @@ use cairo::{
 };
 use gtk4::prelude::{FileExt, IOStreamExt, InputStreamExtManual};
 use tracing::error;
-use xcb::{x, Xid};
+use xcb::{x, Xid, XidNew};
+
+use crate::metadata::WindowInfo;
 
 extern "C" {
     /// cairo-rs doesn't expose it in its ffi module, so I have to write its declaration myself
@@ -170,3 +172,89 @@ pub fn get_screen_resolution() -> Result<(i32, i32), Error> {
 
     Err(Error::FailedToGetScreenResolution)
 }
+
+/// Gets the title and class of the window which currently has focus, as reported by the window
+/// manager through `_NET_ACTIVE_WINDOW`
+///
+/// # Returns
+/// `None` if the window manager doesn't support `_NET_ACTIVE_WINDOW` or if no window has focus
+pub fn get_active_window() -> Result<Option<WindowInfo>, Error> {
+    let (connection, screen_num) = xcb::Connection::connect(None)?;
+    let root = match connection.get_setup().roots().nth(screen_num as usize) {
+        Some(screen) => screen.root(),
+        None => return Ok(None),
+    };
+
+    let net_active_window = intern_atom(&connection, b"_NET_ACTIVE_WINDOW")?;
+    let net_wm_name = intern_atom(&connection, b"_NET_WM_NAME")?;
+    let utf8_string = intern_atom(&connection, b"UTF8_STRING")?;
+    if net_active_window == x::ATOM_NONE {
+        return Ok(None);
+    }
+
+    let reply = get_property(&connection, root, net_active_window, x::ATOM_WINDOW)?;
+    let window = match reply.format() {
+        32 => match reply.value::<u32>().first() {
+            Some(&id) if id != 0 => x::Window::new(id),
+            _ => return Ok(None),
+        },
+        _ => return Ok(None),
+    };
+
+    let title = match get_string_property(&connection, window, net_wm_name, utf8_string)? {
+        Some(title) => Some(title),
+        None => get_string_property(&connection, window, x::ATOM_WM_NAME, x::ATOM_STRING)?,
+    };
+    // WM_CLASS holds two nul-terminated strings: the instance name followed by the class name
+    let class = get_string_property(&connection, window, x::ATOM_WM_CLASS, x::ATOM_STRING)?
+        .and_then(|wm_class| wm_class.split('\0').nth(1).map(String::from))
+        .filter(|class| !class.is_empty());
+
+    Ok(Some(WindowInfo { title, class }))
+}
+
+fn intern_atom(connection: &xcb::Connection, name: &[u8]) -> Result<x::Atom, Error> {
+    let cookie = connection.send_request(&x::InternAtom {
+        only_if_exists: true,
+        name,
+    });
+
+    Ok(connection.wait_for_reply(cookie)?.atom())
+}
+
+fn get_property(
+    connection: &xcb::Connection,
+    window: x::Window,
+    property: x::Atom,
+    r#type: x::Atom,
+) -> Result<x::GetPropertyReply, Error> {
+    let cookie = connection.send_request(&x::GetProperty {
+        delete: false,
+        window,
+        property,
+        r#type,
+        long_offset: 0,
+        long_length: u32::MAX,
+    });
+
+    Ok(connection.wait_for_reply(cookie)?)
+}
+
+fn get_string_property(
+    connection: &xcb::Connection,
+    window: x::Window,
+    property: x::Atom,
+    r#type: x::Atom,
+) -> Result<Option<String>, Error> {
+    if property == x::ATOM_NONE {
+        return Ok(None);
+    }
+
+    let reply = get_property(connection, window, property, r#type)?;
+    if reply.format() != 8 || reply.value::<u8>().is_empty() {
+        return Ok(None);
+    }
+
+    let value = String::from_utf8_lossy(reply.value::<u8>());
+    Ok(Some(value.trim_end_matches('\0').to_owned()))
+}
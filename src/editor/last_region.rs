@@ -0,0 +1,120 @@
+use gtk4::{gdk, gio, prelude::*};
+use tracing::{error, info, warn};
+
+use super::{data::Rectangle, display_server, utils};
+use crate::{
+    historymodel::HistoryModel,
+    kcshot::KCShot,
+    metadata::CaptureMode,
+    postcapture::{self, Capture},
+};
+
+/// Remembers `rect` as the last cropped region, together with the monitor it was on
+pub fn save(settings: &gio::Settings, rect: Rectangle) {
+    let Rectangle { x, y, w, h } = rect;
+    if let Err(why) = settings.set("last-region", &(x, y, w, h)) {
+        error!("Failed to save 'last-region': {}", why);
+    }
+
+    let monitor = monitor_containing(rect).unwrap_or_default();
+    if let Err(why) = settings.set_string("last-region-monitor", &monitor) {
+        error!("Failed to save 'last-region-monitor': {}", why);
+    }
+}
+
+/// Retrieves the region saved by [`save`], if one was saved
+pub fn load(settings: &gio::Settings) -> Option<Rectangle> {
+    let (x, y, w, h) = settings.get::<(f64, f64, f64, f64)>("last-region");
+
+    // (w, h) == (0, 0) is the default value of the key and means no region was ever saved
+    if w == 0.0 && h == 0.0 {
+        None
+    } else {
+        Some(Rectangle { x, y, w, h })
+    }
+}
+
+/// Takes a screenshot of the last region cropped in the editor and runs the post-capture action
+/// on it, without showing the editor
+pub fn capture(app: &KCShot, history_model: &HistoryModel) {
+    let settings = gio::Settings::new("kc.kcshot");
+
+    let rect = match load(&settings) {
+        Some(rect) => rect,
+        None => {
+            error!("No region has been cropped yet, there is no last region to capture");
+            return;
+        }
+    };
+
+    let monitor = settings.string("last-region-monitor");
+    if !monitor.is_empty() && !is_monitor_connected(&monitor) {
+        error!(
+            "The last region was captured on monitor '{}' which is no longer connected",
+            monitor
+        );
+        return;
+    }
+
+    let window = super::active_window();
+    let surface = match display_server::take_screenshot() {
+        Ok(surface) => surface,
+        Err(why) => {
+            error!("Failed to take screenshot: {}", why);
+            return;
+        }
+    };
+
+    info!("Capturing last region {:?}", rect);
+    match utils::pixbuf_for(&surface, rect) {
+        Some(pixbuf) => {
+            let capture = Capture {
+                pixbuf,
+                project: None,
+                mode: CaptureMode::Region,
+                window,
+                monitor: Some(monitor.to_string())
+                    .filter(|monitor| !monitor.is_empty())
+                    .or_else(|| monitor_containing(rect)),
+            };
+            postcapture::current_action().handle(history_model, app.conn(), capture)
+        }
+        None => error!(
+            "Failed to create a pixbuf from the surface: {:?} with crop region {:#?}",
+            surface, rect
+        ),
+    }
+}
+
+fn monitors() -> Vec<gdk::Monitor> {
+    let monitors = match gdk::Display::default().and_then(|display| display.monitors()) {
+        Some(monitors) => monitors,
+        None => {
+            warn!("Failed to fetch the list of monitors");
+            return vec![];
+        }
+    };
+
+    (0..monitors.n_items())
+        .filter_map(|idx| monitors.item(idx))
+        .filter_map(|monitor| monitor.downcast::<gdk::Monitor>().ok())
+        .collect()
+}
+
+/// Returns the connector name of the monitor which contains the centre of `rect`
+pub fn monitor_containing(rect: Rectangle) -> Option<String> {
+    let centre_x = (rect.x + rect.w / 2.0) as i32;
+    let centre_y = (rect.y + rect.h / 2.0) as i32;
+
+    monitors()
+        .into_iter()
+        .find(|monitor| monitor.geometry().contains_point(centre_x, centre_y))
+        .and_then(|monitor| monitor.connector())
+        .map(Into::into)
+}
+
+fn is_monitor_connected(connector: &str) -> bool {
+    monitors()
+        .iter()
+        .any(|monitor| monitor.connector().as_deref() == Some(connector))
+}
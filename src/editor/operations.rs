@@ -1,26 +1,18 @@
-use std::f64::consts::PI;
+use std::{convert::TryFrom, f64::consts::PI};
 
 use cairo::{Context, Error as CairoError, ImageSurface};
-use gtk4::{
-    gdk::prelude::GdkCairoContextExt,
-    gdk_pixbuf::{Colorspace, Pixbuf},
-    pango::FontDescription,
-};
-use image::{
-    flat::{self, SampleLayout},
-    imageops, FlatSamples, Rgb,
-};
-use rand::{prelude::StdRng, Rng, SeedableRng};
+use gtk4::pango::{self, prelude::FontMapExt, FontDescription};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
 use tracing::{error, info};
 
+mod effects;
 mod stack;
 
 pub use stack::*;
 
-use super::{
-    data::*,
-    utils::{self, CairoExt},
-};
+use self::effects::{Cache, Region};
+use super::{data::*, utils::CairoExt};
 
 const HIGHLIGHT_COLOUR: Colour = Colour {
     red: 255,
@@ -36,25 +28,77 @@ const INVISIBLE: Colour = Colour {
     alpha: 0,
 };
 
-/// The length of the arrowhead will be 1/10th of the length of the body
-const ARROWHEAD_LENGTH_RATIO: f64 = 0.1;
+/// How long arrowheads are compared to the width of the line, so that they look the same however
+/// long the arrow is
+const ARROWHEAD_LENGTH_RATIO: f64 = 5.0;
+/// Arrowheads are never shorter than this, those of thin lines would be hard to see otherwise
+const ARROWHEAD_MIN_LENGTH: f64 = 10.0;
 /// How open/closed the arrowhead will be
 const ARROWHEAD_APERTURE: f64 = PI / 6.0;
-/// How big will pixelate boxes be, in this case, we will group the rectangle into 4x4 boxes, which we will set all of its pixels to the same value
-const PIXELATE_SIZE: u64 = 4;
-/// How big the bubbles will be
-const BUBBLE_RADIUS: f64 = 10.0;
+/// How wide lines were before their width could be chosen, cairo's default
+const OLD_LINE_WIDTH: f64 = 2.0;
+/// How close to the handle of an arrow a click has to be to grab it
+pub const ARROW_HANDLE_RADIUS: f64 = 8.0;
+/// How big pixelate boxes were before their size could be chosen, projects which were saved back
+/// then don't store it
+const OLD_PIXELATE_SIZE: usize = 4;
+/// Blurring takes longer the bigger the radius is, and this is plenty to hide anything
+pub const MAX_BLUR_RADIUS: f32 = 20.0;
+/// Blurs whose radius is smaller than this may leave text readable
+const LEGIBLE_BLUR_RADIUS: f32 = 6.0;
+/// Pixelate boxes smaller than this may leave text readable
+const LEGIBLE_PIXELATE_SIZE: usize = 10;
+/// The standard deviation of the blur spotlights can put over what's outside their spots
+const SPOTLIGHT_BLUR_RADIUS: f32 = 4.0;
+/// How wide and high the part of the image which magnifiers show enlarged is, after enlarging it,
+/// when it was clicked instead of dragged out
+const MAGNIFIER_SIZE: f64 = 160.0;
+/// How far the destination of a magnifier whose destination wasn't placed is from its source
+const MAGNIFIER_GAP: f64 = 16.0;
+const MAGNIFIER_BORDER_WIDTH: f64 = 3.0;
+/// How far the box behind text reaches past it
+const TEXT_BOX_PADDING: f64 = 6.0;
+/// How wide the outline around text is compared to the size of its font
+const TEXT_OUTLINE_RATIO: f64 = 0.125;
+/// The font text has if none was chosen
+pub const DEFAULT_FONT: &str = "Fira Code, 40pt";
+/// How far square bubbles reach past their number, round ones fit around its corners instead
+const BUBBLE_PADDING: f64 = 4.0;
+const BUBBLE_OUTLINE_WIDTH: f64 = 2.0;
+/// The font of bubbles, they are as big as their numbers so their size is the size of the font
+const BUBBLE_FONT: &str = "Fira Code";
+/// The biggest number which can be written with roman numerals without bars over them
+const MAX_ROMAN: i32 = 3999;
+/// How far the box of a callout reaches past its text
+const CALLOUT_PADDING: f64 = 10.0;
+const CALLOUT_CORNER_RADIUS: f64 = 12.0;
+/// How wide the tail of a callout is where it meets the box
+const CALLOUT_TAIL_WIDTH: f64 = 24.0;
+const CALLOUT_BORDER_WIDTH: f64 = 2.0;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum Operation {
     Crop(Rectangle),
     Blur {
         rect: Rectangle,
         radius: f32,
+        /// The blurred rectangle, with the rectangle and radius it was made for
+        #[serde(skip)]
+        rendered: Cache<(Rectangle, f32)>,
     },
     Pixelate {
         rect: Rectangle,
         seed: u64,
+        /// How big the boxes the rectangle is split into are, every pixel of a box is set to
+        /// the same value
+        #[serde(default = "old_pixelate_size")]
+        size: usize,
+    },
+    /// Covers a rectangle with something which doesn't depend on what was under it, unlike blurs
+    /// and pixelation which can sometimes be undone
+    Redact {
+        rect: Rectangle,
+        redaction: Redaction,
     },
     DrawLine {
         start: Point,
@@ -66,34 +110,328 @@ pub enum Operation {
         border: Colour,
         fill: Colour,
     },
+    /// Text with Pango markup
     Text {
         top_left: Point,
         text: String,
         colour: Colour,
+        #[serde(with = "font_description")]
         font_description: FontDescription,
+        /// The colour of a box drawn behind the text
+        #[serde(default)]
+        background: Option<Colour>,
+        /// The colour of a line drawn around the text
+        #[serde(default)]
+        outline: Option<Colour>,
     },
     DrawArrow {
         start: Point,
         end: Point,
         colour: Colour,
+        #[serde(default = "old_line_width")]
+        width: f64,
+        #[serde(default)]
+        head: ArrowHead,
+        /// Whether there's a head at the start too
+        #[serde(default)]
+        is_double: bool,
+        /// The control point of the quadratic Bézier curve the arrow follows, arrows without one
+        /// are straight
+        #[serde(default)]
+        control: Option<Point>,
     },
     Highlight {
         rect: Rectangle,
     },
+    /// Darkens, and maybe blurs, the whole image except for the spots
+    Spotlight {
+        spots: Vec<Spot>,
+        /// How opaque the black which is drawn over the rest of the image is, from 0 to 1
+        darkness: f64,
+        blur: bool,
+        /// The blurred image, with the size of the image it was made for
+        #[serde(skip)]
+        rendered: Cache<(i32, i32)>,
+    },
+    /// Shows a copy of the `source` part of the image which is enlarged `zoom` times to fill
+    /// `destination`, a line connects the two. The source is dragged out first and the
+    /// destination after it, until then it's next to the source.
+    Magnifier {
+        source: Rectangle,
+        destination: Option<Rectangle>,
+        zoom: f64,
+        is_ellipse: bool,
+        border: Colour,
+    },
     DrawEllipse {
         ellipse: Ellipse,
         border: Colour,
         fill: Colour,
     },
+    /// A circle or square as big as the number in it, bubbles are numbered in the order they
+    /// were made in
     Bubble {
         centre: Point,
         bubble_colour: Colour,
         text_colour: Colour,
         number: i32,
+        #[serde(with = "font_description")]
+        font_description: FontDescription,
+        /// How `number` is written
+        #[serde(default)]
+        numbering: Numbering,
+        #[serde(default)]
+        is_square: bool,
+        /// Whether a line is drawn around the bubble in the colour of its number
+        #[serde(default)]
+        outline: bool,
+    },
+    /// A rounded box around text with Pango markup, and a tail which points from the box at
+    /// `target`. The box is as big as the text in it, the text has the colour of the border.
+    Callout {
+        target: Point,
+        /// Where the text starts, the box reaches [`CALLOUT_PADDING`] past it
+        top_left: Point,
+        text: String,
+        #[serde(with = "font_description")]
         font_description: FontDescription,
+        fill: Colour,
+        border: Colour,
+    },
+}
+
+/// What redacted rectangles are filled with
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum Redaction {
+    /// An opaque colour
+    Solid(Colour),
+    Noise {
+        seed: u64,
     },
 }
 
+/// A part of the image which a spotlight leaves as it is
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum Spot {
+    Rectangle(Rectangle),
+    Ellipse(Ellipse),
+}
+
+impl Spot {
+    fn new(Point { x, y }: Point, is_ellipse: bool) -> Self {
+        if is_ellipse {
+            Self::Ellipse(Ellipse {
+                x,
+                y,
+                w: 1.0,
+                h: 1.0,
+            })
+        } else {
+            Self::Rectangle(Rectangle {
+                x,
+                y,
+                w: 1.0,
+                h: 1.0,
+            })
+        }
+    }
+
+    fn resize(&mut self, new_width: f64, new_height: f64) {
+        let (w, h) = match self {
+            Spot::Rectangle(Rectangle { w, h, .. }) | Spot::Ellipse(Ellipse { w, h, .. }) => (w, h),
+        };
+        *w = new_width;
+        *h = new_height;
+    }
+
+    fn add_to_path(&self, cairo: &Context) -> Result<(), Error> {
+        match *self {
+            Spot::Rectangle(Rectangle { x, y, w, h }) => {
+                add_shape_to_path(cairo, x, y, w, h, false)
+            }
+            Spot::Ellipse(Ellipse { x, y, w, h }) => add_shape_to_path(cairo, x, y, w, h, true),
+        }
+    }
+}
+
+/// Adds a rectangle, or the ellipse which fits inside it, to the current path
+fn add_shape_to_path(
+    cairo: &Context,
+    x: f64,
+    y: f64,
+    w: f64,
+    h: f64,
+    is_ellipse: bool,
+) -> Result<(), Error> {
+    if !is_ellipse {
+        cairo.rectangle(x, y, w, h);
+    } else if w != 0.0 && h != 0.0 {
+        // Scaling by 0 would make the transformation matrix invalid
+        cairo.save()?;
+        cairo.new_sub_path();
+        cairo.translate(x + w / 2.0, y + h / 2.0);
+        cairo.scale(w / 2.0, h / 2.0);
+        cairo.arc(0.0, 0.0, 1.0, 0.0, 2.0 * PI);
+        cairo.restore()?;
+    }
+
+    Ok(())
+}
+
+/// What's drawn around text to make it stand out from what's behind it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextStyle {
+    Plain,
+    Box,
+    Outline,
+}
+
+impl TextStyle {
+    /// The name under which the style is stored in settings
+    pub const fn id(self) -> &'static str {
+        match self {
+            TextStyle::Plain => "plain",
+            TextStyle::Box => "box",
+            TextStyle::Outline => "outline",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<Self> {
+        match id {
+            "plain" => Some(TextStyle::Plain),
+            "box" => Some(TextStyle::Box),
+            "outline" => Some(TextStyle::Outline),
+            _ => None,
+        }
+    }
+
+    /// The background and outline colours of text with this style
+    fn colours(self, colour: Colour) -> (Option<Colour>, Option<Colour>) {
+        match self {
+            TextStyle::Plain => (None, None),
+            TextStyle::Box => (Some(colour), None),
+            TextStyle::Outline => (None, Some(colour)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ArrowHead {
+    /// Two lines, like a chevron
+    #[default]
+    Open,
+    /// A filled triangle
+    Filled,
+}
+
+impl ArrowHead {
+    /// The name under which the arrowhead is stored in settings
+    pub const fn id(self) -> &'static str {
+        match self {
+            ArrowHead::Open => "open",
+            ArrowHead::Filled => "filled",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<Self> {
+        match id {
+            "open" => Some(ArrowHead::Open),
+            "filled" => Some(ArrowHead::Filled),
+            _ => None,
+        }
+    }
+}
+
+/// How the numbers of bubbles are written
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Numbering {
+    #[default]
+    Numbers,
+    /// A to Z, then AA to AZ and so on, like the columns of spreadsheets
+    Letters,
+    Roman,
+}
+
+impl Numbering {
+    /// The name under which the numbering is stored in settings
+    pub const fn id(self) -> &'static str {
+        match self {
+            Numbering::Numbers => "numbers",
+            Numbering::Letters => "letters",
+            Numbering::Roman => "roman",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<Self> {
+        match id {
+            "numbers" => Some(Numbering::Numbers),
+            "letters" => Some(Numbering::Letters),
+            "roman" => Some(Numbering::Roman),
+            _ => None,
+        }
+    }
+
+    /// Writes `number` this way, numbers which can't be written this way are written with digits
+    fn format(self, number: i32) -> String {
+        match self {
+            Numbering::Letters if number > 0 => {
+                let mut letters = vec![];
+                let mut number = number;
+                while number > 0 {
+                    number -= 1;
+                    letters.push(b'A' + (number % 26) as u8);
+                    number /= 26;
+                }
+                letters.iter().rev().map(|&letter| letter as char).collect()
+            }
+            Numbering::Roman if number > 0 && number <= MAX_ROMAN => {
+                const NUMERALS: [(i32, &str); 13] = [
+                    (1000, "M"),
+                    (900, "CM"),
+                    (500, "D"),
+                    (400, "CD"),
+                    (100, "C"),
+                    (90, "XC"),
+                    (50, "L"),
+                    (40, "XL"),
+                    (10, "X"),
+                    (9, "IX"),
+                    (5, "V"),
+                    (4, "IV"),
+                    (1, "I"),
+                ];
+                let mut numerals = String::new();
+                let mut number = number;
+                for (value, numeral) in NUMERALS {
+                    while number >= value {
+                        numerals.push_str(numeral);
+                        number -= value;
+                    }
+                }
+                numerals
+            }
+            _ => number.to_string(),
+        }
+    }
+}
+
+/// Which way the caret of the text which is being typed moves
+#[derive(Clone, Copy, Debug)]
+pub enum CaretMovement {
+    Left,
+    Right,
+    LineStart,
+    LineEnd,
+}
+
+fn old_pixelate_size() -> usize {
+    OLD_PIXELATE_SIZE
+}
+
+fn old_line_width() -> f64 {
+    OLD_LINE_WIDTH
+}
+
 /// This enum is like [Operations] but without any associated data
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Tool {
@@ -107,6 +445,60 @@ pub enum Tool {
     Blur = 7,
     AutoincrementBubble = 8,
     Text = 9,
+    Redact = 10,
+    Spotlight = 11,
+    Magnifier = 12,
+    Callout = 13,
+}
+
+/// How the tools which have settings besides colours are set up, new operations are made with
+/// these settings
+#[derive(Clone, Copy, Debug)]
+pub struct ToolSettings {
+    /// The standard deviation of blurs
+    pub blur_radius: f32,
+    pub pixelate_size: usize,
+    /// Whether redacted rectangles are filled with noise instead of the primary colour
+    pub redact_with_noise: bool,
+    /// Whether new spots are ellipses instead of rectangles
+    pub spotlight_ellipse: bool,
+    pub spotlight_darkness: f64,
+    pub spotlight_blur: bool,
+    pub magnifier_zoom: f64,
+    pub magnifier_ellipse: bool,
+    pub text_style: TextStyle,
+    /// The size of the numbers of bubbles in points, the bubbles fit around them
+    pub bubble_size: f64,
+    pub bubble_square: bool,
+    pub bubble_outline: bool,
+    pub bubble_numbering: Numbering,
+    pub arrow_width: f64,
+    pub arrow_head: ArrowHead,
+    /// Whether new arrows have heads at both ends
+    pub arrow_double: bool,
+}
+
+impl Default for ToolSettings {
+    fn default() -> Self {
+        Self {
+            blur_radius: 8.0,
+            pixelate_size: 12,
+            redact_with_noise: false,
+            spotlight_ellipse: false,
+            spotlight_darkness: 0.6,
+            spotlight_blur: false,
+            magnifier_zoom: 2.0,
+            magnifier_ellipse: true,
+            text_style: TextStyle::Plain,
+            bubble_size: 16.0,
+            bubble_square: false,
+            bubble_outline: false,
+            bubble_numbering: Numbering::Numbers,
+            arrow_width: 3.0,
+            arrow_head: ArrowHead::Open,
+            arrow_double: false,
+        }
+    }
 }
 
 impl Tool {
@@ -122,8 +514,41 @@ impl Tool {
             Tool::Blur => "resources/editor/tool-blur.png",
             Tool::AutoincrementBubble => "resources/editor/tool-autoincrementbubble.png",
             Tool::Text => "resources/editor/tool-text.png",
+            Tool::Redact => "resources/editor/tool-redact.png",
+            Tool::Spotlight => "resources/editor/tool-spotlight.png",
+            Tool::Magnifier => "resources/editor/tool-magnifier.png",
+            Tool::Callout => "resources/editor/tool-callout.png",
         }
     }
+
+    /// Whether the operations this tool makes are typed into after they're made
+    pub const fn types_text(self) -> bool {
+        matches!(self, Tool::Text | Tool::Callout)
+    }
+}
+
+impl TryFrom<i32> for Tool {
+    type Error = i32;
+
+    fn try_from(value: i32) -> Result<Self, Self::Error> {
+        Ok(match value {
+            0 => Tool::CropAndSave,
+            1 => Tool::Line,
+            2 => Tool::Arrow,
+            3 => Tool::Rectangle,
+            4 => Tool::Ellipse,
+            5 => Tool::Highlight,
+            6 => Tool::Pixelate,
+            7 => Tool::Blur,
+            8 => Tool::AutoincrementBubble,
+            9 => Tool::Text,
+            10 => Tool::Redact,
+            11 => Tool::Spotlight,
+            12 => Tool::Magnifier,
+            13 => Tool::Callout,
+            _ => return Err(value),
+        })
+    }
 }
 
 impl Operation {
@@ -133,6 +558,8 @@ impl Operation {
         bubble_index: &mut i32,
         primary_colour: Colour,
         secondary_colour: Colour,
+        settings: ToolSettings,
+        text_font: &FontDescription,
     ) -> Self {
         let rect = Rectangle {
             x: start.x,
@@ -141,8 +568,6 @@ impl Operation {
             h: 1.0,
         };
 
-        let font_description = FontDescription::from_string("Fira Code, 40pt");
-
         match tool {
             Tool::CropAndSave => Self::Crop(Rectangle {
                 x: start.x,
@@ -160,6 +585,10 @@ impl Operation {
                 start,
                 end: start,
                 colour: primary_colour,
+                width: settings.arrow_width,
+                head: settings.arrow_head,
+                is_double: settings.arrow_double,
+                control: None,
             },
             Tool::Rectangle => Self::DrawRectangle {
                 rect,
@@ -177,27 +606,79 @@ impl Operation {
                 fill: primary_colour,
             },
             Tool::Highlight => Self::Highlight { rect },
+            Tool::Spotlight => Self::Spotlight {
+                spots: vec![Spot::new(start, settings.spotlight_ellipse)],
+                darkness: settings.spotlight_darkness,
+                blur: settings.spotlight_blur,
+                rendered: Cache::default(),
+            },
+            Tool::Magnifier => Self::Magnifier {
+                // Without a drag, the zoom decides how big it is
+                source: Rectangle {
+                    x: start.x,
+                    y: start.y,
+                    w: 0.0,
+                    h: 0.0,
+                },
+                destination: None,
+                zoom: settings.magnifier_zoom,
+                is_ellipse: settings.magnifier_ellipse,
+                border: primary_colour,
+            },
             Tool::Pixelate => Self::Pixelate {
                 rect,
                 seed: rand::thread_rng().gen(),
+                size: settings.pixelate_size,
+            },
+            Tool::Blur => Self::Blur {
+                rect,
+                radius: settings.blur_radius,
+                rendered: Cache::default(),
+            },
+            Tool::Redact => Self::Redact {
+                rect,
+                redaction: if settings.redact_with_noise {
+                    Redaction::Noise {
+                        seed: rand::thread_rng().gen(),
+                    }
+                } else {
+                    Redaction::Solid(primary_colour)
+                },
             },
-            Tool::Blur => Self::Blur { rect, radius: 5.0 },
             Tool::AutoincrementBubble => {
+                let mut font_description = FontDescription::from_string(BUBBLE_FONT);
+                font_description.set_size((settings.bubble_size * pango::SCALE as f64) as i32);
                 let bubble = Self::Bubble {
                     centre: start,
                     bubble_colour: secondary_colour,
                     text_colour: primary_colour,
                     number: *bubble_index,
                     font_description,
+                    numbering: settings.bubble_numbering,
+                    is_square: settings.bubble_square,
+                    outline: settings.bubble_outline,
                 };
                 *bubble_index += 1;
                 bubble
             }
-            Tool::Text => Self::Text {
+            Tool::Text => {
+                let (background, outline) = settings.text_style.colours(secondary_colour);
+                Self::Text {
+                    top_left: start,
+                    text: "".into(),
+                    colour: primary_colour,
+                    font_description: text_font.clone(),
+                    background,
+                    outline,
+                }
+            }
+            Tool::Callout => Self::Callout {
+                target: start,
                 top_left: start,
                 text: "".into(),
-                colour: primary_colour,
-                font_description,
+                font_description: text_font.clone(),
+                fill: primary_colour,
+                border: secondary_colour,
             },
         }
     }
@@ -231,28 +712,37 @@ impl Operation {
                     // However, that is not done here, but rather inside EditorWindow::do_save_surface
                 }
             }
-            Operation::Blur { rect, radius } => {
-                cairo.save()?;
-                let pixbuf = utils::pixbuf_for(surface, *rect).ok_or(Error::Pixbuf(*rect))?;
-
-                blur(
-                    cairo,
-                    pixbuf,
-                    *radius,
-                    Point {
-                        x: rect.x,
-                        y: rect.y,
-                    },
-                )?;
-
-                cairo.restore()?;
+            Operation::Blur {
+                rect,
+                radius,
+                rendered,
+            } => {
+                // Projects from before blurs were capped may have stronger ones
+                let radius = radius.min(MAX_BLUR_RADIUS);
+                rendered.paint(cairo, (*rect, radius), || {
+                    let mut region = Region::copy(surface, *rect)?;
+                    if let Some(region) = &mut region {
+                        region.blur(radius)?;
+                    }
+                    Ok(region)
+                })?;
             }
-            Operation::Pixelate { rect, seed } => {
+            Operation::Pixelate { rect, seed, size } => {
                 info!("Pixelate");
-
-                let pixbuf = utils::pixbuf_for(surface, *rect).ok_or(Error::Pixbuf(*rect))?;
-
-                pixelate(cairo, pixbuf, rect, *seed)?;
+                if let Some(mut region) = Region::copy(surface, *rect)? {
+                    region.pixelate(*size, *seed)?;
+                    region.paint(cairo)?;
+                }
+            }
+            Operation::Redact { rect, redaction } => {
+                info!("Redact");
+                if let Some(mut region) = Region::empty(surface, *rect)? {
+                    match redaction {
+                        Redaction::Solid(colour) => region.fill(*colour)?,
+                        Redaction::Noise { seed } => region.fill_with_noise(*seed)?,
+                    }
+                    region.paint(cairo)?;
+                }
             }
             Operation::DrawLine { start, end, colour } => {
                 info!("Line");
@@ -267,20 +757,65 @@ impl Operation {
                 text,
                 colour,
                 font_description,
+                background,
+                outline,
             } => {
                 info!("Text");
-                cairo.save()?;
-                draw_text_at(cairo, *top_left, text, *colour, font_description)?;
-                cairo.restore()?;
+                let layout = text_layout(cairo, text, font_description, true);
+                draw_text_at(cairo, *top_left, &layout, *colour, *background, *outline)?;
             }
-            Operation::DrawArrow { start, end, colour } => {
+            Operation::DrawArrow {
+                start,
+                end,
+                colour,
+                width,
+                head,
+                is_double,
+                control,
+            } => {
                 info!("Arrow");
-                draw_arrow(cairo, *start, *end, *colour)?;
+                draw_arrow(
+                    cairo, *start, *end, *control, *colour, *width, *head, *is_double,
+                )?;
             }
             Operation::Highlight { rect } => {
                 info!("Highlight");
                 draw_rectangle(cairo, rect, INVISIBLE, HIGHLIGHT_COLOUR)?;
             }
+            Operation::Spotlight {
+                spots,
+                darkness,
+                blur,
+                rendered,
+            } => {
+                info!("Spotlight");
+                let blurred = if *blur { Some(rendered) } else { None };
+                draw_spotlight(cairo, surface, spots, *darkness, blurred)?;
+            }
+            Operation::Magnifier {
+                source,
+                destination,
+                zoom,
+                is_ellipse,
+                border,
+            } => {
+                info!("Magnifier");
+                let destination = destination.unwrap_or_else(|| Rectangle {
+                    x: source.x + source.w + MAGNIFIER_GAP,
+                    y: source.y + source.h / 2.0 - source.h * *zoom / 2.0,
+                    w: source.w * *zoom,
+                    h: source.h * *zoom,
+                });
+                draw_magnifier(
+                    cairo,
+                    surface,
+                    source,
+                    &destination,
+                    *zoom,
+                    *is_ellipse,
+                    *border,
+                )?;
+            }
             Operation::DrawEllipse {
                 ellipse,
                 border,
@@ -297,43 +832,309 @@ impl Operation {
                 text_colour,
                 number,
                 font_description,
+                numbering,
+                is_square,
+                outline,
             } => {
                 info!("Bubble");
-                let Point { x, y } = centre;
-                let num_str = number.to_string();
-
-                let ellipse = Ellipse {
-                    x: x - BUBBLE_RADIUS,
-                    y: y - BUBBLE_RADIUS,
-                    w: 2.0 * BUBBLE_RADIUS,
-                    h: 2.0 * BUBBLE_RADIUS,
-                };
-
-                draw_ellipse(cairo, &ellipse, INVISIBLE, *bubble_colour)?;
-                draw_text_centred_at(
+                let layout =
+                    text_layout(cairo, &numbering.format(*number), font_description, false);
+                draw_bubble(
                     cairo,
                     *centre,
-                    num_str.as_str(),
+                    &layout,
+                    *bubble_colour,
                     *text_colour,
-                    font_description,
+                    *is_square,
+                    *outline,
                 )?;
             }
+            Operation::Callout {
+                target,
+                top_left,
+                text,
+                font_description,
+                fill,
+                border,
+            } => {
+                info!("Callout");
+                let layout = text_layout(cairo, text, font_description, true);
+                draw_callout(cairo, *target, *top_left, &layout, *fill, *border)?;
+            }
         };
 
         Ok(())
     }
 
-    pub fn set_text(&mut self, input_text: Text) {
+    /// Gives the source or destination of a magnifier which was clicked instead of dragged out the
+    /// size its zoom asks for, centred on where it was clicked
+    pub(super) fn size_clicked_magnifier(&mut self) {
+        let (source, destination, zoom) = match self {
+            Operation::Magnifier {
+                source,
+                destination,
+                zoom,
+                ..
+            } => (source, destination, *zoom),
+            _ => return,
+        };
+
+        if source.w == 0.0 && source.h == 0.0 {
+            let size = MAGNIFIER_SIZE / zoom;
+            *source = Rectangle {
+                x: source.x - size / 2.0,
+                y: source.y - size / 2.0,
+                w: size,
+                h: size,
+            };
+        }
+        if let Some(destination) = destination {
+            if destination.w == 0.0 && destination.h == 0.0 {
+                let (w, h) = (source.w * zoom, source.h * zoom);
+                *destination = Rectangle {
+                    x: destination.x - w / 2.0,
+                    y: destination.y - h / 2.0,
+                    w,
+                    h,
+                };
+            }
+        }
+    }
+
+    /// Adds a spot at `start` to a spotlight, nothing happens to other operations
+    fn add_spot(&mut self, start: Point, is_ellipse: bool) {
+        if let Operation::Spotlight { spots, .. } = self {
+            spots.push(Spot::new(start, is_ellipse));
+        }
+    }
+
+    /// Whether this is a blur or pixelation which is too weak to make the text it seems to cover
+    /// unreadable
+    pub fn may_leave_text_legible(&self, surface: &ImageSurface) -> Result<bool, Error> {
+        let rect = match self {
+            Operation::Blur { rect, radius, .. } if *radius < LEGIBLE_BLUR_RADIUS => rect,
+            Operation::Pixelate { rect, size, .. } if *size < LEGIBLE_PIXELATE_SIZE => rect,
+            _ => return Ok(false),
+        };
+
+        match Region::copy(surface, *rect)? {
+            Some(mut region) => region.looks_like_text(),
+            None => Ok(false),
+        }
+    }
+
+    /// Draws text which is being typed, like [`Operation::execute`] would but with the tags of its
+    /// markup shown, and with a caret at the byte index `caret`. Nothing is drawn for other
+    /// operations.
+    pub fn draw_text_being_edited(&self, cairo: &Context, caret: usize) -> Result<(), Error> {
+        let (top_left, colour, layout) = match self {
+            Operation::Text {
+                top_left,
+                text,
+                colour,
+                font_description,
+                background,
+                outline,
+            } => {
+                let layout = text_layout(cairo, text, font_description, false);
+                draw_text_at(cairo, *top_left, &layout, *colour, *background, *outline)?;
+                (top_left, colour, layout)
+            }
+            Operation::Callout {
+                target,
+                top_left,
+                text,
+                font_description,
+                fill,
+                border,
+            } => {
+                let layout = text_layout(cairo, text, font_description, false);
+                draw_callout(cairo, *target, *top_left, &layout, *fill, *border)?;
+                (top_left, border, layout)
+            }
+            _ => return Ok(()),
+        };
+
+        let caret = layout.index_to_pos(caret as i32);
+        let x = top_left.x + caret.x as f64 / pango::SCALE as f64;
+        let y = top_left.y + caret.y as f64 / pango::SCALE as f64;
+        cairo.save()?;
+        cairo.set_source_colour(*colour);
+        cairo.set_line_width(2.0);
+        cairo.move_to(x, y);
+        cairo.rel_line_to(0.0, caret.height as f64 / pango::SCALE as f64);
+        cairo.stroke()?;
+        cairo.restore()?;
+
+        Ok(())
+    }
+
+    /// Whether this is text, or a callout, which covers `point`. Only the box of callouts counts.
+    pub fn text_contains(&self, Point { x, y }: Point) -> bool {
+        let (top_left, text, font_description, padding) = match self {
+            Operation::Text {
+                top_left,
+                text,
+                font_description,
+                ..
+            } => (top_left, text, font_description, 0.0),
+            Operation::Callout {
+                top_left,
+                text,
+                font_description,
+                ..
+            } => (top_left, text, font_description, CALLOUT_PADDING),
+            _ => return false,
+        };
+        let layout = match measuring_layout() {
+            Some(layout) => layout,
+            None => return false,
+        };
+        set_layout_text(&layout, text, true);
+        layout.set_font_description(Some(font_description));
+        let (_, extents) = layout.pixel_extents();
+
+        let x = x - top_left.x;
+        let y = y - top_left.y;
+        x >= extents.x as f64 - padding
+            && x <= (extents.x + extents.width) as f64 + padding
+            && y >= extents.y as f64 - padding
+            && y <= (extents.y + extents.height) as f64 + padding
+    }
+
+    /// Where the handle which bends arrows is, that is at their control point or in the middle of
+    /// straight ones. Other operations don't have one.
+    pub fn arrow_handle(&self) -> Option<Point> {
+        match self {
+            Operation::DrawArrow {
+                control: Some(control),
+                ..
+            } => Some(*control),
+            Operation::DrawArrow { start, end, .. } => Some(Point {
+                x: (start.x + end.x) / 2.0,
+                y: (start.y + end.y) / 2.0,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Draws the handle which bends arrows, nothing is drawn for other operations
+    pub fn draw_arrow_handle(&self, cairo: &Context) -> Result<(), Error> {
+        if let Some(Point { x, y }) = self.arrow_handle() {
+            cairo.save()?;
+            cairo.new_sub_path();
+            cairo.arc(x, y, ARROW_HANDLE_RADIUS / 2.0, 0.0, 2.0 * PI);
+            cairo.set_source_colour(Colour::WHITE);
+            cairo.fill_preserve()?;
+            cairo.set_source_colour(Colour::BLACK);
+            cairo.set_line_width(1.0);
+            cairo.stroke()?;
+            cairo.restore()?;
+        }
+
+        Ok(())
+    }
+
+    /// Whether this is a bubble which covers `point`
+    pub fn bubble_contains(&self, point: Point) -> bool {
+        let (centre, number, font_description, numbering, is_square) = match self {
+            Operation::Bubble {
+                centre,
+                number,
+                font_description,
+                numbering,
+                is_square,
+                ..
+            } => (centre, number, font_description, numbering, is_square),
+            _ => return false,
+        };
+        let layout = match measuring_layout() {
+            Some(layout) => layout,
+            None => return false,
+        };
+        layout.set_text(&numbering.format(*number));
+        layout.set_font_description(Some(font_description));
+
+        let radius = bubble_radius(&layout, *is_square);
+        let Point { x, y } = point - *centre;
+        if *is_square {
+            x.abs() <= radius && y.abs() <= radius
+        } else {
+            x.hypot(y) <= radius
+        }
+    }
+
+    /// Takes one off the number of bubbles whose number is bigger than `number`
+    pub(super) fn renumber_bubble_after(&mut self, deleted_number: i32) {
+        if let Operation::Bubble { number, .. } = self {
+            if *number > deleted_number {
+                *number -= 1;
+            }
+        }
+    }
+
+    /// Whether the operation shows nothing, like text which nothing was typed into or arrows which
+    /// were never dragged out, those aren't worth keeping
+    pub(super) fn is_empty(&self) -> bool {
+        match self {
+            Operation::Text { text, .. } | Operation::Callout { text, .. } => text.is_empty(),
+            Operation::DrawArrow { start, end, .. } => (*end - *start).dist() == 0.0,
+            _ => false,
+        }
+    }
+
+    /// The text of text and callouts
+    pub fn text(&self) -> Option<&String> {
+        match self {
+            Operation::Text { text, .. } | Operation::Callout { text, .. } => Some(text),
+            _ => None,
+        }
+    }
+
+    pub(super) fn text_mut(&mut self) -> Option<&mut String> {
+        match self {
+            Operation::Text { text, .. } | Operation::Callout { text, .. } => Some(text),
+            _ => None,
+        }
+    }
+
+    /// Gives text and callouts the colours they would have if they were made with these colours,
+    /// and text the style it would have if it were made with `style`
+    fn set_text_colours(
+        &mut self,
+        primary_colour: Colour,
+        secondary_colour: Colour,
+        style: TextStyle,
+    ) {
+        match self {
+            Operation::Text {
+                colour,
+                background,
+                outline,
+                ..
+            } => {
+                *colour = primary_colour;
+                let (new_background, new_outline) = style.colours(secondary_colour);
+                *background = new_background;
+                *outline = new_outline;
+            }
+            Operation::Callout { fill, border, .. } => {
+                *fill = primary_colour;
+                *border = secondary_colour;
+            }
+            _ => {}
+        }
+    }
+
+    fn set_font_description(&mut self, new_font_description: &FontDescription) {
         if let Operation::Text {
-            text,
-            colour,
-            font_description,
-            ..
+            font_description, ..
+        }
+        | Operation::Callout {
+            font_description, ..
         } = self
         {
-            *text = input_text.string;
-            *font_description = input_text.font_description;
-            *colour = input_text.colour;
+            *font_description = new_font_description.clone();
         }
     }
 }
@@ -344,12 +1145,6 @@ pub enum Error {
     Cairo(#[from] CairoError),
     #[error("Encountered a cairo error while trying to borrow something: {0}")]
     Borrow(#[from] cairo::BorrowError),
-    #[error("Encountered an error while converting a `image::flat::FlatSamples` to a `image::flat::View: {0}")]
-    Image(#[from] flat::Error),
-    #[error("Couldn't make Pixbuf from ImageSurface with rect: {0:?}")]
-    Pixbuf(Rectangle),
-    #[error("`pixel_bytes` on a Pixbuf returned None")]
-    PixelBytes,
 }
 
 fn draw_rectangle(
@@ -372,6 +1167,118 @@ fn draw_rectangle(
     Ok(())
 }
 
+/// Unlike other operations, spotlights cover the whole of `surface`, except for their spots. What's
+/// outside the spots is blurred too if there's a cache to keep the blurred image in.
+fn draw_spotlight(
+    cairo: &Context,
+    surface: &ImageSurface,
+    spots: &[Spot],
+    darkness: f64,
+    blurred: Option<&Cache<(i32, i32)>>,
+) -> Result<(), Error> {
+    let whole_image = Rectangle {
+        x: 0.0,
+        y: 0.0,
+        w: surface.width() as f64,
+        h: surface.height() as f64,
+    };
+
+    cairo.save()?;
+    // Everything is drawn in a group first so that the spots can be cut out of it, going over a
+    // spot twice would darken it twice otherwise
+    cairo.push_group();
+    if let Some(blurred) = blurred {
+        blurred.paint(cairo, (surface.width(), surface.height()), || {
+            let mut region = Region::copy(surface, whole_image)?;
+            if let Some(region) = &mut region {
+                region.blur(SPOTLIGHT_BLUR_RADIUS)?;
+            }
+            Ok(region)
+        })?;
+    }
+    cairo.rectangle(whole_image.x, whole_image.y, whole_image.w, whole_image.h);
+    cairo.set_source_rgba(0.0, 0.0, 0.0, darkness);
+    cairo.fill()?;
+
+    cairo.set_operator(cairo::Operator::Clear);
+    for spot in spots {
+        spot.add_to_path(cairo)?;
+    }
+    cairo.fill()?;
+
+    cairo.pop_group_to_source()?;
+    cairo.set_operator(cairo::Operator::Over);
+    cairo.paint()?;
+    cairo.restore()?;
+
+    Ok(())
+}
+
+/// Draws the outline of `source`, `source` enlarged over `destination`, and a line between them
+fn draw_magnifier(
+    cairo: &Context,
+    surface: &ImageSurface,
+    source: &Rectangle,
+    destination: &Rectangle,
+    zoom: f64,
+    is_ellipse: bool,
+    border: Colour,
+) -> Result<(), Error> {
+    let source_centre = Point {
+        x: source.x + source.w / 2.0,
+        y: source.y + source.h / 2.0,
+    };
+    let destination_centre = Point {
+        x: destination.x + destination.w / 2.0,
+        y: destination.y + destination.h / 2.0,
+    };
+
+    cairo.save()?;
+    cairo.set_source_colour(border);
+    cairo.set_line_width(MAGNIFIER_BORDER_WIDTH);
+
+    add_shape_to_path(cairo, source.x, source.y, source.w, source.h, is_ellipse)?;
+    cairo.stroke()?;
+
+    // The line starts at the edge of the source, the destination covers its other end
+    let direction = destination_centre - source_centre;
+    let length = direction.dist();
+    if length > 0.0 {
+        let half_size = source.w.min(source.h) / 2.0;
+        let distance_to_edge = if is_ellipse {
+            half_size / length
+        } else {
+            half_size / direction.x.abs().max(direction.y.abs())
+        };
+        if distance_to_edge < 1.0 {
+            let start = source_centre + direction * distance_to_edge;
+            cairo.move_to(start.x, start.y);
+            cairo.line_to(destination_centre.x, destination_centre.y);
+            cairo.stroke()?;
+        }
+    }
+
+    add_shape_to_path(
+        cairo,
+        destination.x,
+        destination.y,
+        destination.w,
+        destination.h,
+        is_ellipse,
+    )?;
+    cairo.save()?;
+    cairo.clip_preserve();
+    cairo.translate(destination.x, destination.y);
+    cairo.scale(zoom, zoom);
+    cairo.set_source_surface(surface, -source.x, -source.y)?;
+    cairo.paint()?;
+    cairo.restore()?;
+    cairo.stroke()?;
+    cairo.restore()?;
+
+    Ok(())
+}
+
 fn draw_ellipse(
     cairo: &Context,
     ellipse: &Ellipse,
@@ -412,29 +1319,97 @@ fn draw_line(
     Ok(())
 }
 
-fn draw_arrow(cairo: &Context, start: Point, end: Point, colour: Colour) -> Result<(), Error> {
-    let angle = get_line_angle(start, end);
-    let length = (end.to_owned() - start.to_owned()).dist();
-    let arrow_length = length * ARROWHEAD_LENGTH_RATIO;
-
-    cairo.move_to(start.x, start.y);
-    cairo.line_to(end.x, end.y);
-
-    // Since cos(theta) = adjacent / hypothenuse, x1 = arrow_length * cos(theta)
-    let x1 = -arrow_length * (angle - ARROWHEAD_APERTURE).cos();
-    let x2 = -arrow_length * (angle + ARROWHEAD_APERTURE).cos();
+/// Draws an arrow from `start` to `end`, which bends towards `control` if there is one
+#[allow(clippy::too_many_arguments)]
+fn draw_arrow(
+    cairo: &Context,
+    start: Point,
+    end: Point,
+    control: Option<Point>,
+    colour: Colour,
+    width: f64,
+    head: ArrowHead,
+    is_double: bool,
+) -> Result<(), Error> {
+    if (end - start).dist() == 0.0 {
+        // Clicks make arrows without a direction, there's nothing to point with them
+        return Ok(());
+    }
 
-    // Since sin(theta) = opposite / hypothenuse, y1 = arrow_length * sin(theta)
-    let y1 = -arrow_length * (angle - ARROWHEAD_APERTURE).sin();
-    let y2 = -arrow_length * (angle + ARROWHEAD_APERTURE).sin();
+    let head_length = (width * ARROWHEAD_LENGTH_RATIO).max(ARROWHEAD_MIN_LENGTH);
+    // The heads point along the curve where they are, which is towards the control point
+    let (end_angle, start_angle) = match control {
+        Some(control) => (get_line_angle(control, end), get_line_angle(control, start)),
+        None => (get_line_angle(start, end), get_line_angle(end, start)),
+    };
 
-    cairo.rel_move_to(x1, y1);
-    cairo.line_to(end.x, end.y);
-    cairo.rel_line_to(x2, y2);
+    // Filled heads cover the end of the line, except for its corners, so the line stops where
+    // the heads start
+    let inset = match head {
+        ArrowHead::Open => 0.0,
+        ArrowHead::Filled => head_length * ARROWHEAD_APERTURE.cos(),
+    };
+    let line_end = end - Point::from((end_angle.cos(), end_angle.sin())) * inset;
+    let line_start = if is_double {
+        start - Point::from((start_angle.cos(), start_angle.sin())) * inset
+    } else {
+        start
+    };
 
+    cairo.save()?;
     cairo.set_source_colour(colour);
+    cairo.set_line_width(width);
+    cairo.move_to(line_start.x, line_start.y);
+    match control {
+        // Cairo only has cubic curves, this is the cubic curve which is the same as the quadratic
+        // one
+        Some(control) => {
+            let first = line_start + (control - line_start) * (2.0 / 3.0);
+            let second = line_end + (control - line_end) * (2.0 / 3.0);
+            cairo.curve_to(first.x, first.y, second.x, second.y, line_end.x, line_end.y);
+        }
+        None => cairo.line_to(line_end.x, line_end.y),
+    }
     cairo.stroke()?;
 
+    draw_arrowhead(cairo, end, end_angle, head_length, head)?;
+    if is_double {
+        draw_arrowhead(cairo, start, start_angle, head_length, head)?;
+    }
+    cairo.restore()?;
+
+    Ok(())
+}
+
+/// Draws an arrowhead whose tip is at `tip` and which points in the direction of `angle`, with
+/// the source and line width that are set
+fn draw_arrowhead(
+    cairo: &Context,
+    tip: Point,
+    angle: f64,
+    length: f64,
+    head: ArrowHead,
+) -> Result<(), Error> {
+    // Since cos(theta) = adjacent / hypothenuse, x1 = length * cos(theta), and likewise for sin
+    let x1 = -length * (angle - ARROWHEAD_APERTURE).cos();
+    let y1 = -length * (angle - ARROWHEAD_APERTURE).sin();
+    let x2 = -length * (angle + ARROWHEAD_APERTURE).cos();
+    let y2 = -length * (angle + ARROWHEAD_APERTURE).sin();
+
+    cairo.move_to(tip.x + x1, tip.y + y1);
+    cairo.line_to(tip.x, tip.y);
+    cairo.line_to(tip.x + x2, tip.y + y2);
+    match head {
+        ArrowHead::Open => {
+            cairo.set_line_join(cairo::LineJoin::Miter);
+            cairo.stroke()?;
+        }
+        ArrowHead::Filled => {
+            cairo.close_path();
+            cairo.fill()?;
+        }
+    }
+
     Ok(())
 }
 
@@ -443,128 +1418,216 @@ fn get_line_angle(start: Point, end: Point) -> f64 {
     y.atan2(x)
 }
 
-fn draw_text_at(
+/// Makes a layout for `text`, which is treated as Pango markup if `is_markup` is set. Text whose
+/// markup is invalid is shown as it is, tags and all.
+fn text_layout(
     cairo: &Context,
-    Point { x, y }: Point,
     text: &str,
-    colour: Colour,
     font_description: &FontDescription,
-) -> Result<(), Error> {
+    is_markup: bool,
+) -> pango::Layout {
     let layout = pangocairo::create_layout(cairo).unwrap();
-
-    layout.set_markup(text);
+    set_layout_text(&layout, text, is_markup);
     layout.set_font_description(Some(font_description));
-    cairo.move_to(x, y);
-    cairo.set_source_colour(colour);
     pangocairo::update_layout(cairo, &layout);
-    pangocairo::show_layout(cairo, &layout);
-    Ok(())
+
+    layout
 }
 
-fn draw_text_centred_at(
+fn set_layout_text(layout: &pango::Layout, text: &str, is_markup: bool) {
+    if is_markup && pango::parse_markup(text, '\0').is_ok() {
+        layout.set_markup(text);
+    } else {
+        layout.set_text(text);
+    }
+}
+
+fn draw_text_at(
     cairo: &Context,
     Point { x, y }: Point,
-    text: &str,
+    layout: &pango::Layout,
     colour: Colour,
-    font_description: &FontDescription,
+    background: Option<Colour>,
+    outline: Option<Colour>,
 ) -> Result<(), Error> {
-    let layout = pangocairo::create_layout(cairo).unwrap();
+    cairo.save()?;
 
-    layout.set_markup(text);
-    layout.set_font_description(Some(font_description));
+    if let Some(background) = background {
+        let (_, extents) = layout.pixel_extents();
+        cairo.rectangle(
+            x + extents.x as f64 - TEXT_BOX_PADDING,
+            y + extents.y as f64 - TEXT_BOX_PADDING,
+            extents.width as f64 + 2.0 * TEXT_BOX_PADDING,
+            extents.height as f64 + 2.0 * TEXT_BOX_PADDING,
+        );
+        cairo.set_source_colour(background);
+        cairo.fill()?;
+    }
 
-    let pixel_extents = layout.pixel_extents().1;
-    let w = pixel_extents.width as f64;
-    let h = pixel_extents.height as f64;
+    if let Some(outline) = outline {
+        let font_size = layout
+            .font_description()
+            .map(|font_description| font_description.size() as f64 / pango::SCALE as f64)
+            .unwrap_or(10.0);
+        cairo.move_to(x, y);
+        pangocairo::layout_path(cairo, layout);
+        cairo.set_source_colour(outline);
+        cairo.set_line_width((font_size * TEXT_OUTLINE_RATIO).max(1.0));
+        cairo.set_line_join(cairo::LineJoin::Round);
+        cairo.stroke()?;
+    }
 
-    cairo.move_to(x - w / 2.0, y - h / 2.0);
+    cairo.move_to(x, y);
     cairo.set_source_colour(colour);
-    pangocairo::update_layout(cairo, &layout);
-    pangocairo::show_layout(cairo, &layout);
+    pangocairo::show_layout(cairo, layout);
+    cairo.restore()?;
+
     Ok(())
 }
 
-fn blur(cairo: &Context, pixbuf: Pixbuf, sigma: f32, Point { x, y }: Point) -> Result<(), Error> {
-    let flat_samples = FlatSamples {
-        samples: pixbuf.pixel_bytes().ok_or(Error::PixelBytes)?.to_vec(),
-        layout: SampleLayout {
-            channels: pixbuf.n_channels() as u8,
-            channel_stride: 1,
-            width: pixbuf.width() as u32,
-            width_stride: 3,
-            height: pixbuf.height() as u32,
-            height_stride: pixbuf.rowstride() as usize,
-        },
-        color_hint: None,
+/// Draws a callout whose text starts at `top_left`, the tail goes from the middle of the box to
+/// `target` and is hidden by the box if `target` is inside it
+fn draw_callout(
+    cairo: &Context,
+    target: Point,
+    top_left: Point,
+    layout: &pango::Layout,
+    fill: Colour,
+    border: Colour,
+) -> Result<(), Error> {
+    let (_, extents) = layout.pixel_extents();
+    let rect = Rectangle {
+        x: top_left.x + extents.x as f64 - CALLOUT_PADDING,
+        y: top_left.y + extents.y as f64 - CALLOUT_PADDING,
+        w: extents.width as f64 + 2.0 * CALLOUT_PADDING,
+        h: extents.height as f64 + 2.0 * CALLOUT_PADDING,
     };
-    let image = flat_samples.as_view::<Rgb<u8>>()?;
-    let mut blurred_image = imageops::blur(&image, sigma);
-    let width = blurred_image.width() as i32;
-    let height = blurred_image.height() as i32;
-    let blurred_flat_samples = blurred_image.as_flat_samples_mut();
-
-    let blurred_pixbuf = Pixbuf::from_mut_slice(
-        blurred_flat_samples.samples,
-        Colorspace::Rgb,
-        false,
-        8,
-        width,
-        height,
-        blurred_flat_samples.layout.height_stride as i32,
-    );
 
     cairo.save()?;
-    cairo.set_operator(cairo::Operator::Over);
-    cairo.set_source_pixbuf(&blurred_pixbuf, x, y);
+    // The border is stroked twice as wide as it should be and the inner half is then covered by
+    // the fill, which also covers where the box and the tail overlap. It's done in a group so
+    // that translucent fills replace the border instead of showing it through.
+    cairo.push_group();
+    add_callout_to_path(cairo, &rect, target);
+    cairo.set_source_colour(border);
+    cairo.set_line_width(2.0 * CALLOUT_BORDER_WIDTH);
+    cairo.set_line_join(cairo::LineJoin::Round);
+    cairo.stroke()?;
+
+    cairo.set_operator(cairo::Operator::Source);
+    cairo.set_source_colour(fill);
+    add_callout_to_path(cairo, &rect, target);
+    // The tail and the box may go around in different directions, so their overlap would be a
+    // hole with the winding fill rule
+    cairo.set_fill_rule(cairo::FillRule::EvenOdd);
+    cairo.fill()?;
+    add_rounded_rectangle_to_path(cairo, &rect);
+    cairo.fill()?;
+
+    cairo.pop_group_to_source()?;
     cairo.paint()?;
     cairo.restore()?;
 
-    Ok(())
+    draw_text_at(cairo, top_left, layout, border, None, None)
 }
 
-fn pixelate(
-    cairo: &Context,
-    pixbuf: Pixbuf,
-    &Rectangle { x, y, w, h }: &Rectangle,
-    seed: u64,
-) -> Result<(), Error> {
-    let mut rng = StdRng::seed_from_u64(seed);
+/// Adds the box of a callout and its tail to the current path, as separate sub-paths
+fn add_callout_to_path(cairo: &Context, rect: &Rectangle, target: Point) {
+    add_rounded_rectangle_to_path(cairo, rect);
 
-    let rowstride = pixbuf.rowstride() as u64;
-    let bytes_per_pixel = 3 * (pixbuf.bits_per_sample() / 8) as u64;
-    let pixels = unsafe { pixbuf.pixels() };
+    let centre = Point {
+        x: rect.x + rect.w / 2.0,
+        y: rect.y + rect.h / 2.0,
+    };
+    let direction = target - centre;
+    let length = direction.dist();
+    if length == 0.0 {
+        return;
+    }
+    // The base of the tail is perpendicular to it, and no wider than the box
+    let half_width = CALLOUT_TAIL_WIDTH.min(rect.w).min(rect.h) / 2.0;
+    let across = Point {
+        x: -direction.y / length,
+        y: direction.x / length,
+    } * half_width;
 
-    for i in (0..(w as u64)).step_by(PIXELATE_SIZE as usize) {
-        for j in (0..(h as u64)).step_by(PIXELATE_SIZE as usize) {
-            let pixelate_size_x: u64 = PIXELATE_SIZE.min(w as u64 - i);
-            let pixelate_size_y: u64 = PIXELATE_SIZE.min(h as u64 - j);
+    let base_start = centre + across;
+    let base_end = centre - across;
+    cairo.new_sub_path();
+    cairo.move_to(base_start.x, base_start.y);
+    cairo.line_to(target.x, target.y);
+    cairo.line_to(base_end.x, base_end.y);
+    cairo.close_path();
+}
 
-            let sample_x: u64 = i + rng.gen_range(0..pixelate_size_x);
-            let sample_y: u64 = j + rng.gen_range(0..pixelate_size_y);
+fn add_rounded_rectangle_to_path(cairo: &Context, &Rectangle { x, y, w, h }: &Rectangle) {
+    let radius = CALLOUT_CORNER_RADIUS.min(w / 2.0).min(h / 2.0);
 
-            // Note that we don't multiply sample_y by bytes_per_pixel since its size is contained inside rowstride
-            let row_index = sample_y * rowstride + bytes_per_pixel * sample_x;
-            let mut sample = Vec::with_capacity(bytes_per_pixel as usize);
-            for k in 0..bytes_per_pixel as usize {
-                sample.push(pixels[row_index as usize + k]);
-            }
+    cairo.new_sub_path();
+    cairo.arc(x + w - radius, y + radius, radius, -PI / 2.0, 0.0);
+    cairo.arc(x + w - radius, y + h - radius, radius, 0.0, PI / 2.0);
+    cairo.arc(x + radius, y + h - radius, radius, PI / 2.0, PI);
+    cairo.arc(x + radius, y + radius, radius, PI, 3.0 * PI / 2.0);
+    cairo.close_path();
+}
 
-            for pixel_x in i..(i + pixelate_size_x) {
-                for pixel_y in j..(j + pixelate_size_y) {
-                    let row_index = pixel_y * rowstride + bytes_per_pixel * pixel_x;
-                    for k in 0..bytes_per_pixel {
-                        pixels[(row_index + k) as usize] = sample[k as usize];
-                    }
-                }
-            }
-        }
-    }
+/// Draws a bubble around the number which `layout` has, centred on `centre`
+fn draw_bubble(
+    cairo: &Context,
+    centre: Point,
+    layout: &pango::Layout,
+    bubble_colour: Colour,
+    text_colour: Colour,
+    is_square: bool,
+    outline: bool,
+) -> Result<(), Error> {
+    let radius = bubble_radius(layout, is_square);
 
     cairo.save()?;
-    cairo.set_operator(cairo::Operator::Over);
-    cairo.set_source_pixbuf(&pixbuf, x, y);
-    cairo.paint()?;
+    add_shape_to_path(
+        cairo,
+        centre.x - radius,
+        centre.y - radius,
+        2.0 * radius,
+        2.0 * radius,
+        !is_square,
+    )?;
+    cairo.set_source_colour(bubble_colour);
+    if outline {
+        cairo.fill_preserve()?;
+        cairo.set_source_colour(text_colour);
+        cairo.set_line_width(BUBBLE_OUTLINE_WIDTH);
+        cairo.stroke()?;
+    } else {
+        cairo.fill()?;
+    }
+
+    let (_, extents) = layout.pixel_extents();
+    cairo.move_to(
+        centre.x - extents.x as f64 - extents.width as f64 / 2.0,
+        centre.y - extents.y as f64 - extents.height as f64 / 2.0,
+    );
+    cairo.set_source_colour(text_colour);
+    pangocairo::show_layout(cairo, layout);
     cairo.restore()?;
 
     Ok(())
 }
+
+/// How far the edges of a bubble around the number which `layout` has are from its centre
+fn bubble_radius(layout: &pango::Layout, is_square: bool) -> f64 {
+    let (_, extents) = layout.pixel_extents();
+    let (width, height) = (extents.width as f64, extents.height as f64);
+    if is_square {
+        width.max(height) / 2.0 + BUBBLE_PADDING
+    } else {
+        width.hypot(height) / 2.0
+    }
+}
+
+/// Makes a layout which isn't drawn anywhere, to find out how big things drawn with it would be
+fn measuring_layout() -> Option<pango::Layout> {
+    pangocairo::FontMap::default()
+        .and_then(|map| map.create_context())
+        .map(|context| pango::Layout::new(&context))
+}
@@ -0,0 +1,541 @@
+//! Effects which change the pixels of the image instead of drawing over them. They work on a copy
+//! of the region they affect, in cairo's ARGB32 format, whatever format the image itself has.
+//!
+//! ARGB32 pixels are 4 bytes wide and premultiplied by their alpha, so every channel can be
+//! treated the same way and the order of the bytes doesn't matter to us.
+
+use std::{cell::RefCell, fmt};
+
+use cairo::{Context, Format, ImageSurface, Operator};
+use rand::{prelude::StdRng, Rng, SeedableRng};
+
+use super::{Colour, Error, Rectangle};
+
+const BYTES_PER_PIXEL: usize = 4;
+/// How different the luminance of neighbouring pixels has to be for there to be an edge between
+/// them, the edges of text are sharp so they are well above this
+const EDGE_THRESHOLD: i32 = 96;
+/// How many of the pairs of neighbouring pixels of a region need to have an edge between them for
+/// the region to look like it contains text
+const TEXT_EDGE_RATIO: f64 = 0.05;
+
+/// A copy of the part of an image that an effect applies to
+pub struct Region {
+    surface: ImageSurface,
+    x: i32,
+    y: i32,
+}
+
+/// Keeps the region an effect made last, along with the `K` it was made from, so that drawing the
+/// effect again only makes the region again once that changed. Clones start out empty.
+pub struct Cache<K>(RefCell<Option<(K, Option<Region>)>>);
+
+impl<K: PartialEq> Cache<K> {
+    /// Paints the region which was made for `key`, it's made with `make` first unless the last
+    /// one was made for the same `key`
+    pub fn paint(
+        &self,
+        cairo: &Context,
+        key: K,
+        make: impl FnOnce() -> Result<Option<Region>, Error>,
+    ) -> Result<(), Error> {
+        let mut cached = self.0.borrow_mut();
+        if !matches!(&*cached, Some((cached_key, _)) if *cached_key == key) {
+            *cached = Some((key, make()?));
+        }
+
+        match &*cached {
+            Some((_, Some(region))) => region.paint(cairo),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<K> Default for Cache<K> {
+    fn default() -> Self {
+        Self(RefCell::new(None))
+    }
+}
+
+impl<K> Clone for Cache<K> {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+impl<K> fmt::Debug for Cache<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Cache")
+    }
+}
+
+impl Region {
+    /// Makes a transparent region which covers the part of `surface` that `rect` covers, `rect`
+    /// gets clamped to the bounds of `surface`. Returns `None` if none of `rect` is on `surface`.
+    pub fn empty(surface: &ImageSurface, mut rect: Rectangle) -> Result<Option<Self>, Error> {
+        rect.normalise();
+        let x = (rect.x.floor() as i32).max(0);
+        let y = (rect.y.floor() as i32).max(0);
+        let right = ((rect.x + rect.w).ceil() as i32).min(surface.width());
+        let bottom = ((rect.y + rect.h).ceil() as i32).min(surface.height());
+        if right <= x || bottom <= y {
+            return Ok(None);
+        }
+
+        Ok(Some(Self {
+            surface: ImageSurface::create(Format::ARgb32, right - x, bottom - y)?,
+            x,
+            y,
+        }))
+    }
+
+    /// Copies the part of `surface` which `rect` covers, like [`Region::empty`] but with the
+    /// contents of `surface`
+    pub fn copy(surface: &ImageSurface, rect: Rectangle) -> Result<Option<Self>, Error> {
+        let region = match Self::empty(surface, rect)? {
+            Some(region) => region,
+            None => return Ok(None),
+        };
+
+        {
+            let cairo = Context::new(&region.surface)?;
+            cairo.set_source_surface(surface, -region.x as f64, -region.y as f64)?;
+            cairo.set_operator(Operator::Source);
+            cairo.paint()?;
+        }
+        region.surface.flush();
+
+        Ok(Some(region))
+    }
+
+    /// Blurs the region with a gaussian blur whose standard deviation is `sigma`
+    pub fn blur(&mut self, sigma: f32) -> Result<(), Error> {
+        if sigma <= 0.0 {
+            return Ok(());
+        }
+
+        let kernel = gaussian_kernel(sigma);
+        let (width, height, stride) = self.dimensions();
+        let mut pixels = self.surface.data()?;
+
+        let mut row = vec![0.0; width * BYTES_PER_PIXEL];
+        for y in 0..height {
+            let row_pixels = &mut pixels[y * stride..];
+            for x in 0..width {
+                convolve(&kernel, &mut row[x * BYTES_PER_PIXEL..], |offset| {
+                    let x = clamp(x as isize + offset, width);
+                    &row_pixels[x * BYTES_PER_PIXEL..]
+                });
+            }
+            store(&row, &mut row_pixels[..width * BYTES_PER_PIXEL]);
+        }
+
+        let mut column = vec![0.0; height * BYTES_PER_PIXEL];
+        for x in 0..width {
+            for y in 0..height {
+                convolve(&kernel, &mut column[y * BYTES_PER_PIXEL..], |offset| {
+                    let y = clamp(y as isize + offset, height);
+                    &pixels[y * stride + x * BYTES_PER_PIXEL..]
+                });
+            }
+            for y in 0..height {
+                let pixel = y * stride + x * BYTES_PER_PIXEL;
+                store(
+                    &column[y * BYTES_PER_PIXEL..(y + 1) * BYTES_PER_PIXEL],
+                    &mut pixels[pixel..pixel + BYTES_PER_PIXEL],
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Splits the region into `size`x`size` boxes and gives every pixel of a box the value of a
+    /// pixel of that box picked at random, the boxes at the right and bottom edges may be
+    /// smaller. The same `seed` always picks the same pixels.
+    pub fn pixelate(&mut self, size: usize, seed: u64) -> Result<(), Error> {
+        let size = size.max(1);
+        let mut rng = StdRng::seed_from_u64(seed);
+        let (width, height, stride) = self.dimensions();
+        let mut pixels = self.surface.data()?;
+
+        for box_x in (0..width).step_by(size) {
+            for box_y in (0..height).step_by(size) {
+                let box_width = size.min(width - box_x);
+                let box_height = size.min(height - box_y);
+
+                let sample_x = box_x + rng.gen_range(0..box_width);
+                let sample_y = box_y + rng.gen_range(0..box_height);
+                let sample_index = sample_y * stride + sample_x * BYTES_PER_PIXEL;
+                let mut sample = [0; BYTES_PER_PIXEL];
+                sample.copy_from_slice(&pixels[sample_index..sample_index + BYTES_PER_PIXEL]);
+
+                for y in box_y..box_y + box_height {
+                    let row = &mut pixels[y * stride..];
+                    for pixel in row[box_x * BYTES_PER_PIXEL..(box_x + box_width) * BYTES_PER_PIXEL]
+                        .chunks_exact_mut(BYTES_PER_PIXEL)
+                    {
+                        pixel.copy_from_slice(&sample);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Fills the whole region with `colour`, which is made opaque so that nothing shows through
+    pub fn fill(&mut self, colour: Colour) -> Result<(), Error> {
+        let Colour {
+            red, green, blue, ..
+        } = colour;
+        let pixel = u32::from_be_bytes([0xff, red, green, blue]).to_ne_bytes();
+
+        self.fill_with(|| pixel)
+    }
+
+    /// Fills the whole region with grey noise, the same `seed` always makes the same noise
+    pub fn fill_with_noise(&mut self, seed: u64) -> Result<(), Error> {
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        self.fill_with(|| {
+            let grey = rng.gen();
+            u32::from_be_bytes([0xff, grey, grey, grey]).to_ne_bytes()
+        })
+    }
+
+    /// Guesses whether the region contains text by how many sharp edges it has
+    pub fn looks_like_text(&mut self) -> Result<bool, Error> {
+        let (width, height, stride) = self.dimensions();
+        if width < 2 {
+            return Ok(false);
+        }
+
+        let pixels = self.surface.data()?;
+        let mut edges = 0;
+        for y in 0..height {
+            let row = &pixels[y * stride..y * stride + width * BYTES_PER_PIXEL];
+            let luminances = row.chunks_exact(BYTES_PER_PIXEL).map(luminance);
+            edges += luminances
+                .clone()
+                .zip(luminances.skip(1))
+                .filter(|(left, right)| (left - right).abs() > EDGE_THRESHOLD)
+                .count();
+        }
+
+        Ok(edges as f64 / ((width - 1) * height) as f64 > TEXT_EDGE_RATIO)
+    }
+
+    /// Replaces every pixel with what `next_pixel` returns, going row by row
+    fn fill_with(
+        &mut self,
+        mut next_pixel: impl FnMut() -> [u8; BYTES_PER_PIXEL],
+    ) -> Result<(), Error> {
+        let (width, height, stride) = self.dimensions();
+        let mut pixels = self.surface.data()?;
+
+        for y in 0..height {
+            let row = &mut pixels[y * stride..y * stride + width * BYTES_PER_PIXEL];
+            for pixel in row.chunks_exact_mut(BYTES_PER_PIXEL) {
+                pixel.copy_from_slice(&next_pixel());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Puts the region back where it was copied from, replacing what was there
+    pub fn paint(&self, cairo: &Context) -> Result<(), Error> {
+        cairo.save()?;
+        cairo.set_source_surface(&self.surface, self.x as f64, self.y as f64)?;
+        cairo.set_operator(Operator::Source);
+        cairo.rectangle(
+            self.x as f64,
+            self.y as f64,
+            self.surface.width() as f64,
+            self.surface.height() as f64,
+        );
+        cairo.fill()?;
+        cairo.restore()?;
+
+        Ok(())
+    }
+
+    /// The width and height of the region in pixels, and the length of its rows in bytes
+    fn dimensions(&self) -> (usize, usize, usize) {
+        (
+            self.surface.width() as usize,
+            self.surface.height() as usize,
+            self.surface.stride() as usize,
+        )
+    }
+}
+
+/// Makes a normalised gaussian kernel which goes out to 3 standard deviations on either side,
+/// past that the weights are too small to matter
+fn gaussian_kernel(sigma: f32) -> Vec<f32> {
+    let radius = (3.0 * sigma).ceil() as isize;
+    let kernel = (-radius..=radius)
+        .map(|offset| (-((offset * offset) as f32) / (2.0 * sigma * sigma)).exp())
+        .collect::<Vec<_>>();
+    let sum = kernel.iter().sum::<f32>();
+
+    kernel.into_iter().map(|weight| weight / sum).collect()
+}
+
+/// Sums the pixels around a pixel weighted by `kernel` into `out`, `pixel_at` returns the bytes
+/// starting with the pixel at an offset from the pixel in the middle of the kernel
+fn convolve<'a>(kernel: &[f32], out: &mut [f32], pixel_at: impl Fn(isize) -> &'a [u8]) {
+    let radius = (kernel.len() / 2) as isize;
+    let out = &mut out[..BYTES_PER_PIXEL];
+    out.iter_mut().for_each(|channel| *channel = 0.0);
+
+    for (offset, weight) in (-radius..=radius).zip(kernel) {
+        let pixel = pixel_at(offset);
+        for (channel, value) in out.iter_mut().zip(&pixel[..BYTES_PER_PIXEL]) {
+            *channel += weight * *value as f32;
+        }
+    }
+}
+
+fn store(channels: &[f32], pixels: &mut [u8]) {
+    for (pixel, channel) in pixels.iter_mut().zip(channels) {
+        *pixel = channel.round().clamp(0.0, 255.0) as u8;
+    }
+}
+
+/// The luminance of an ARGB32 pixel, from 0 to 255 times its alpha
+fn luminance(pixel: &[u8]) -> i32 {
+    let [_, red, green, blue] =
+        u32::from_ne_bytes([pixel[0], pixel[1], pixel[2], pixel[3]]).to_be_bytes();
+
+    (299 * red as i32 + 587 * green as i32 + 114 * blue as i32) / 1000
+}
+
+/// Clamps `index` to `0..len`, so that the pixels at the edges get repeated past them
+fn clamp(index: isize, len: usize) -> usize {
+    index.clamp(0, len as isize - 1) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// How many bytes of padding the surfaces made by [`padded_surface`] have after every row
+    const PADDING: usize = 8;
+    const PADDING_BYTE: u8 = 0xab;
+
+    fn pixel(alpha: u8, red: u8, green: u8, blue: u8) -> [u8; BYTES_PER_PIXEL] {
+        u32::from_be_bytes([alpha, red, green, blue]).to_ne_bytes()
+    }
+
+    fn grey(value: u8) -> [u8; BYTES_PER_PIXEL] {
+        pixel(0xff, value, value, value)
+    }
+
+    /// Makes an ARGB32 surface whose rows are longer than its pixels, with the padding filled with
+    /// [`PADDING_BYTE`]
+    fn padded_surface(
+        width: usize,
+        height: usize,
+        pixel_at: impl Fn(usize, usize) -> [u8; BYTES_PER_PIXEL],
+    ) -> ImageSurface {
+        let stride = width * BYTES_PER_PIXEL + PADDING;
+        let mut data = vec![PADDING_BYTE; stride * height];
+        for y in 0..height {
+            for x in 0..width {
+                let index = y * stride + x * BYTES_PER_PIXEL;
+                data[index..index + BYTES_PER_PIXEL].copy_from_slice(&pixel_at(x, y));
+            }
+        }
+
+        ImageSurface::create_for_data(
+            data,
+            Format::ARgb32,
+            width as i32,
+            height as i32,
+            stride as i32,
+        )
+        .unwrap()
+    }
+
+    fn padded_region(
+        width: usize,
+        height: usize,
+        pixel_at: impl Fn(usize, usize) -> [u8; BYTES_PER_PIXEL],
+    ) -> Region {
+        Region {
+            surface: padded_surface(width, height, pixel_at),
+            x: 0,
+            y: 0,
+        }
+    }
+
+    /// The pixels of the region row by row, without the padding
+    fn pixels_of(region: &mut Region) -> Vec<[u8; BYTES_PER_PIXEL]> {
+        let (width, height, stride) = region.dimensions();
+        let data = region.surface.data().unwrap();
+        (0..height)
+            .flat_map(|y| (0..width).map(move |x| y * stride + x * BYTES_PER_PIXEL))
+            .map(|index| {
+                let mut pixel = [0; BYTES_PER_PIXEL];
+                pixel.copy_from_slice(&data[index..index + BYTES_PER_PIXEL]);
+                pixel
+            })
+            .collect()
+    }
+
+    fn assert_padding_untouched(region: &mut Region) {
+        let (width, height, stride) = region.dimensions();
+        let data = region.surface.data().unwrap();
+        for y in 0..height {
+            let padding = &data[y * stride + width * BYTES_PER_PIXEL..(y + 1) * stride];
+            assert!(padding.iter().all(|&byte| byte == PADDING_BYTE));
+        }
+    }
+
+    /// A pixel whose channels tell where it is
+    fn numbered(x: usize, y: usize) -> [u8; BYTES_PER_PIXEL] {
+        pixel(0xff, (x * 16) as u8, (y * 16) as u8, (x + y) as u8)
+    }
+
+    fn rect(x: f64, y: f64, w: f64, h: f64) -> Rectangle {
+        Rectangle { x, y, w, h }
+    }
+
+    #[test]
+    fn copy_reads_padded_surfaces() {
+        let surface = padded_surface(5, 3, numbered);
+        let mut region = Region::copy(&surface, rect(1.0, 1.0, 3.0, 2.0))
+            .unwrap()
+            .unwrap();
+
+        assert_eq!((region.x, region.y), (1, 1));
+        assert_eq!(region.dimensions().0, 3);
+        assert_eq!(region.dimensions().1, 2);
+        let expected = (1..3)
+            .flat_map(|y| (1..4).map(move |x| numbered(x, y)))
+            .collect::<Vec<_>>();
+        assert_eq!(pixels_of(&mut region), expected);
+    }
+
+    #[test]
+    fn copy_clamps_rectangles_which_hang_off_every_edge() {
+        let surface = padded_surface(4, 3, numbered);
+        let mut region = Region::copy(&surface, rect(-2.0, -1.0, 10.0, 10.0))
+            .unwrap()
+            .unwrap();
+
+        assert_eq!((region.x, region.y), (0, 0));
+        assert_eq!(region.dimensions().0, 4);
+        assert_eq!(region.dimensions().1, 3);
+        let expected = (0..3)
+            .flat_map(|y| (0..4).map(move |x| numbered(x, y)))
+            .collect::<Vec<_>>();
+        assert_eq!(pixels_of(&mut region), expected);
+    }
+
+    #[test]
+    fn copy_clamps_rectangles_drawn_backwards() {
+        let surface = padded_surface(4, 3, numbered);
+        let mut region = Region::copy(&surface, rect(5.0, 5.0, -3.0, -3.5))
+            .unwrap()
+            .unwrap();
+
+        assert_eq!((region.x, region.y), (2, 1));
+        assert_eq!(
+            pixels_of(&mut region),
+            vec![
+                numbered(2, 1),
+                numbered(3, 1),
+                numbered(2, 2),
+                numbered(3, 2)
+            ]
+        );
+    }
+
+    #[test]
+    fn copy_of_rectangles_off_the_surface_is_none() {
+        let surface = padded_surface(4, 3, numbered);
+
+        for off_surface in [
+            rect(4.0, 0.0, 2.0, 2.0),
+            rect(0.0, 3.0, 2.0, 2.0),
+            rect(-3.0, 0.0, 2.0, 2.0),
+            rect(0.0, -3.0, 2.0, 2.0),
+            rect(10.0, 10.0, 5.0, 5.0),
+        ] {
+            assert!(Region::copy(&surface, off_surface).unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn pixelate_fills_each_box_with_one_of_its_pixels() {
+        // Which pixel gets picked depends on the seed, so try a few
+        for seed in 0..8 {
+            let mut region = padded_region(5, 4, numbered);
+            region.pixelate(2, seed).unwrap();
+            let pixels = pixels_of(&mut region);
+
+            // The boxes at the right edge are 1 pixel wide
+            for (box_x, box_y) in [(0, 0), (2, 0), (4, 0), (0, 2), (2, 2), (4, 2)] {
+                let in_box = (box_y..box_y + 2)
+                    .flat_map(|y| (box_x..(box_x + 2).min(5)).map(move |x| (x, y)))
+                    .collect::<Vec<_>>();
+                let colour = pixels[box_y * 5 + box_x];
+
+                assert!(
+                    in_box.iter().all(|&(x, y)| pixels[y * 5 + x] == colour),
+                    "seed {}, box at {:?}",
+                    seed,
+                    (box_x, box_y)
+                );
+                assert!(
+                    in_box.iter().any(|&(x, y)| numbered(x, y) == colour),
+                    "seed {}, box at {:?}",
+                    seed,
+                    (box_x, box_y)
+                );
+            }
+            assert_padding_untouched(&mut region);
+        }
+    }
+
+    #[test]
+    fn blur_matches_reference() {
+        // Black on the left, white on the right
+        let mut region = padded_region(8, 3, |x, _| grey(if x < 4 { 0 } else { 255 }));
+        region.blur(1.0).unwrap();
+
+        for (row, pixels) in pixels_of(&mut region).chunks(8).enumerate() {
+            for (pixel, expected) in pixels.iter().zip(BLUR_REFERENCE) {
+                let [alpha, red, green, blue] = u32::from_ne_bytes(*pixel).to_be_bytes();
+                assert_eq!(alpha, 0xff, "row {}", row);
+                assert!(
+                    (red as i32 - expected).abs() <= 1,
+                    "row {}: {:?}",
+                    row,
+                    pixels
+                );
+                assert_eq!((red, red), (green, blue), "row {}", row);
+            }
+        }
+        assert_padding_untouched(&mut region);
+    }
+
+    #[test]
+    fn blur_repeats_the_edges() {
+        // Nothing transparent from outside the region may bleed in
+        let mut region = padded_region(3, 3, |_, _| grey(0x80));
+        region.blur(2.0).unwrap();
+
+        assert!(pixels_of(&mut region)
+            .iter()
+            .all(|&pixel| pixel == grey(0x80)));
+        assert_padding_untouched(&mut region);
+    }
+
+    /// A row of 4 black and 4 white pixels blurred with a standard deviation of 1, worked out by
+    /// hand with a gaussian which is cut off at 3 standard deviations
+    const BLUR_REFERENCE: [i32; 8] = [0, 1, 15, 77, 178, 240, 254, 255];
+}
@@ -1,8 +1,12 @@
-use crate::editor::data::Text;
+use std::convert::TryFrom;
 
-use super::{Colour, Operation, Point, Rectangle, Tool};
+use super::{
+    ArrowHead, CaretMovement, Colour, Error, Numbering, Operation, Point, Rectangle, TextStyle,
+    Tool, ToolSettings, ARROW_HANDLE_RADIUS, DEFAULT_FONT, MAX_BLUR_RADIUS,
+};
 
 use cairo::{Context, ImageSurface};
+use gtk4::{gio, pango::FontDescription, prelude::*};
 use tracing::{error, warn};
 
 #[derive(Debug)]
@@ -10,9 +14,42 @@ pub struct OperationStack {
     operations: Vec<Operation>,
     current_tool: Tool,
     current_operation: Option<Operation>,
+    /// The index of the finished operation which is being changed where it is in the stack, it's
+    /// the current operation instead of `current_operation` then
+    edited_index: Option<usize>,
     autoincrement_bubble_number: i32,
     pub primary_colour: Colour,
     pub secondary_colour: Colour,
+    pub tool_settings: ToolSettings,
+    /// The font new text is made with
+    text_font: FontDescription,
+    /// Where the caret is in the text which is being typed, as a byte index into it. Text is only
+    /// being typed when this is set, the text is the current operation then.
+    text_caret: Option<usize>,
+    /// Where the handle of the arrow which is being bent was when the drag which bends it began,
+    /// the current operation is that arrow then
+    bent_arrow_handle: Option<Point>,
+    /// What the last press on the image added, so that it can be taken back when the press turns
+    /// out to be the first click of a double click
+    last_press: LastPress,
+    /// The index of the magnifier whose source was just dragged out, the next press with the
+    /// magnifier tool places its destination
+    unplaced_magnifier: Option<usize>,
+    /// Where the drag which sizes the source or the destination of the current magnifier began
+    magnifier_drag_start: Option<Point>,
+    /// How the project this was loaded from was cropped, it's cropped the same way again unless
+    /// another crop is dragged out
+    project_crop: Option<Rectangle>,
+}
+
+/// What a press on the image added to the operation stack
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum LastPress {
+    Nothing,
+    /// A new operation, which is at this index once it's finished unless it was dropped
+    Created(usize),
+    /// A spot, to the spotlight at this index
+    AddedSpot(usize),
 }
 
 impl OperationStack {
@@ -21,6 +58,7 @@ impl OperationStack {
             operations: vec![],
             current_tool: Tool::CropAndSave,
             current_operation: None,
+            edited_index: None,
             autoincrement_bubble_number: 1,
             primary_colour: Colour {
                 red: 127,
@@ -34,20 +72,305 @@ impl OperationStack {
                 blue: 127,
                 alpha: 255,
             },
+            tool_settings: ToolSettings::default(),
+            text_font: FontDescription::from_string(DEFAULT_FONT),
+            text_caret: None,
+            bent_arrow_handle: None,
+            last_press: LastPress::Nothing,
+            unplaced_magnifier: None,
+            magnifier_drag_start: None,
+            project_crop: None,
+        }
+    }
+
+    /// Creates an operation stack which already contains `operations` and is cropped to `crop`,
+    /// for example the ones that were loaded from a project file
+    pub fn from_operations(operations: Vec<Operation>, crop: Option<Rectangle>) -> Self {
+        let autoincrement_bubble_number = operations
+            .iter()
+            .filter_map(|operation| match operation {
+                Operation::Bubble { number, .. } => Some(number + 1),
+                _ => None,
+            })
+            .max()
+            .unwrap_or(1);
+
+        Self {
+            operations,
+            autoincrement_bubble_number,
+            project_crop: crop,
+            ..Self::new()
+        }
+    }
+
+    /// Returns the operations which annotate the image, that is every finished operation except
+    /// for crops, projects keep the [`OperationStack::crop_region`] on its own
+    pub fn annotations(&self) -> Vec<Operation> {
+        self.operations
+            .iter()
+            .filter(|operation| !matches!(operation, Operation::Crop(_)))
+            .cloned()
+            .collect()
+    }
+
+    /// Restores the tool and colours that were stored by [`OperationStack::save_tool_settings`]
+    pub fn restore_tool_settings(&mut self, settings: &gio::Settings) {
+        let last_tool = settings.int("last-tool");
+        match Tool::try_from(last_tool) {
+            Ok(tool) => self.current_tool = tool,
+            Err(tool) => warn!("Ignoring unknown tool {} stored in 'last-tool'", tool),
+        }
+
+        self.primary_colour = Colour::from_tuple(settings.get("last-primary-colour"));
+        self.secondary_colour = Colour::from_tuple(settings.get("last-secondary-colour"));
+        self.tool_settings = ToolSettings {
+            blur_radius: (settings.double("last-blur-radius") as f32).min(MAX_BLUR_RADIUS),
+            pixelate_size: settings.int("last-pixelate-size").max(1) as usize,
+            redact_with_noise: settings.boolean("last-redact-with-noise"),
+            spotlight_ellipse: settings.boolean("last-spotlight-ellipse"),
+            spotlight_darkness: settings.double("last-spotlight-darkness"),
+            spotlight_blur: settings.boolean("last-spotlight-blur"),
+            magnifier_zoom: settings.double("last-magnifier-zoom"),
+            magnifier_ellipse: settings.boolean("last-magnifier-ellipse"),
+            text_style: TextStyle::from_id(&settings.string("last-text-style"))
+                .unwrap_or(TextStyle::Plain),
+            bubble_size: settings.double("last-bubble-size"),
+            bubble_square: settings.boolean("last-bubble-square"),
+            bubble_outline: settings.boolean("last-bubble-outline"),
+            bubble_numbering: Numbering::from_id(&settings.string("last-bubble-numbering"))
+                .unwrap_or_default(),
+            arrow_width: settings.double("last-arrow-width"),
+            arrow_head: ArrowHead::from_id(&settings.string("last-arrow-head")).unwrap_or_default(),
+            arrow_double: settings.boolean("last-arrow-double"),
+        };
+
+        let text_font = settings.string("last-text-font");
+        if !text_font.is_empty() {
+            self.text_font = FontDescription::from_string(&text_font);
+        }
+    }
+
+    pub fn save_tool_settings(&self, settings: &gio::Settings) {
+        if let Err(why) = settings.set_int("last-tool", self.current_tool as i32) {
+            error!("Failed to save 'last-tool': {}", why);
+        }
+        if let Err(why) = settings.set("last-primary-colour", &self.primary_colour.to_tuple()) {
+            error!("Failed to save 'last-primary-colour': {}", why);
+        }
+        if let Err(why) = settings.set("last-secondary-colour", &self.secondary_colour.to_tuple()) {
+            error!("Failed to save 'last-secondary-colour': {}", why);
+        }
+        let ToolSettings {
+            blur_radius,
+            pixelate_size,
+            redact_with_noise,
+            spotlight_ellipse,
+            spotlight_darkness,
+            spotlight_blur,
+            magnifier_zoom,
+            magnifier_ellipse,
+            text_style,
+            bubble_size,
+            bubble_square,
+            bubble_outline,
+            bubble_numbering,
+            arrow_width,
+            arrow_head,
+            arrow_double,
+        } = self.tool_settings;
+        if let Err(why) = settings.set_double("last-blur-radius", blur_radius as f64) {
+            error!("Failed to save 'last-blur-radius': {}", why);
+        }
+        if let Err(why) = settings.set_int("last-pixelate-size", pixelate_size as i32) {
+            error!("Failed to save 'last-pixelate-size': {}", why);
+        }
+        if let Err(why) = settings.set_boolean("last-redact-with-noise", redact_with_noise) {
+            error!("Failed to save 'last-redact-with-noise': {}", why);
+        }
+        if let Err(why) = settings.set_boolean("last-spotlight-ellipse", spotlight_ellipse) {
+            error!("Failed to save 'last-spotlight-ellipse': {}", why);
+        }
+        if let Err(why) = settings.set_double("last-spotlight-darkness", spotlight_darkness) {
+            error!("Failed to save 'last-spotlight-darkness': {}", why);
+        }
+        if let Err(why) = settings.set_boolean("last-spotlight-blur", spotlight_blur) {
+            error!("Failed to save 'last-spotlight-blur': {}", why);
+        }
+        if let Err(why) = settings.set_double("last-magnifier-zoom", magnifier_zoom) {
+            error!("Failed to save 'last-magnifier-zoom': {}", why);
+        }
+        if let Err(why) = settings.set_boolean("last-magnifier-ellipse", magnifier_ellipse) {
+            error!("Failed to save 'last-magnifier-ellipse': {}", why);
+        }
+        if let Err(why) = settings.set_string("last-text-style", text_style.id()) {
+            error!("Failed to save 'last-text-style': {}", why);
+        }
+        if let Err(why) = settings.set_string("last-text-font", &self.text_font.to_str()) {
+            error!("Failed to save 'last-text-font': {}", why);
+        }
+        if let Err(why) = settings.set_double("last-bubble-size", bubble_size) {
+            error!("Failed to save 'last-bubble-size': {}", why);
+        }
+        if let Err(why) = settings.set_boolean("last-bubble-square", bubble_square) {
+            error!("Failed to save 'last-bubble-square': {}", why);
+        }
+        if let Err(why) = settings.set_boolean("last-bubble-outline", bubble_outline) {
+            error!("Failed to save 'last-bubble-outline': {}", why);
+        }
+        if let Err(why) = settings.set_string("last-bubble-numbering", bubble_numbering.id()) {
+            error!("Failed to save 'last-bubble-numbering': {}", why);
+        }
+        if let Err(why) = settings.set_double("last-arrow-width", arrow_width) {
+            error!("Failed to save 'last-arrow-width': {}", why);
+        }
+        if let Err(why) = settings.set_string("last-arrow-head", arrow_head.id()) {
+            error!("Failed to save 'last-arrow-head': {}", why);
+        }
+        if let Err(why) = settings.set_boolean("last-arrow-double", arrow_double) {
+            error!("Failed to save 'last-arrow-double': {}", why);
         }
     }
 
     pub fn set_current_tool(&mut self, tool: Tool) {
+        if self.is_editing_text() {
+            self.finish_current_operation();
+        }
+        // Magnifiers whose destination wasn't placed keep the one next to their source
+        self.unplaced_magnifier = None;
         self.current_tool = tool;
     }
 
+    /// Sets the primary colour, the text which is being typed gets it too
+    pub fn set_primary_colour(&mut self, colour: Colour) {
+        self.primary_colour = colour;
+        self.update_text_being_edited();
+    }
+
+    /// Sets the secondary colour, the text which is being typed gets it too
+    pub fn set_secondary_colour(&mut self, colour: Colour) {
+        self.secondary_colour = colour;
+        self.update_text_being_edited();
+    }
+
+    /// Sets the style of new text, the text which is being typed gets it too
+    pub fn set_text_style(&mut self, style: TextStyle) {
+        self.tool_settings.text_style = style;
+        self.update_text_being_edited();
+    }
+
+    pub fn text_font(&self) -> &FontDescription {
+        &self.text_font
+    }
+
+    /// Sets the font of new text, the text which is being typed gets it too
+    pub fn set_text_font(&mut self, font_description: FontDescription) {
+        self.text_font = font_description;
+        self.update_text_being_edited();
+    }
+
+    fn update_text_being_edited(&mut self) {
+        if !self.is_editing_text() {
+            return;
+        }
+
+        let (primary_colour, secondary_colour) = (self.primary_colour, self.secondary_colour);
+        let text_style = self.tool_settings.text_style;
+        let text_font = self.text_font.clone();
+        if let Some(text) = self.current_operation_mut() {
+            text.set_text_colours(primary_colour, secondary_colour, text_style);
+            text.set_font_description(&text_font);
+        }
+    }
+
     pub fn current_tool(&self) -> Tool {
         self.current_tool
     }
 
+    /// The number the next bubble gets
+    pub fn next_bubble_number(&self) -> i32 {
+        self.autoincrement_bubble_number
+    }
+
+    pub fn set_next_bubble_number(&mut self, number: i32) {
+        self.autoincrement_bubble_number = number;
+    }
+
+    /// Deletes the topmost bubble at `point` and returns whether there was any. The bubbles
+    /// whose numbers come after its number move up one to fill the gap, and so does the number
+    /// the next bubble gets.
+    pub fn delete_bubble_at(&mut self, point: Point) -> bool {
+        // Removing an operation would move the one which is being changed
+        self.finish_current_operation();
+        self.last_press = LastPress::Nothing;
+        self.unplaced_magnifier = None;
+        let index = match self
+            .operations
+            .iter()
+            .rposition(|operation| operation.bubble_contains(point))
+        {
+            Some(index) => index,
+            None => return false,
+        };
+
+        if let Operation::Bubble { number, .. } = self.operations.remove(index) {
+            for operation in &mut self.operations {
+                operation.renumber_bubble_after(number);
+            }
+            if self.autoincrement_bubble_number > number {
+                self.autoincrement_bubble_number -= 1;
+            }
+        }
+
+        true
+    }
+
     pub fn start_operation_at(&mut self, point: Point) {
-        if let Some(old_operation) = self.current_operation.take() {
-            self.operations.push(old_operation);
+        self.finish_current_operation();
+        self.last_press = LastPress::Nothing;
+        let unplaced_magnifier = self.unplaced_magnifier.take();
+
+        // Clicking on text with the text tool edits it instead of adding more text
+        if self.current_tool == Tool::Text && self.edit_text_at(point) {
+            return;
+        }
+        // Likewise, clicking on the handle of an arrow with the arrow tool bends it
+        if self.current_tool == Tool::Arrow && self.bend_arrow_at(point) {
+            return;
+        }
+
+        // The press after the one which made a magnifier places its destination
+        if let (Tool::Magnifier, Some(index)) = (self.current_tool, unplaced_magnifier) {
+            if let Some(Operation::Magnifier {
+                destination: destination @ None,
+                ..
+            }) = self.operations.get_mut(index)
+            {
+                *destination = Some(Rectangle {
+                    x: point.x,
+                    y: point.y,
+                    w: 0.0,
+                    h: 0.0,
+                });
+                self.edited_index = Some(index);
+                self.magnifier_drag_start = Some(point);
+                return;
+            }
+        }
+
+        // All the spots go in the same spotlight, otherwise every spotlight would darken the
+        // spots of the others
+        if self.current_tool == Tool::Spotlight {
+            let spotlight = self
+                .operations
+                .iter()
+                .rposition(|operation| matches!(operation, Operation::Spotlight { .. }));
+            if let Some(index) = spotlight {
+                // The spotlight stays where it is, so that what was drawn over it stays on top
+                self.operations[index].add_spot(point, self.tool_settings.spotlight_ellipse);
+                self.edited_index = Some(index);
+                self.last_press = LastPress::AddedSpot(index);
+                return;
+            }
         }
 
         self.current_operation = Some(Operation::create_default_for_tool(
@@ -56,11 +379,139 @@ impl OperationStack {
             &mut self.autoincrement_bubble_number,
             self.primary_colour,
             self.secondary_colour,
+            self.tool_settings,
+            &self.text_font,
         ));
+        self.last_press = LastPress::Created(self.operations.len());
+        if self.current_tool == Tool::Magnifier {
+            self.magnifier_drag_start = Some(point);
+        }
+        if self.current_tool.types_text() {
+            self.text_caret = Some(0);
+        }
+    }
+
+    /// Makes the topmost arrow whose handle is at `point` the current operation where it is in the
+    /// stack, so that dragging moves its control point, and returns whether there was any
+    fn bend_arrow_at(&mut self, point: Point) -> bool {
+        let index = match self.operations.iter().rposition(|operation| {
+            let handle = operation.arrow_handle();
+            matches!(handle, Some(handle) if (handle - point).dist() <= ARROW_HANDLE_RADIUS)
+        }) {
+            Some(index) => index,
+            None => return false,
+        };
+
+        self.bent_arrow_handle = self.operations[index].arrow_handle();
+        self.edited_index = Some(index);
+
+        true
+    }
+
+    /// Starts editing the topmost text at `point` and returns whether there was any, this is
+    /// what double clicks do
+    pub fn edit_text_at(&mut self, point: Point) -> bool {
+        if self.is_editing_text() && self.current_tool == Tool::Text {
+            // The first click of the double click already started editing
+            return true;
+        }
+        // With the callout tool, the first click made a callout which nothing was typed into yet,
+        // finishing it drops it
+        self.finish_current_operation();
+
+        // The first click of a double click may have made an operation already, it's at the top
+        // of the stack unless finishing it dropped it
+        let created = match self.last_press {
+            LastPress::Created(index) if index < self.operations.len() => Some(index),
+            _ => None,
+        };
+        let index = match self.operations[..created.unwrap_or(self.operations.len())]
+            .iter()
+            .rposition(|operation| operation.text_contains(point))
+        {
+            Some(index) => index,
+            None => return false,
+        };
+
+        // The first click didn't mean to add anything, so what it added is taken back
+        self.unplaced_magnifier = None;
+        if let Some(created) = created {
+            if let Operation::Bubble { .. } = self.operations.remove(created) {
+                self.autoincrement_bubble_number -= 1;
+            }
+        } else if let LastPress::AddedSpot(spotlight) = self.last_press {
+            if let Operation::Spotlight { spots, .. } = &mut self.operations[spotlight] {
+                spots.pop();
+            }
+        }
+        self.last_press = LastPress::Nothing;
+
+        self.text_caret = self.operations[index].text().map(String::len);
+        self.edited_index = Some(index);
+
+        true
+    }
+
+    pub fn is_editing_text(&self) -> bool {
+        self.text_caret.is_some()
+    }
+
+    /// Types `input` at the caret of the text which is being typed
+    pub fn insert_text(&mut self, input: &str) {
+        if let (Some(caret), Some(text)) = (
+            self.text_caret,
+            self.current_operation_mut().and_then(Operation::text_mut),
+        ) {
+            text.insert_str(caret, input);
+            self.text_caret = Some(caret + input.len());
+        }
+    }
+
+    /// Deletes the character before the caret of the text which is being typed, or the one after
+    /// it if `forwards` is set
+    pub fn delete_text(&mut self, forwards: bool) {
+        if let (Some(mut caret), Some(text)) = (
+            self.text_caret,
+            self.current_operation_mut().and_then(Operation::text_mut),
+        ) {
+            if forwards {
+                if let Some(character) = text[caret..].chars().next() {
+                    text.replace_range(caret..caret + character.len_utf8(), "");
+                }
+            } else if let Some(character) = text[..caret].chars().next_back() {
+                caret -= character.len_utf8();
+                text.replace_range(caret..caret + character.len_utf8(), "");
+            }
+            self.text_caret = Some(caret);
+        }
+    }
+
+    pub fn move_text_caret(&mut self, movement: CaretMovement) {
+        if let (Some(text), Some(caret)) = (
+            self.current_operation().and_then(Operation::text),
+            self.text_caret,
+        ) {
+            self.text_caret = Some(match movement {
+                CaretMovement::Left => text[..caret]
+                    .chars()
+                    .next_back()
+                    .map_or(caret, |character| caret - character.len_utf8()),
+                CaretMovement::Right => text[caret..]
+                    .chars()
+                    .next()
+                    .map_or(caret, |character| caret + character.len_utf8()),
+                CaretMovement::LineStart => text[..caret].rfind('\n').map_or(0, |index| index + 1),
+                CaretMovement::LineEnd => text[caret..]
+                    .find('\n')
+                    .map_or(text.len(), |index| caret + index),
+            });
+        }
     }
 
     pub fn update_current_operation_end_coordinate(&mut self, new_width: f64, new_height: f64) {
-        let current_operation = match self.current_operation.as_mut() {
+        let bent_arrow_handle = self.bent_arrow_handle;
+        let magnifier_drag_start = self.magnifier_drag_start;
+        let current_operation = match self.current_operation_mut() {
             Some(curr) => curr,
             None => return,
         };
@@ -81,6 +532,11 @@ impl OperationStack {
                 rect.h = new_height;
                 rect.normalise();
             }
+            Operation::Redact { rect, .. } => {
+                rect.w = new_width;
+                rect.h = new_height;
+                rect.normalise();
+            }
             Operation::DrawLine { start, end, .. } => {
                 *end = Point {
                     x: start.x + new_width,
@@ -93,46 +549,171 @@ impl OperationStack {
                 rect.h = new_height;
                 rect.normalise();
             }
-            Operation::DrawArrow { start, end, .. } => {
-                *end = Point {
-                    x: start.x + new_width,
-                    y: start.y + new_height,
+            Operation::DrawArrow {
+                start,
+                end,
+                control,
+                ..
+            } => match bent_arrow_handle {
+                Some(handle) => {
+                    *control = Some(Point {
+                        x: handle.x + new_width,
+                        y: handle.y + new_height,
+                    })
                 }
-            }
+                None => {
+                    *end = Point {
+                        x: start.x + new_width,
+                        y: start.y + new_height,
+                    }
+                }
+            },
             Operation::Highlight { rect } => {
                 rect.w = new_width;
                 rect.h = new_height;
                 rect.normalise();
             }
+            Operation::Spotlight { spots, .. } => {
+                if let Some(spot) = spots.last_mut() {
+                    spot.resize(new_width, new_height);
+                }
+            }
+            Operation::Magnifier {
+                source,
+                destination,
+                zoom,
+                ..
+            } => {
+                let start = match magnifier_drag_start {
+                    Some(start) => start,
+                    None => return,
+                };
+                match destination {
+                    // The first drag sizes the source
+                    None => {
+                        *source = Rectangle {
+                            x: start.x,
+                            y: start.y,
+                            w: new_width,
+                            h: new_height,
+                        };
+                        source.normalise();
+                    }
+                    // The second one sizes the destination, which is the source enlarged enough
+                    // to reach the pointer, and that's how much the magnifier zooms
+                    Some(destination) if new_width != 0.0 || new_height != 0.0 => {
+                        *zoom = (new_width.abs() / source.w)
+                            .max(new_height.abs() / source.h)
+                            .max(1.0);
+                        let (w, h) = (source.w * *zoom, source.h * *zoom);
+                        *destination = Rectangle {
+                            x: if new_width < 0.0 {
+                                start.x - w
+                            } else {
+                                start.x
+                            },
+                            y: if new_height < 0.0 {
+                                start.y - h
+                            } else {
+                                start.y
+                            },
+                            w,
+                            h,
+                        };
+                    }
+                    Some(_) => {}
+                }
+            }
             Operation::DrawEllipse { ellipse, .. } => {
                 ellipse.w = new_width;
                 ellipse.h = new_height;
             }
+            Operation::Callout {
+                target,
+                top_left,
+                text,
+                ..
+            } => {
+                // The box follows the pointer until something is typed into it, after that it's
+                // only moved by accident
+                if text.is_empty() {
+                    *top_left = Point {
+                        x: target.x + new_width,
+                        y: target.y + new_height,
+                    };
+                }
+            }
             Operation::Bubble { .. } | Operation::Text { .. } => {}
         }
     }
 
-    pub fn set_text(&mut self, text: Text) {
-        if self.current_tool != Tool::Text {
-            warn!(
-                "Trying to set text when self.current_tool={:?}",
-                self.current_tool
-            );
-            return;
+    /// Whether the operation which is being drawn is a blur or pixelation which is too weak to
+    /// make the text it seems to cover unreadable
+    pub fn current_operation_may_leave_text_legible(&self, surface: &ImageSurface) -> bool {
+        let operation = match self.current_operation() {
+            Some(operation) => operation,
+            None => return false,
+        };
+
+        operation
+            .may_leave_text_legible(surface)
+            .unwrap_or_else(|why| {
+                error!("Failed to check if text stays legible: {}", why);
+                false
+            })
+    }
+
+    /// The operation which is being drawn or changed, if there is any
+    fn current_operation(&self) -> Option<&Operation> {
+        match self.edited_index {
+            Some(index) => self.operations.get(index),
+            None => self.current_operation.as_ref(),
+        }
+    }
+
+    fn current_operation_mut(&mut self) -> Option<&mut Operation> {
+        match self.edited_index {
+            Some(index) => self.operations.get_mut(index),
+            None => self.current_operation.as_mut(),
         }
-        self.current_operation
-            .as_mut()
-            .expect("A current operation should exist if we reach this")
-            .set_text(text);
     }
 
     pub fn finish_current_operation(&mut self) {
-        if let Some(operation) = self.current_operation.take() {
-            self.operations.push(operation);
+        self.text_caret = None;
+        self.bent_arrow_handle = None;
+        self.magnifier_drag_start = None;
+        // Everything which was typed into the text which was edited could have been deleted again
+        if let Some(index) = self.edited_index.take() {
+            self.operations[index].size_clicked_magnifier();
+            if self.operations[index].is_empty() {
+                self.operations.remove(index);
+            }
+        }
+        // Nothing was typed into the text or the arrow was never dragged out, so there's nothing
+        // to keep, an arrow without a length would only leave its handle behind
+        if let Some(mut operation) = self.current_operation.take() {
+            operation.size_clicked_magnifier();
+            if let Operation::Magnifier {
+                destination: None, ..
+            } = operation
+            {
+                self.unplaced_magnifier = Some(self.operations.len());
+            }
+            if !operation.is_empty() {
+                self.operations.push(operation);
+            }
         }
     }
 
     pub fn crop_region(&self) -> Option<Rectangle> {
+        // Without a crop being dragged out, the entire screen is the crop region, unless the
+        // project this was loaded from was cropped
+        self.crop_being_dragged().or(self.project_crop)
+    }
+
+    /// The region the user dragged out in this editor, unlike [`Self::crop_region`] this ignores the
+    /// crop of the project which was loaded
+    pub fn crop_being_dragged(&self) -> Option<Rectangle> {
         // We do not look at the top of `self.operations` as cropping should be the last operation
         // in the UX I want.
         if let Some(Operation::Crop(rect)) = &self.current_operation {
@@ -149,17 +730,56 @@ impl OperationStack {
     }
 
     pub fn execute(&self, surface: &ImageSurface, cairo: &Context, is_in_draw_event: bool) {
-        for operation in &self.operations {
+        for (index, operation) in self.operations.iter().enumerate() {
             tracing::warn!("We had at least one operation");
-            if let Err(why) = operation.execute(surface, cairo, is_in_draw_event) {
+            let res = if self.edited_index == Some(index) {
+                self.execute_current_operation(operation, surface, cairo, is_in_draw_event)
+            } else {
+                operation.execute(surface, cairo, is_in_draw_event)
+            };
+            if let Err(why) = res {
                 error!("{}", why);
             }
         }
 
         if let Some(operation) = &self.current_operation {
-            if let Err(why) = operation.execute(surface, cairo, is_in_draw_event) {
+            if let Err(why) =
+                self.execute_current_operation(operation, surface, cairo, is_in_draw_event)
+            {
+                error!("{}", why);
+            }
+        }
+
+        // Shows how the project will be cropped, until a crop is dragged out instead
+        if let (Some(crop), None) = (self.project_crop, self.crop_being_dragged()) {
+            if let Err(why) = Operation::Crop(crop).execute(surface, cairo, is_in_draw_event) {
                 error!("{}", why);
             }
         }
+
+        // Arrows can only be bent with the arrow tool, so their handles are only in the way
+        // otherwise
+        if is_in_draw_event && self.current_tool == Tool::Arrow {
+            for operation in self.operations.iter().chain(&self.current_operation) {
+                if let Err(why) = operation.draw_arrow_handle(cairo) {
+                    error!("{}", why);
+                }
+            }
+        }
+    }
+
+    /// Draws the operation which is being drawn or changed, the text which is being typed is drawn
+    /// with its caret
+    fn execute_current_operation(
+        &self,
+        operation: &Operation,
+        surface: &ImageSurface,
+        cairo: &Context,
+        is_in_draw_event: bool,
+    ) -> Result<(), Error> {
+        match self.text_caret {
+            Some(caret) if is_in_draw_event => operation.draw_text_being_edited(cairo, caret),
+            _ => operation.execute(surface, cairo, is_in_draw_event),
+        }
     }
 }
@@ -0,0 +1,140 @@
+//! Projects let a screenshot be reopened in the editor with its annotations still editable.
+//!
+//! A project is made of two files which are saved next to the screenshot they belong to:
+//! * `<name>.base.png`, the image as it was before any annotations were drawn on it,
+//! * `<name>.kcshot`, a JSON file which refers to the base image and contains the operations.
+
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter},
+    path::{Path, PathBuf},
+};
+
+use cairo::ImageSurface;
+use serde::{Deserialize, Serialize};
+
+use super::{data::Rectangle, operations::Operation};
+
+/// The extension of project files
+pub const EXTENSION: &str = "kcshot";
+
+/// What's stored inside the project file
+#[derive(Serialize, Deserialize)]
+struct ProjectFile {
+    /// The file name of the base image, relative to the directory containing the project file
+    base_image: String,
+    operations: Vec<Operation>,
+    /// Projects from before crops were kept aren't cropped
+    #[serde(default)]
+    crop: Option<Rectangle>,
+}
+
+impl ProjectFile {
+    fn read(project_path: &Path) -> Result<Self, Error> {
+        Ok(serde_json::from_reader(BufReader::new(File::open(
+            project_path,
+        )?))?)
+    }
+
+    fn base_image_path(&self, project_path: &Path) -> PathBuf {
+        project_path
+            .parent()
+            .unwrap_or_else(|| Path::new(""))
+            .join(&self.base_image)
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("Encountered an I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Encountered an error from cairo: {0}")]
+    Cairo(#[from] cairo::Error),
+    #[error("Failed to (de)serialise project: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Project path {0:?} has no file name")]
+    NoFileName(PathBuf),
+}
+
+impl From<cairo::IoError> for Error {
+    fn from(cerr: cairo::IoError) -> Self {
+        match cerr {
+            cairo::IoError::Cairo(cairo) => Self::Cairo(cairo),
+            cairo::IoError::Io(io) => Self::Io(io),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Project {
+    pub surface: ImageSurface,
+    pub operations: Vec<Operation>,
+    /// The part of the image which was saved, all of it if this isn't set
+    pub crop: Option<Rectangle>,
+}
+
+impl Project {
+    /// Saves the project next to `screenshot_path`, returning the path of the project file
+    pub fn save_next_to(&self, screenshot_path: &Path) -> Result<PathBuf, Error> {
+        let project_path = screenshot_path.with_extension(EXTENSION);
+        let base_image_path = screenshot_path.with_extension("base.png");
+        let base_image = base_image_path
+            .file_name()
+            .ok_or_else(|| Error::NoFileName(base_image_path.clone()))?
+            .to_string_lossy()
+            .into_owned();
+
+        self.surface
+            .write_to_png(&mut BufWriter::new(File::create(&base_image_path)?))?;
+
+        let project_file = ProjectFile {
+            base_image,
+            operations: self.operations.clone(),
+            crop: self.crop,
+        };
+        serde_json::to_writer(BufWriter::new(File::create(&project_path)?), &project_file)?;
+
+        Ok(project_path)
+    }
+
+    /// Whether `path` is the base image of a project which is saved next to it, rather than a
+    /// screenshot of its own
+    pub fn is_base_image(path: &Path) -> bool {
+        let name = path
+            .file_name()
+            .and_then(|file_name| file_name.to_str())
+            .and_then(|file_name| file_name.strip_suffix(".base.png"));
+
+        match name {
+            Some(name) => path
+                .with_file_name(format!("{}.{}", name, EXTENSION))
+                .is_file(),
+            None => false,
+        }
+    }
+
+    pub fn load(project_path: &Path) -> Result<Self, Error> {
+        let project_file = ProjectFile::read(project_path)?;
+
+        let base_image_path = project_file.base_image_path(project_path);
+        let surface =
+            ImageSurface::create_from_png(&mut BufReader::new(File::open(base_image_path)?))?;
+
+        Ok(Self {
+            surface,
+            operations: project_file.operations,
+            crop: project_file.crop,
+        })
+    }
+
+    /// Returns the paths of the files the project at `project_path` is made of, that is the
+    /// project file itself and its base image
+    pub fn files(project_path: &Path) -> Result<[PathBuf; 2], Error> {
+        let project_file = ProjectFile::read(project_path)?;
+
+        Ok([
+            project_path.to_owned(),
+            project_file.base_image_path(project_path),
+        ])
+    }
+}
@@ -1,9 +1,13 @@
-use std::{cell::RefCell, rc::Rc};
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
 
 use cairo::Context;
 use diesel::SqliteConnection;
 use gtk4::{
-    gdk::{keys::constants as GdkKey, BUTTON_PRIMARY},
+    gdk::{self, keys::constants as GdkKey, BUTTON_MIDDLE, BUTTON_PRIMARY, BUTTON_SECONDARY},
+    gio,
     glib::{self, clone, signal::Inhibit, ParamSpec},
     prelude::*,
     subclass::prelude::*,
@@ -15,14 +19,16 @@ use tracing::{error, info, warn};
 use crate::{
     editor::{
         data::{Colour, Point, Rectangle},
-        display_server::get_screen_resolution,
-        operations::Tool,
-        textdialog::DialogResponse,
+        last_region,
+        operations::{ArrowHead, CaretMovement, Numbering, TextStyle, Tool, MAX_BLUR_RADIUS},
         utils::{self, CairoExt},
+        viewport::Viewport,
+        Project,
     },
     historymodel::HistoryModel,
     kcshot::KCShot,
-    postcapture,
+    metadata::{CaptureMode, WindowInfo},
+    postcapture::{self, Capture},
 };
 
 use super::operations::OperationStack;
@@ -32,10 +38,301 @@ use crate::log_if_err;
 struct Image {
     surface: cairo::ImageSurface,
     operation_stack: OperationStack,
+    /// How the image was obtained, before it was edited
+    mode: CaptureMode,
+    active_window: Option<WindowInfo>,
 }
 
 type ImageRef = Rc<RefCell<Option<Image>>>;
 
+/// The widgets through which the settings of the current tool are changed, only the ones which
+/// the current tool has are shown
+#[derive(Clone, Debug)]
+struct ToolOptions {
+    strength: gtk4::SpinButton,
+    redaction: gtk4::ComboBoxText,
+    /// The shape of spots, magnifiers and bubbles
+    shape: gtk4::ComboBoxText,
+    spotlight_blur: gtk4::CheckButton,
+    font: gtk4::FontButton,
+    text_style: gtk4::ComboBoxText,
+    bubble_outline: gtk4::CheckButton,
+    numbering: gtk4::ComboBoxText,
+    next_bubble_number: gtk4::SpinButton,
+    arrow_head: gtk4::ComboBoxText,
+    arrow_double: gtk4::CheckButton,
+    /// Redrawn when the settings of the text which is being typed change
+    canvas: gtk4::DrawingArea,
+}
+
+impl ToolOptions {
+    fn new(image: &ImageRef, canvas: &gtk4::DrawingArea) -> Self {
+        let strength = gtk4::SpinButton::with_range(1.0, MAX_BLUR_RADIUS as f64, 1.0);
+        strength.connect_value_changed(clone!(@strong image => move |this| {
+            if let Ok(mut image) = image.try_borrow_mut() {
+                let operation_stack = &mut image.as_mut().unwrap().operation_stack;
+                let current_tool = operation_stack.current_tool();
+                let settings = &mut operation_stack.tool_settings;
+                match current_tool {
+                    Tool::Blur => settings.blur_radius = this.value() as f32,
+                    Tool::Pixelate => settings.pixelate_size = this.value_as_int() as usize,
+                    Tool::Spotlight => settings.spotlight_darkness = this.value() / 100.0,
+                    Tool::Magnifier => settings.magnifier_zoom = this.value(),
+                    Tool::AutoincrementBubble => settings.bubble_size = this.value(),
+                    Tool::Arrow => settings.arrow_width = this.value(),
+                    _ => {}
+                }
+            }
+        }));
+
+        let redaction = gtk4::ComboBoxText::new();
+        redaction.append(Some("solid"), "Primary colour");
+        redaction.append(Some("noise"), "Noise");
+        redaction.set_tooltip_text(Some("What redacted areas are filled with"));
+        redaction.connect_changed(clone!(@strong image => move |this| {
+            if let Ok(mut image) = image.try_borrow_mut() {
+                image.as_mut().unwrap().operation_stack.tool_settings.redact_with_noise =
+                    this.active_id().as_deref() == Some("noise");
+            }
+        }));
+
+        let shape = gtk4::ComboBoxText::new();
+        shape.append(Some("rectangle"), "Rectangle");
+        shape.append(Some("ellipse"), "Ellipse");
+        shape.connect_changed(clone!(@strong image => move |this| {
+            if let Ok(mut image) = image.try_borrow_mut() {
+                let operation_stack = &mut image.as_mut().unwrap().operation_stack;
+                let is_ellipse = this.active_id().as_deref() == Some("ellipse");
+                match operation_stack.current_tool() {
+                    Tool::Spotlight => operation_stack.tool_settings.spotlight_ellipse = is_ellipse,
+                    Tool::Magnifier => operation_stack.tool_settings.magnifier_ellipse = is_ellipse,
+                    Tool::AutoincrementBubble => operation_stack.tool_settings.bubble_square = !is_ellipse,
+                    _ => {}
+                }
+            }
+        }));
+
+        let spotlight_blur = gtk4::CheckButton::with_label("Blur");
+        spotlight_blur.set_tooltip_text(Some("Blur what's outside the spots too"));
+        spotlight_blur.connect_toggled(clone!(@strong image => move |this| {
+            if let Ok(mut image) = image.try_borrow_mut() {
+                image.as_mut().unwrap().operation_stack.tool_settings.spotlight_blur =
+                    this.is_active();
+            }
+        }));
+
+        let font = gtk4::FontButton::new();
+        font.set_tooltip_text(Some("The font of the text"));
+        font.connect_font_set(clone!(@strong image, @weak canvas => move |this| {
+            if let (Ok(mut image), Some(font_description)) = (image.try_borrow_mut(), this.font_desc()) {
+                image.as_mut().unwrap().operation_stack.set_text_font(font_description);
+                canvas.queue_draw();
+            }
+        }));
+
+        let text_style = gtk4::ComboBoxText::new();
+        text_style.append(Some(TextStyle::Plain.id()), "Plain");
+        text_style.append(Some(TextStyle::Box.id()), "Box");
+        text_style.append(Some(TextStyle::Outline.id()), "Outline");
+        text_style.set_tooltip_text(Some(
+            "What's drawn around the text, in the secondary colour",
+        ));
+        text_style.connect_changed(clone!(@strong image, @weak canvas => move |this| {
+            let style = this.active_id().and_then(|id| TextStyle::from_id(&id));
+            if let (Ok(mut image), Some(style)) = (image.try_borrow_mut(), style) {
+                image.as_mut().unwrap().operation_stack.set_text_style(style);
+                canvas.queue_draw();
+            }
+        }));
+
+        let bubble_outline = gtk4::CheckButton::with_label("Outline");
+        bubble_outline
+            .set_tooltip_text(Some("Draw a line around the bubbles in the primary colour"));
+        bubble_outline.connect_toggled(clone!(@strong image => move |this| {
+            if let Ok(mut image) = image.try_borrow_mut() {
+                image.as_mut().unwrap().operation_stack.tool_settings.bubble_outline =
+                    this.is_active();
+            }
+        }));
+
+        let numbering = gtk4::ComboBoxText::new();
+        numbering.append(Some(Numbering::Numbers.id()), "1, 2, 3");
+        numbering.append(Some(Numbering::Letters.id()), "A, B, C");
+        numbering.append(Some(Numbering::Roman.id()), "I, II, III");
+        numbering.set_tooltip_text(Some("How the bubbles are numbered"));
+        numbering.connect_changed(clone!(@strong image => move |this| {
+            let numbering = this.active_id().and_then(|id| Numbering::from_id(&id));
+            if let (Ok(mut image), Some(numbering)) = (image.try_borrow_mut(), numbering) {
+                image.as_mut().unwrap().operation_stack.tool_settings.bubble_numbering = numbering;
+            }
+        }));
+
+        let next_bubble_number = gtk4::SpinButton::with_range(1.0, 9999.0, 1.0);
+        next_bubble_number.set_tooltip_text(Some(
+            "The number of the next bubble, right click a bubble to delete it and renumber the \
+             ones after it",
+        ));
+        next_bubble_number.connect_value_changed(clone!(@strong image => move |this| {
+            if let Ok(mut image) = image.try_borrow_mut() {
+                image
+                    .as_mut()
+                    .unwrap()
+                    .operation_stack
+                    .set_next_bubble_number(this.value_as_int());
+            }
+        }));
+
+        let arrow_head = gtk4::ComboBoxText::new();
+        arrow_head.append(Some(ArrowHead::Open.id()), "Open");
+        arrow_head.append(Some(ArrowHead::Filled.id()), "Filled");
+        arrow_head.set_tooltip_text(Some(
+            "The shape of the arrowheads, drag the handle in the middle of an arrow to bend it",
+        ));
+        arrow_head.connect_changed(clone!(@strong image => move |this| {
+            let head = this.active_id().and_then(|id| ArrowHead::from_id(&id));
+            if let (Ok(mut image), Some(head)) = (image.try_borrow_mut(), head) {
+                image.as_mut().unwrap().operation_stack.tool_settings.arrow_head = head;
+            }
+        }));
+
+        let arrow_double = gtk4::CheckButton::with_label("Both ends");
+        arrow_double.set_tooltip_text(Some("Draw heads at both ends of the arrows"));
+        arrow_double.connect_toggled(clone!(@strong image => move |this| {
+            if let Ok(mut image) = image.try_borrow_mut() {
+                image.as_mut().unwrap().operation_stack.tool_settings.arrow_double =
+                    this.is_active();
+            }
+        }));
+
+        Self {
+            strength,
+            redaction,
+            shape,
+            spotlight_blur,
+            font,
+            text_style,
+            bubble_outline,
+            numbering,
+            next_bubble_number,
+            arrow_head,
+            arrow_double,
+            canvas: canvas.clone(),
+        }
+    }
+
+    fn append_to(&self, toolbar: &gtk4::Box) {
+        toolbar.append(&self.strength);
+        toolbar.append(&self.redaction);
+        toolbar.append(&self.shape);
+        toolbar.append(&self.spotlight_blur);
+        toolbar.append(&self.font);
+        toolbar.append(&self.text_style);
+        toolbar.append(&self.bubble_outline);
+        toolbar.append(&self.numbering);
+        toolbar.append(&self.next_bubble_number);
+        toolbar.append(&self.arrow_head);
+        toolbar.append(&self.arrow_double);
+    }
+
+    /// Shows the options the current tool of `operation_stack` has, set to what they are in it
+    fn show_for(&self, operation_stack: &OperationStack) {
+        let tool = operation_stack.current_tool();
+        let settings = operation_stack.tool_settings;
+        let strength = match tool {
+            Tool::Blur => Some((
+                settings.blur_radius as f64,
+                1.0,
+                MAX_BLUR_RADIUS as f64,
+                "Blur strength",
+            )),
+            Tool::Pixelate => Some((
+                settings.pixelate_size as f64,
+                2.0,
+                64.0,
+                "Size of the pixelated boxes",
+            )),
+            Tool::Spotlight => Some((
+                settings.spotlight_darkness * 100.0,
+                0.0,
+                100.0,
+                "How much the rest of the image is darkened, in percent",
+            )),
+            Tool::Magnifier => Some((
+                settings.magnifier_zoom,
+                2.0,
+                8.0,
+                "How many times the image is enlarged, unless the destination is dragged out",
+            )),
+            Tool::AutoincrementBubble => Some((
+                settings.bubble_size,
+                6.0,
+                72.0,
+                "Size of the numbers in points, the bubbles fit around them",
+            )),
+            Tool::Arrow => Some((
+                settings.arrow_width,
+                1.0,
+                32.0,
+                "Width of the line, the arrowheads grow with it",
+            )),
+            _ => None,
+        };
+        self.strength.set_visible(strength.is_some());
+        if let Some((value, min, max, tooltip)) = strength {
+            self.strength.set_range(min, max);
+            self.strength.set_value(value);
+            self.strength.set_tooltip_text(Some(tooltip));
+        }
+
+        self.redaction.set_visible(tool == Tool::Redact);
+        self.redaction
+            .set_active_id(Some(if settings.redact_with_noise {
+                "noise"
+            } else {
+                "solid"
+            }));
+
+        let is_ellipse = match tool {
+            Tool::Spotlight => Some((settings.spotlight_ellipse, "The shape of the spots")),
+            Tool::Magnifier => Some((settings.magnifier_ellipse, "The shape of the magnifier")),
+            Tool::AutoincrementBubble => {
+                Some((!settings.bubble_square, "The shape of the bubbles"))
+            }
+            _ => None,
+        };
+        self.shape.set_visible(is_ellipse.is_some());
+        if let Some((is_ellipse, tooltip)) = is_ellipse {
+            self.shape
+                .set_active_id(Some(if is_ellipse { "ellipse" } else { "rectangle" }));
+            self.shape.set_tooltip_text(Some(tooltip));
+        }
+        self.spotlight_blur.set_visible(tool == Tool::Spotlight);
+        self.spotlight_blur.set_active(settings.spotlight_blur);
+
+        self.font.set_visible(tool.types_text());
+        self.font.set_font_desc(operation_stack.text_font());
+        self.text_style.set_visible(tool == Tool::Text);
+        self.text_style
+            .set_active_id(Some(settings.text_style.id()));
+
+        let is_bubble = tool == Tool::AutoincrementBubble;
+        self.bubble_outline.set_visible(is_bubble);
+        self.bubble_outline.set_active(settings.bubble_outline);
+        self.numbering.set_visible(is_bubble);
+        self.numbering
+            .set_active_id(Some(settings.bubble_numbering.id()));
+        self.next_bubble_number.set_visible(is_bubble);
+        self.next_bubble_number
+            .set_value(operation_stack.next_bubble_number() as f64);
+
+        self.arrow_head.set_visible(tool == Tool::Arrow);
+        self.arrow_head
+            .set_active_id(Some(settings.arrow_head.id()));
+        self.arrow_double.set_visible(tool == Tool::Arrow);
+        self.arrow_double.set_active(settings.arrow_double);
+    }
+}
+
 #[derive(Default, Debug)]
 pub struct EditorWindow {
     image: ImageRef,
@@ -72,27 +369,48 @@ impl EditorWindow {
         };
         EditorWindow::do_draw(image, &cairo, false);
 
-        let rectangle = image.operation_stack.crop_region().unwrap_or_else(|| {
-            let (w, h) = get_screen_resolution().map_or_else(
-                |why| {
-                    error!(
-                        "Unable to retrieve screen resolution{}\n\t\tgoing with 1920x1080",
-                        why
-                    );
-                    (1920, 1080)
-                },
-                |screen_resolution| screen_resolution,
-            );
-            Rectangle {
+        let rectangle = image
+            .operation_stack
+            .crop_region()
+            .unwrap_or_else(|| Rectangle {
                 x: 0.0,
                 y: 0.0,
-                w: w as f64,
-                h: h as f64,
+                w: image.surface.width() as f64,
+                h: image.surface.height() as f64,
+            });
+
+        let is_screenshot = image.mode == CaptureMode::Screen;
+        let settings = gio::Settings::new("kc.kcshot");
+        if is_screenshot {
+            if let Some(crop_region) = image.operation_stack.crop_being_dragged() {
+                last_region::save(&settings, crop_region);
             }
-        });
+        }
+        image.operation_stack.save_tool_settings(&settings);
 
         match utils::pixbuf_for(&image.surface, rectangle) {
-            Some(pixbuf) => postcapture::current_action().handle(history_model, conn, pixbuf),
+            Some(pixbuf) => {
+                let project = Project {
+                    surface: image.surface.clone(),
+                    operations: image.operation_stack.annotations(),
+                    crop: image.operation_stack.crop_region(),
+                };
+                let mode = if is_screenshot && image.operation_stack.crop_region().is_some() {
+                    CaptureMode::Region
+                } else {
+                    image.mode
+                };
+                let capture = Capture {
+                    pixbuf,
+                    project: Some(&project),
+                    mode,
+                    window: image.active_window.clone(),
+                    monitor: is_screenshot
+                        .then(|| last_region::monitor_containing(rectangle))
+                        .flatten(),
+                };
+                postcapture::current_action().handle(history_model, conn, capture)
+            }
             None => {
                 error!(
                     "Failed to create a pixbuf from the surface: {:?} with crop region {:#?}",
@@ -108,6 +426,7 @@ impl EditorWindow {
     fn make_primary_colour_chooser_button(
         image: ImageRef,
         parent_window: &gtk4::Window,
+        canvas: &gtk4::DrawingArea,
     ) -> gtk4::Button {
         let drawing_area = gtk4::DrawingArea::new();
         drawing_area.set_size_request(32, 32);
@@ -155,10 +474,14 @@ impl EditorWindow {
 
         }));
 
-        Self::make_button::<true>(&drawing_area, parent_window, image)
+        Self::make_button::<true>(&drawing_area, parent_window, image, canvas)
     }
 
-    fn make_secondary_colour_button(image: ImageRef, parent_window: &gtk4::Window) -> gtk4::Button {
+    fn make_secondary_colour_button(
+        image: ImageRef,
+        parent_window: &gtk4::Window,
+        canvas: &gtk4::DrawingArea,
+    ) -> gtk4::Button {
         let drawing_area = gtk4::DrawingArea::new();
         drawing_area.set_size_request(32, 32);
         drawing_area.set_draw_func(clone!(@strong image =>  move |_this, cairo, _w, _h| {
@@ -190,30 +513,34 @@ impl EditorWindow {
 
         }));
 
-        Self::make_button::<false>(&drawing_area, parent_window, image)
+        Self::make_button::<false>(&drawing_area, parent_window, image, canvas)
     }
 
     fn make_button<const IS_PRIMARY: bool>(
         drawing_area: &gtk4::DrawingArea,
         parent_window: &gtk4::Window,
         image: ImageRef,
+        canvas: &gtk4::DrawingArea,
     ) -> gtk4::Button {
         let button = gtk4::Button::new();
         button.set_child(Some(drawing_area));
 
-        button.connect_clicked(clone!(@strong parent_window, @strong image, @strong drawing_area => move |_this| {
+        button.connect_clicked(clone!(@strong parent_window, @strong image, @strong drawing_area, @strong canvas => move |_this| {
             let colour_chooser = gtk4::ColorChooserDialog::new(Some("Pick a colour"), Some(&parent_window));
 
-            colour_chooser.connect_response(clone!(@strong image, @strong drawing_area => move |this, response| {
+            colour_chooser.connect_response(clone!(@strong image, @strong drawing_area, @strong canvas => move |this, response| {
                 if response == ResponseType::Ok {
                     let mut image = image.borrow_mut();
                     let image = image.as_mut().unwrap();
+                    let colour = Colour::from_gdk_rgba(this.rgba());
                     if IS_PRIMARY {
-                        image.operation_stack.primary_colour = Colour::from_gdk_rgba(this.rgba());
+                        image.operation_stack.set_primary_colour(colour);
                     } else {
-                        image.operation_stack.secondary_colour = Colour::from_gdk_rgba(this.rgba());
+                        image.operation_stack.set_secondary_colour(colour);
                     }
                     drawing_area.queue_draw();
+                    // The text which is being typed may have changed colour
+                    canvas.queue_draw();
                 }
 
                 this.close();
@@ -224,52 +551,77 @@ impl EditorWindow {
 
         button
     }
-}
-
-#[glib::object_subclass]
-impl ObjectSubclass for EditorWindow {
-    const NAME: &'static str = "EditorWindow";
-    type Type = super::EditorWindow;
-    type ParentType = gtk4::ApplicationWindow;
-}
 
-impl ObjectImpl for EditorWindow {
-    fn constructed(&self, obj: &Self::Type) {
-        self.parent_constructed(obj);
-        let image = super::display_server::take_screenshot().expect("Couldn't take a screenshot");
+    /// Sets the image shown by the editor and builds the editor's UI around it, this must be
+    /// called exactly once, right after the window is constructed
+    pub(super) fn set_image(
+        &self,
+        obj: &super::EditorWindow,
+        image: cairo::ImageSurface,
+        operation_stack: OperationStack,
+        mode: CaptureMode,
+        active_window: Option<WindowInfo>,
+    ) {
         warn!("Image status {:?}", image.status());
 
         let overlay = gtk4::Overlay::new();
         obj.set_child(Some(&overlay));
-        let drawing_area = gtk4::DrawingArea::builder().can_focus(true).build();
+        let drawing_area = gtk4::DrawingArea::builder()
+            .can_focus(true)
+            .content_width(image.width())
+            .content_height(image.height())
+            .build();
+
+        // Images which are bigger than the window (e.g. ones opened from a file) can be scrolled
+        // through, for screenshots the drawing area is exactly as big as the window
+        let scrolled_window = gtk4::ScrolledWindow::new();
+        scrolled_window.set_child(Some(&drawing_area));
+
+        let viewport = Rc::new(Viewport::new(
+            &drawing_area,
+            &scrolled_window,
+            (image.width(), image.height()),
+        ));
 
         let toolbar = gtk4::Box::new(gtk4::Orientation::Horizontal, 0);
 
-        overlay.set_child(Some(&drawing_area));
+        overlay.set_child(Some(&scrolled_window));
         overlay.add_overlay(&toolbar);
 
-        overlay.connect_get_child_position(|_this, widget| {
-            let (screen_width, screen_height) = match super::display_server::get_screen_resolution()
-            {
-                Ok(res) => res,
-                Err(why) => {
-                    error!(
-                        "Error getting screen resolution: {}.\n\t\tGoing with 1920x1080",
-                        why
-                    );
-                    (1920, 1080)
+        // Shown when a blur or pixelation seems to be too weak to hide the text under it
+        let weak_effect_warning = gtk4::InfoBar::new();
+        weak_effect_warning.set_message_type(gtk4::MessageType::Warning);
+        weak_effect_warning.set_show_close_button(true);
+        weak_effect_warning.add_child(&gtk4::Label::new(Some(
+            "The text under this may still be readable, make the effect stronger \
+             or use the redact tool to hide it for sure",
+        )));
+        weak_effect_warning.set_halign(gtk4::Align::Center);
+        weak_effect_warning.set_valign(gtk4::Align::End);
+        weak_effect_warning.set_margin_bottom(20);
+        weak_effect_warning.hide();
+        weak_effect_warning.connect_response(|this, _| this.hide());
+        overlay.add_overlay(&weak_effect_warning);
+
+        overlay.connect_get_child_position(
+            clone!(@weak toolbar => @default-return None, move |this, widget| {
+                if widget != toolbar.upcast_ref::<gtk4::Widget>() {
+                    return None;
                 }
-            };
-            Some(Allocation {
-                x: screen_width / 2 - widget.width() / 2,
-                y: screen_height / 5,
-                width: 11 * 32,
-                height: 32,
-            })
-        });
+
+                let (_, natural_size) = widget.preferred_size();
+                Some(Allocation {
+                    x: (this.width() - natural_size.width).max(0) / 2,
+                    y: this.height() / 5,
+                    width: natural_size.width,
+                    height: natural_size.height,
+                })
+            }),
+        );
 
         drawing_area.set_draw_func(
-            clone!(@strong self.image as image => move |_widget, cairo, _w, _h| {
+            clone!(@strong self.image as image, @strong viewport => move |_widget, cairo, _w, _h| {
+                cairo.scale(viewport.zoom(), viewport.zoom());
                 match image.try_borrow() {
                     Ok(image) => EditorWindow::do_draw(image.as_ref().unwrap(), cairo, true),
                     Err(why) => info!("Image already borrowed: {:?}", why)
@@ -277,26 +629,129 @@ impl ObjectImpl for EditorWindow {
             }),
         );
 
+        // While space is held, dragging with the primary button pans the view instead of drawing
+        let is_space_held = Rc::new(Cell::new(false));
+
         let key_event_handler = gtk4::EventControllerKey::new();
-        key_event_handler.connect_key_pressed(clone!(@strong obj => move |_this, key, _, _| {
-            if key == GdkKey::Escape {
-                obj.hide();
+        // Text is typed through an input method, so that dead keys, compose sequences and the
+        // like work
+        let im_context = gtk4::IMMulticontext::new();
+        im_context.set_client_widget(Some(&drawing_area));
+        im_context.connect_commit(
+            clone!(@strong self.image as image, @strong drawing_area => move |_this, input| {
+                if let Ok(mut image) = image.try_borrow_mut() {
+                    image.as_mut().unwrap().operation_stack.insert_text(input);
+                    drawing_area.queue_draw();
+                }
+            }),
+        );
+
+        key_event_handler.connect_key_pressed(
+            clone!(@strong self.image as image, @strong obj, @strong drawing_area, @strong im_context, @strong viewport, @strong is_space_held => move |this, key, _, modifiers| {
+                let is_ctrl_held = modifiers.contains(gdk::ModifierType::CONTROL_MASK);
+                let is_editing_text =
+                    matches!(&*image.borrow(), Some(image) if image.operation_stack.is_editing_text());
+                if is_editing_text {
+                    let is_typed =
+                        matches!(this.current_event(), Some(event) if im_context.filter_keypress(&event));
+                    if !is_typed {
+                        let mut image = image.borrow_mut();
+                        edit_text(&mut image.as_mut().unwrap().operation_stack, &key, is_ctrl_held);
+                    }
+                    drawing_area.queue_draw();
+                    return Inhibit(true);
+                }
+
+                if key == GdkKey::Escape {
+                    obj.hide();
+                } else if key == GdkKey::space {
+                    is_space_held.set(true);
+                } else if is_ctrl_held && (key == GdkKey::plus || key == GdkKey::equal || key == GdkKey::KP_Add) {
+                    viewport.zoom_in();
+                } else if is_ctrl_held && (key == GdkKey::minus || key == GdkKey::KP_Subtract) {
+                    viewport.zoom_out();
+                } else if is_ctrl_held && key == GdkKey::_0 {
+                    viewport.zoom_to_fit();
+                } else if is_ctrl_held && key == GdkKey::_1 {
+                    viewport.set_zoom(1.0);
+                }
+                Inhibit(false)
+            }),
+        );
+        key_event_handler.connect_key_released(
+            clone!(@strong is_space_held => move |_this, key, _, _| {
+                if key == GdkKey::space {
+                    is_space_held.set(false);
+                }
+            }),
+        );
+        obj.add_controller(&key_event_handler);
+
+        let scroll_controller =
+            gtk4::EventControllerScroll::new(gtk4::EventControllerScrollFlags::VERTICAL);
+        // Run before the scrolled window gets to scroll, so Ctrl+scroll zooms instead
+        scroll_controller.set_propagation_phase(gtk4::PropagationPhase::Capture);
+        scroll_controller.connect_scroll(clone!(@strong viewport => move |this, _dx, dy| {
+            if !this.current_event_state().contains(gdk::ModifierType::CONTROL_MASK) {
+                return Inhibit(false);
+            }
+
+            if dy < 0.0 {
+                viewport.zoom_in();
+            } else if dy > 0.0 {
+                viewport.zoom_out();
             }
-            Inhibit(false)
+            Inhibit(true)
         }));
-        obj.add_controller(&key_event_handler);
+        scrolled_window.add_controller(&scroll_controller);
+
+        // Panning is done with the middle mouse button, or with the primary one while space is held.
+        // It's attached to the scrolled window rather than to the drawing area because the drawing
+        // area moves around under the pointer while we pan
+        let pan_controller = gtk4::GestureDrag::new();
+        pan_controller.set_button(0);
+        pan_controller.set_propagation_phase(gtk4::PropagationPhase::Capture);
+        let pan_start = Rc::new(Cell::new((0.0, 0.0)));
+        pan_controller.connect_drag_begin(
+            clone!(@strong viewport, @strong is_space_held, @strong pan_start => move |this, _x, _y| {
+                let button = this.current_button();
+                if button == BUTTON_MIDDLE || (button == BUTTON_PRIMARY && is_space_held.get()) {
+                    // Claiming the sequence stops the drawing area's gestures from drawing
+                    this.set_state(gtk4::EventSequenceState::Claimed);
+                    pan_start.set(viewport.scroll_position());
+                } else {
+                    this.set_state(gtk4::EventSequenceState::Denied);
+                }
+            }),
+        );
+        pan_controller.connect_drag_update(
+            clone!(@strong viewport, @strong pan_start => move |_this, dx, dy| {
+                viewport.pan_from(pan_start.get(), dx, dy);
+            }),
+        );
+        scrolled_window.add_controller(&pan_controller);
+
+        let tool_options = ToolOptions::new(&self.image, &drawing_area);
 
         let click_event_handler = gtk4::GestureClick::new();
 
         click_event_handler.set_button(BUTTON_PRIMARY);
         click_event_handler.connect_pressed(
-            clone!(@strong self.image as image, @strong obj =>  move |_this, _n_clicks, x, y| {
+            clone!(@strong self.image as image, @strong obj, @strong drawing_area, @strong im_context, @strong viewport =>  move |_this, n_clicks, x, y| {
                 tracing::warn!("Got button-press on drawing_area");
                 match image.try_borrow_mut() {
                     Ok(mut image) => {
                         let image = image.as_mut().unwrap();
-                        image.operation_stack.start_operation_at(Point { x, y });
+                        let point = viewport.to_image_space(Point { x, y });
+                        // Double clicking on text edits it, whichever tool is used
+                        if n_clicks != 2 || !image.operation_stack.edit_text_at(point) {
+                            image.operation_stack.start_operation_at(point);
+                        }
+                        if image.operation_stack.is_editing_text() {
+                            im_context.focus_in();
+                        }
                         obj.queue_draw();
+                        drawing_area.queue_draw();
                     }
                     Err(why) => info!("Image already borrowed: {:?}", why),
                 }
@@ -310,24 +765,23 @@ impl ObjectImpl for EditorWindow {
             .expect("Should have a history model when taking a screenshot")
             .clone();
         click_event_handler.connect_released(
-            clone!(@strong self.image as image, @strong obj, @strong drawing_area => move |_this, _n_clicks, _x, _y| {
+            clone!(@strong self.image as image, @strong obj, @strong drawing_area, @strong weak_effect_warning, @strong tool_options => move |_this, _n_clicks, _x, _y| {
                 info!("AAA?");
                 let mut imagerc = image.borrow_mut();
                 let image = imagerc.as_mut().unwrap();
-                if image.operation_stack.current_tool() == Tool::Text {
-                    tracing::info!("Text tool has been activated");
-                    let res = super::textdialog::pop_text_dialog_and_get_text(obj.upcast_ref());
-                    match res {
-                        DialogResponse::Text(text) => {
-                            image.operation_stack.set_text(text);
-                            drawing_area.queue_draw();
-                        }
-                        DialogResponse::Cancel => { /* do nothing */ }
-                    }
+                if image.operation_stack.is_editing_text() {
+                    // The text stays editable until something else is done
+                    drawing_area.queue_draw();
                     return;
                 } else if image.operation_stack.current_tool() != Tool::CropAndSave {
                     tracing::info!("This is called");
+                    if image.operation_stack.current_operation_may_leave_text_legible(&image.surface) {
+                        weak_effect_warning.show();
+                    }
                     image.operation_stack.finish_current_operation();
+                    tool_options
+                        .next_bubble_number
+                        .set_value(image.operation_stack.next_bubble_number() as f64);
                     drawing_area.queue_draw();
                     return;
                 }
@@ -339,12 +793,32 @@ impl ObjectImpl for EditorWindow {
 
         drawing_area.add_controller(&click_event_handler);
 
+        // Right clicking a bubble deletes it, whichever tool is used
+        let delete_event_handler = gtk4::GestureClick::new();
+        delete_event_handler.set_button(BUTTON_SECONDARY);
+        delete_event_handler.connect_pressed(
+            clone!(@strong self.image as image, @strong drawing_area, @strong tool_options, @strong viewport => move |_this, _n_clicks, x, y| {
+                if let Ok(mut image) = image.try_borrow_mut() {
+                    let operation_stack = &mut image.as_mut().unwrap().operation_stack;
+                    let point = viewport.to_image_space(Point { x, y });
+                    if operation_stack.delete_bubble_at(point) {
+                        tool_options
+                            .next_bubble_number
+                            .set_value(operation_stack.next_bubble_number() as f64);
+                        drawing_area.queue_draw();
+                    }
+                }
+            }),
+        );
+        drawing_area.add_controller(&delete_event_handler);
+
         let drag_controller = gtk4::GestureDrag::new();
         drag_controller.connect_drag_update(
-            clone!(@strong self.image as image, @strong drawing_area =>  move |_this, x, y| {
+            clone!(@strong self.image as image, @strong drawing_area, @strong viewport =>  move |_this, x, y| {
                 let mut image = image.borrow_mut();
                 let image = image.as_mut().unwrap();
                 info!("Dragging to {{ {}, {} }}", x, y);
+                let Point { x, y } = viewport.to_image_space(Point { x, y });
                 image.operation_stack.update_current_operation_end_coordinate(x, y);
                 drawing_area.queue_draw();
             }),
@@ -353,13 +827,16 @@ impl ObjectImpl for EditorWindow {
 
         self.image.replace(Some(Image {
             surface: image,
-            operation_stack: OperationStack::new(),
+            operation_stack,
+            mode,
+            active_window,
         }));
 
         fn make_tool_button(
             tool: Tool,
             toolbar: &gtk4::Box,
             image: ImageRef,
+            tool_options: &ToolOptions,
             group_source: Option<&gtk4::ToggleButton>,
         ) -> gtk4::ToggleButton {
             let button = match group_source {
@@ -371,40 +848,116 @@ impl ObjectImpl for EditorWindow {
                 None => gtk4::ToggleButton::new(),
             };
             button.set_child(Some(&gtk4::Image::from_file(tool.path())));
-
-            button.connect_clicked(clone!(@strong image => move |_| {
+            let is_current_tool = image
+                .borrow()
+                .as_ref()
+                .unwrap()
+                .operation_stack
+                .current_tool()
+                == tool;
+
+            button.connect_clicked(clone!(@strong image, @strong tool_options => move |_| {
                 info!("Entered on-click handler of {:?}", tool);
                 image.borrow_mut().as_mut().unwrap().operation_stack.set_current_tool(tool);
+                tool_options.show_for(&image.borrow().as_ref().unwrap().operation_stack);
+                // Switching tools stops the text from being typed
+                tool_options.canvas.queue_draw();
             }));
-            button.set_active(false);
+            button.set_active(is_current_tool);
             toolbar.append(&button);
             tracing::info!("waa");
             button
         }
 
-        let group_source = make_tool_button(Tool::CropAndSave, &toolbar, self.image.clone(), None);
+        let group_source = make_tool_button(
+            Tool::CropAndSave,
+            &toolbar,
+            self.image.clone(),
+            &tool_options,
+            None,
+        );
 
         // rustfmt make this section of code ugly, tell it to shutup
         #[rustfmt::skip]
         let _ = {
-            make_tool_button(Tool::Line, &toolbar, self.image.clone(), Some(&group_source));
-            make_tool_button(Tool::Arrow, &toolbar, self.image.clone(), Some(&group_source));
-            make_tool_button(Tool::Rectangle, &toolbar, self.image.clone(), Some(&group_source));
-            make_tool_button(Tool::Highlight, &toolbar, self.image.clone(), Some(&group_source));
-            make_tool_button(Tool::Ellipse, &toolbar, self.image.clone(), Some(&group_source));
-            make_tool_button(Tool::Pixelate, &toolbar, self.image.clone(), Some(&group_source));
-            make_tool_button(Tool::Blur, &toolbar, self.image.clone(), Some(&group_source));
-            make_tool_button(Tool::AutoincrementBubble, &toolbar, self.image.clone(), Some(&group_source));
-            make_tool_button(Tool::Text, &toolbar, self.image.clone(), Some(&group_source));
+            make_tool_button(Tool::Line, &toolbar, self.image.clone(), &tool_options, Some(&group_source));
+            make_tool_button(Tool::Arrow, &toolbar, self.image.clone(), &tool_options, Some(&group_source));
+            make_tool_button(Tool::Rectangle, &toolbar, self.image.clone(), &tool_options, Some(&group_source));
+            make_tool_button(Tool::Highlight, &toolbar, self.image.clone(), &tool_options, Some(&group_source));
+            make_tool_button(Tool::Spotlight, &toolbar, self.image.clone(), &tool_options, Some(&group_source));
+            make_tool_button(Tool::Magnifier, &toolbar, self.image.clone(), &tool_options, Some(&group_source));
+            make_tool_button(Tool::Ellipse, &toolbar, self.image.clone(), &tool_options, Some(&group_source));
+            make_tool_button(Tool::Pixelate, &toolbar, self.image.clone(), &tool_options, Some(&group_source));
+            make_tool_button(Tool::Blur, &toolbar, self.image.clone(), &tool_options, Some(&group_source));
+            make_tool_button(Tool::Redact, &toolbar, self.image.clone(), &tool_options, Some(&group_source));
+            make_tool_button(Tool::AutoincrementBubble, &toolbar, self.image.clone(), &tool_options, Some(&group_source));
+            make_tool_button(Tool::Text, &toolbar, self.image.clone(), &tool_options, Some(&group_source));
+            make_tool_button(Tool::Callout, &toolbar, self.image.clone(), &tool_options, Some(&group_source));
         };
 
-        let primary_colour_button =
-            EditorWindow::make_primary_colour_chooser_button(self.image.clone(), obj.upcast_ref());
+        tool_options.append_to(&toolbar);
+        tool_options.show_for(&self.image.borrow().as_ref().unwrap().operation_stack);
+
+        let primary_colour_button = EditorWindow::make_primary_colour_chooser_button(
+            self.image.clone(),
+            obj.upcast_ref(),
+            &drawing_area,
+        );
         toolbar.append(&primary_colour_button);
 
-        let secondary_colour_button =
-            EditorWindow::make_secondary_colour_button(self.image.clone(), obj.upcast_ref());
+        let secondary_colour_button = EditorWindow::make_secondary_colour_button(
+            self.image.clone(),
+            obj.upcast_ref(),
+            &drawing_area,
+        );
         toolbar.append(&secondary_colour_button);
+
+        let zoom_to_fit_button = gtk4::Button::with_label("Fit");
+        zoom_to_fit_button.set_tooltip_text(Some("Zoom to fit (Ctrl+0)"));
+        zoom_to_fit_button
+            .connect_clicked(clone!(@strong viewport => move |_| viewport.zoom_to_fit()));
+        toolbar.append(&zoom_to_fit_button);
+
+        let actual_size_button = gtk4::Button::with_label("100%");
+        actual_size_button.set_tooltip_text(Some("Zoom to the actual size (Ctrl+1)"));
+        actual_size_button
+            .connect_clicked(clone!(@strong viewport => move |_| viewport.set_zoom(1.0)));
+        toolbar.append(&actual_size_button);
+    }
+}
+
+/// Handles the keys which change the text that's being typed without typing anything into it
+fn edit_text(operation_stack: &mut OperationStack, key: &gdk::keys::Key, is_ctrl_held: bool) {
+    let is_enter = *key == GdkKey::Return || *key == GdkKey::KP_Enter;
+    if *key == GdkKey::Escape || (is_enter && is_ctrl_held) {
+        operation_stack.finish_current_operation();
+    } else if is_enter {
+        operation_stack.insert_text("\n");
+    } else if *key == GdkKey::BackSpace {
+        operation_stack.delete_text(false);
+    } else if *key == GdkKey::Delete {
+        operation_stack.delete_text(true);
+    } else if *key == GdkKey::Left {
+        operation_stack.move_text_caret(CaretMovement::Left);
+    } else if *key == GdkKey::Right {
+        operation_stack.move_text_caret(CaretMovement::Right);
+    } else if *key == GdkKey::Home {
+        operation_stack.move_text_caret(CaretMovement::LineStart);
+    } else if *key == GdkKey::End {
+        operation_stack.move_text_caret(CaretMovement::LineEnd);
+    }
+}
+
+#[glib::object_subclass]
+impl ObjectSubclass for EditorWindow {
+    const NAME: &'static str = "EditorWindow";
+    type Type = super::EditorWindow;
+    type ParentType = gtk4::ApplicationWindow;
+}
+
+impl ObjectImpl for EditorWindow {
+    fn constructed(&self, obj: &Self::Type) {
+        self.parent_constructed(obj);
     }
 
     fn properties() -> &'static [glib::ParamSpec] {
@@ -1,7 +1,7 @@
 use super::data::{Colour, Rectangle};
 
-use cairo::Context;
-use gtk4::gdk::{self, gdk_pixbuf::Pixbuf};
+use cairo::{Context, Format, ImageSurface};
+use gtk4::gdk::{self, gdk_pixbuf::Pixbuf, prelude::GdkCairoContextExt};
 
 #[macro_export]
 macro_rules! log_if_err {
@@ -27,6 +27,17 @@ pub fn pixbuf_for(surface: &cairo::Surface, rectangle: Rectangle) -> Option<Pixb
     gdk::pixbuf_get_from_surface(surface, src_x, src_y, width, height)
 }
 
+/// Makes an image surface with the contents of `pixbuf`
+pub fn surface_for(pixbuf: &Pixbuf) -> Result<ImageSurface, cairo::Error> {
+    let surface = ImageSurface::create(Format::ARgb32, pixbuf.width(), pixbuf.height())?;
+
+    let cairo = Context::new(&surface)?;
+    cairo.set_source_pixbuf(pixbuf, 0.0, 0.0);
+    cairo.paint()?;
+
+    Ok(surface)
+}
+
 pub trait CairoExt {
     fn set_source_colour(&self, colour: Colour);
 }
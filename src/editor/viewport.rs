@@ -0,0 +1,122 @@
+use std::cell::Cell;
+
+use gtk4::prelude::*;
+
+use super::data::Point;
+
+const MIN_ZOOM: f64 = 0.1;
+const MAX_ZOOM: f64 = 16.0;
+/// How much zooming in or out once changes the zoom level
+const ZOOM_STEP: f64 = 1.25;
+
+/// Keeps track of how much the image is zoomed in inside the editor, and translates the
+/// coordinates we get from gestures on the drawing area into coordinates on the image
+#[derive(Debug)]
+pub struct Viewport {
+    zoom: Cell<f64>,
+    image_width: f64,
+    image_height: f64,
+    drawing_area: gtk4::DrawingArea,
+    scrolled_window: gtk4::ScrolledWindow,
+}
+
+impl Viewport {
+    pub fn new(
+        drawing_area: &gtk4::DrawingArea,
+        scrolled_window: &gtk4::ScrolledWindow,
+        (image_width, image_height): (i32, i32),
+    ) -> Self {
+        Self {
+            zoom: Cell::new(1.0),
+            image_width: image_width as f64,
+            image_height: image_height as f64,
+            drawing_area: drawing_area.clone(),
+            scrolled_window: scrolled_window.clone(),
+        }
+    }
+
+    pub fn zoom(&self) -> f64 {
+        self.zoom.get()
+    }
+
+    /// Translates a point on the drawing area into a point on the image
+    pub fn to_image_space(&self, Point { x, y }: Point) -> Point {
+        let zoom = self.zoom();
+        Point {
+            x: x / zoom,
+            y: y / zoom,
+        }
+    }
+
+    /// Sets the zoom level, while keeping the point that is at the centre of the view in place
+    pub fn set_zoom(&self, zoom: f64) {
+        let old_zoom = self.zoom();
+        let zoom = zoom.clamp(MIN_ZOOM, MAX_ZOOM);
+        self.zoom.set(zoom);
+
+        let new_width = self.image_width * zoom;
+        let new_height = self.image_height * zoom;
+        self.drawing_area.set_content_width(new_width.ceil() as i32);
+        self.drawing_area
+            .set_content_height(new_height.ceil() as i32);
+
+        for (adjustment, new_upper) in [
+            (self.scrolled_window.hadjustment(), new_width),
+            (self.scrolled_window.vadjustment(), new_height),
+        ] {
+            let adjustment = match adjustment {
+                Some(adjustment) => adjustment,
+                None => continue,
+            };
+
+            let centre = (adjustment.value() + adjustment.page_size() / 2.0) / old_zoom;
+            // The upper bound would only be updated on the next allocation, so we set it ourselves
+            // in order for the new value to not be clamped to the old bounds
+            adjustment.set_upper(new_upper);
+            adjustment.set_value(centre * zoom - adjustment.page_size() / 2.0);
+        }
+
+        self.drawing_area.queue_draw();
+    }
+
+    pub fn zoom_in(&self) {
+        self.set_zoom(self.zoom() * ZOOM_STEP);
+    }
+
+    pub fn zoom_out(&self) {
+        self.set_zoom(self.zoom() / ZOOM_STEP);
+    }
+
+    /// Zooms such that the whole image is visible
+    pub fn zoom_to_fit(&self) {
+        let width = self.scrolled_window.width() as f64;
+        let height = self.scrolled_window.height() as f64;
+
+        if width > 0.0 && height > 0.0 {
+            self.set_zoom((width / self.image_width).min(height / self.image_height));
+        }
+    }
+
+    /// Returns the current scroll position, to be passed to [`Viewport::pan_from`]
+    pub fn scroll_position(&self) -> (f64, f64) {
+        (
+            self.scrolled_window
+                .hadjustment()
+                .map_or(0.0, |adjustment| adjustment.value()),
+            self.scrolled_window
+                .vadjustment()
+                .map_or(0.0, |adjustment| adjustment.value()),
+        )
+    }
+
+    /// Scrolls the view such that it's moved by (`dx`, `dy`) relative to `start`, which was
+    /// obtained through [`Viewport::scroll_position`]
+    pub fn pan_from(&self, (start_x, start_y): (f64, f64), dx: f64, dy: f64) {
+        if let Some(adjustment) = self.scrolled_window.hadjustment() {
+            adjustment.set_value(start_x - dx);
+        }
+        if let Some(adjustment) = self.scrolled_window.vadjustment() {
+            adjustment.set_value(start_y - dy);
+        }
+    }
+}
@@ -0,0 +1,315 @@
+//! Exporting screenshots from the history, along with everything we know about them, so that
+//! they can be handed over to somebody else
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Seek, Write},
+    path::Path,
+    str::FromStr,
+};
+
+use chrono::{Datelike, Local, Timelike};
+use serde::Serialize;
+use zip::{result::ZipError, write::FileOptions, CompressionMethod, ZipWriter};
+
+use crate::db::models::Screenshot;
+
+/// The columns of the CSV export, in order, these are the keys of the serialized [`Entry`]
+const CSV_COLUMNS: &[&str] = &[
+    "id",
+    "path",
+    "time",
+    "url",
+    "project_path",
+    "width",
+    "height",
+    "size",
+    "format",
+    "hash",
+    "capture_mode",
+    "window_title",
+    "window_class",
+    "monitor",
+    "is_favourite",
+    "tags",
+];
+
+/// The name of the file in the archive which describes the screenshots in it
+const MANIFEST_NAME: &str = "manifest.json";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Csv,
+    /// A zip archive which contains the images and a JSON manifest
+    Zip,
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("Unknown export format: {0}, expected json, csv or zip")]
+pub struct UnknownFormat(String);
+
+impl FromStr for Format {
+    type Err = UnknownFormat;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "json" => Ok(Format::Json),
+            "csv" => Ok(Format::Csv),
+            "zip" => Ok(Format::Zip),
+            _ => Err(UnknownFormat(s.into())),
+        }
+    }
+}
+
+impl Format {
+    /// Picks the format by the extension of `path`
+    pub fn for_path(path: &Path) -> Result<Self, UnknownFormat> {
+        path.extension()
+            .map(|extension| extension.to_string_lossy())
+            .unwrap_or_default()
+            .parse()
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("Failed to write the export: {0}")]
+    Io(#[from] io::Error),
+    #[error("Failed to serialize the screenshots: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Failed to write the archive: {0}")]
+    Zip(#[from] ZipError),
+    #[error("Failed to query the database: {0}")]
+    Query(#[from] diesel::result::Error),
+    #[error(transparent)]
+    UnknownFormat(#[from] UnknownFormat),
+}
+
+/// A screenshot as it gets exported
+#[derive(Serialize, Debug)]
+pub struct Entry {
+    #[serde(flatten)]
+    pub screenshot: Screenshot,
+    pub tags: Vec<String>,
+    /// Where the image is in the archive, only set for archives
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub archive_path: Option<String>,
+}
+
+impl Entry {
+    pub fn new(screenshot: Screenshot, tags: Vec<String>) -> Self {
+        Self {
+            screenshot,
+            tags,
+            archive_path: None,
+        }
+    }
+}
+
+/// Exports `entries` to the file at `path` in the format its extension asks for
+///
+/// # Returns
+/// How many screenshots were exported
+pub fn export(entries: Vec<Entry>, path: &Path) -> Result<usize, Error> {
+    let format = Format::for_path(path)?;
+    let out = BufWriter::new(File::create(path)?);
+    let n_entries = entries.len();
+
+    match format {
+        Format::Json => write_json(&entries, out),
+        Format::Csv => write_csv(&entries, out),
+        Format::Zip => write_zip(entries, out),
+    }?;
+
+    Ok(n_entries)
+}
+
+fn write_json(entries: &[Entry], mut out: impl Write) -> Result<(), Error> {
+    serde_json::to_writer_pretty(&mut out, entries)?;
+    out.flush()?;
+
+    Ok(())
+}
+
+fn write_csv(entries: &[Entry], mut out: impl Write) -> Result<(), Error> {
+    writeln!(out, "{}", CSV_COLUMNS.join(","))?;
+
+    for entry in entries {
+        let entry = serde_json::to_value(entry)?;
+        let fields = CSV_COLUMNS
+            .iter()
+            .map(|column| match &entry[*column] {
+                serde_json::Value::Null => String::new(),
+                serde_json::Value::String(string) => csv_field(string),
+                serde_json::Value::Array(tags) => csv_field(
+                    &tags
+                        .iter()
+                        .filter_map(|tag| tag.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                ),
+                value => value.to_string(),
+            })
+            .collect::<Vec<_>>();
+        writeln!(out, "{}", fields.join(","))?;
+    }
+    out.flush()?;
+
+    Ok(())
+}
+
+/// Quotes `field` if it has to be, as RFC 4180 says
+fn csv_field(field: &str) -> String {
+    if field.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+fn write_zip(mut entries: Vec<Entry>, out: impl Write + Seek) -> Result<(), Error> {
+    let mut zip = ZipWriter::new(out);
+    // What we put in archives is mostly PNGs, which don't get any smaller anyway
+    let now = Local::now();
+    let mut options = FileOptions::default().compression_method(CompressionMethod::Stored);
+    if let Ok(time) = zip::DateTime::from_date_and_time(
+        now.year() as u16,
+        now.month() as u8,
+        now.day() as u8,
+        now.hour() as u8,
+        now.minute() as u8,
+        now.second() as u8,
+    ) {
+        options = options.last_modified_time(time);
+    }
+
+    for entry in &mut entries {
+        let path = match &entry.screenshot.path {
+            Some(path) => Path::new(path),
+            None => continue,
+        };
+        let mut file = match File::open(path) {
+            Ok(file) => file,
+            Err(why) => {
+                tracing::warn!("Failed to add {:?} to the archive: {}", path, why);
+                continue;
+            }
+        };
+
+        // The id keeps screenshots which have the same file name apart
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        let archive_path = format!("images/{}-{}", entry.screenshot.id, file_name);
+        zip.start_file(archive_path.as_str(), options)?;
+        io::copy(&mut file, &mut zip)?;
+        entry.archive_path = Some(archive_path);
+    }
+
+    zip.start_file(MANIFEST_NAME, options)?;
+    serde_json::to_writer_pretty(&mut zip, &entries)?;
+    zip.finish()?.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(window_title: &str, tags: &[&str]) -> Entry {
+        Entry::new(
+            Screenshot {
+                id: 7,
+                path: Some("/home/kc/Pictures/screenshot.png".to_owned()),
+                time: "2021-09-04T15:02:11+03:00".to_owned(),
+                url: None,
+                project_path: None,
+                width: Some(1920),
+                height: Some(1080),
+                size: Some(123_456),
+                format: Some("png".to_owned()),
+                hash: None,
+                capture_mode: Some("window".to_owned()),
+                window_title: Some(window_title.to_owned()),
+                window_class: None,
+                monitor: Some("DP-1".to_owned()),
+                is_favourite: true,
+                taken_at: "2021-09-04T12:02:11.000Z".to_owned(),
+            },
+            tags.iter().map(|tag| (*tag).to_owned()).collect(),
+        )
+    }
+
+    #[test]
+    fn plain_csv_fields_are_not_quoted() {
+        assert_eq!(csv_field(""), "");
+        assert_eq!(csv_field("kcshot — a screenshot"), "kcshot — a screenshot");
+    }
+
+    #[test]
+    fn csv_fields_with_separators_or_quotes_are_quoted() {
+        assert_eq!(csv_field("a, b"), "\"a, b\"");
+        assert_eq!(csv_field("say \"cheese\""), "\"say \"\"cheese\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+        assert_eq!(csv_field("two\r\nlines"), "\"two\r\nlines\"");
+        assert_eq!(csv_field("\",\n"), "\"\"\",\n\"");
+    }
+
+    #[test]
+    fn csv_rows_follow_the_columns() {
+        let mut out = vec![];
+        write_csv(
+            &[entry("\"Notes\", draft\nsecond line", &["work", "to do"])],
+            &mut out,
+        )
+        .unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!(
+                "{}\n{}\n",
+                CSV_COLUMNS.join(","),
+                "7,/home/kc/Pictures/screenshot.png,2021-09-04T15:02:11+03:00,,,1920,1080,123456,\
+                 png,,window,\"\"\"Notes\"\", draft\nsecond line\",,DP-1,true,\"work, to do\""
+            )
+        );
+    }
+
+    #[test]
+    fn zip_archives_have_the_images_and_a_manifest() {
+        let image_path =
+            std::env::temp_dir().join(format!("kcshot-export-{}.png", std::process::id()));
+        std::fs::write(&image_path, b"not really a PNG").unwrap();
+        let mut exported = entry("Exported", &[]);
+        exported.screenshot.path = Some(image_path.to_string_lossy().into_owned());
+        // Its file doesn't exist, so only the manifest has it
+        let mut missing = entry("Missing", &[]);
+        missing.screenshot.id = 8;
+
+        let mut out = io::Cursor::new(vec![]);
+        let res = write_zip(vec![exported, missing], &mut out);
+        std::fs::remove_file(&image_path).unwrap();
+        res.unwrap();
+
+        let mut archive = zip::ZipArchive::new(out).unwrap();
+        assert_eq!(archive.len(), 2);
+
+        let image_name = format!(
+            "images/7-{}",
+            image_path.file_name().unwrap().to_string_lossy()
+        );
+        let mut image = archive.by_name(&image_name).unwrap();
+        assert_eq!(image.compression(), CompressionMethod::Stored);
+        let mut contents = vec![];
+        io::Read::read_to_end(&mut image, &mut contents).unwrap();
+        assert_eq!(contents, b"not really a PNG");
+        drop(image);
+
+        let manifest: serde_json::Value =
+            serde_json::from_reader(archive.by_name(MANIFEST_NAME).unwrap()).unwrap();
+        assert_eq!(manifest[0]["window_title"], "Exported");
+        assert_eq!(manifest[0]["archive_path"], image_name.as_str());
+        assert_eq!(manifest[1]["window_title"], "Missing");
+        assert_eq!(manifest[1]["archive_path"], serde_json::Value::Null);
+    }
+}
@@ -1,14 +1,35 @@
-use diesel::SqliteConnection;
+use std::path::Path;
+
+use diesel::{QueryResult, SqliteConnection};
 use gtk4::{
-    gio::ListModel as GListModel, glib, prelude::ListModelExt, subclass::prelude::ObjectSubclassExt,
+    gio::ListModel as GListModel,
+    glib::{self, prelude::ObjectExt},
+    prelude::ListModelExt,
+    subclass::prelude::ObjectSubclassExt,
 };
 
-use crate::{db, kcshot::KCShot};
+use crate::{
+    db::{
+        self,
+        models::{NewScreenshot, Screenshot},
+    },
+    export,
+    import::{self, Scan},
+    integrity,
+    kcshot::KCShot,
+};
 
+mod cache;
+mod deletion;
+mod retention;
 mod rowdata;
 
+pub use deletion::DeletionMode;
+pub use retention::{RetentionPolicy, CLEANUP_INTERVAL_SECONDS};
 pub use rowdata::RowData;
 
+use self::deletion::{DeletedScreenshot, Deletion};
+
 glib::wrapper! {
     pub struct HistoryModel(ObjectSubclass<underlying::ListModel>)
         @implements GListModel;
@@ -20,41 +41,484 @@ impl HistoryModel {
         glib::Object::new(&[("application", app)]).unwrap()
     }
 
-    pub fn add_item_to_history(
+    pub fn add_item_to_history(&self, conn: &SqliteConnection, screenshot: &NewScreenshot) {
+        match db::add_screenshot_to_history(conn, screenshot) {
+            Ok(id) => self.show_if_filtered_in(conn, id),
+            Err(why) => tracing::error!("Failed to add screenshot to history: {:?}", why),
+        }
+    }
+
+    /// Adds the screenshots found by [`import::scan`] to the history, those whose hash shows
+    /// that they're already in it are skipped
+    pub fn import(&self, scan: Scan) -> import::Summary {
+        let impl_ = underlying::ListModel::from_instance(self);
+        let app = impl_.app.borrow();
+        let conn = app.conn();
+
+        let known =
+            db::all_hashes(conn).and_then(|hashes| Ok((hashes, db::paths_without_hash(conn)?)));
+        let (mut known_hashes, unhashed_paths) = match known {
+            Ok(known) => known,
+            Err(why) => {
+                tracing::error!(
+                    "Failed to look up what is in the history already: {:?}",
+                    why
+                );
+                return import::Summary {
+                    failed: scan.failed + scan.screenshots.len(),
+                    ..Default::default()
+                };
+            }
+        };
+        let n_scanned = scan.screenshots.len();
+        let screenshots = scan
+            .screenshots
+            .into_iter()
+            .filter(|screenshot| {
+                // Screenshots from before we hashed them can only be recognised by their path
+                let known_path =
+                    matches!(&screenshot.path, Some(path) if unhashed_paths.contains(path));
+                match &screenshot.hash {
+                    Some(hash) => !known_path && known_hashes.insert(hash.clone()),
+                    None => !known_path,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let mut summary = import::Summary {
+            imported: screenshots.len(),
+            duplicates: n_scanned - screenshots.len(),
+            failed: scan.failed,
+        };
+        if screenshots.is_empty() {
+            return summary;
+        }
+
+        let old_n_items = self.n_items();
+        if let Err(why) = db::add_screenshots_to_history(conn, &screenshots) {
+            tracing::error!(
+                "Failed to add the imported screenshots to history: {:?}",
+                why
+            );
+            summary.failed += summary.imported;
+            summary.imported = 0;
+            return summary;
+        }
+        impl_.cache.borrow_mut().invalidate();
+        self.items_changed(0, old_n_items, self.n_items());
+
+        summary
+    }
+
+    /// Looks up everything which gets exported about the screenshots in `rows`, the export itself
+    /// is left to [`export::export`] so that it can be done on another thread
+    pub fn export_entries(&self, rows: &[RowData]) -> QueryResult<Vec<export::Entry>> {
+        let impl_ = underlying::ListModel::from_instance(self);
+        let app = impl_.app.borrow();
+
+        let screenshots = rows
+            .iter()
+            .map(|row_data| db::fetch_screenshot(app.conn(), row_data.id()))
+            .collect::<QueryResult<Vec<_>>>()?;
+        export_entries(app.conn(), screenshots)
+    }
+
+    /// Like [`HistoryModel::export_entries`], for all the screenshots which the filter lets through
+    pub fn filtered_export_entries(&self) -> QueryResult<Vec<export::Entry>> {
+        let impl_ = underlying::ListModel::from_instance(self);
+        let filter = impl_.filter.borrow();
+
+        self.export_entries_matching(&filter)
+    }
+
+    /// Like [`HistoryModel::export_entries`], for all the screenshots which `filter` lets through,
+    /// regardless of the filter the model has
+    pub fn export_entries_matching(&self, filter: &db::Filter) -> QueryResult<Vec<export::Entry>> {
+        let impl_ = underlying::ListModel::from_instance(self);
+        let app = impl_.app.borrow();
+
+        let count = db::number_of_history_itms(app.conn(), filter)?;
+        let screenshots = db::fetch_screenshots(app.conn(), filter, 0, count)?;
+        export_entries(app.conn(), screenshots)
+    }
+
+    /// Deletes the screenshots from the history, `mode` decides what happens to their files.
+    /// Unless the files were deleted permanently, this can be undone through
+    /// [`HistoryModel::undo_last_deletion`]
+    pub fn delete(&self, rows: &[RowData], mode: DeletionMode) {
+        let impl_ = underlying::ListModel::from_instance(self);
+        let app = impl_.app.borrow();
+        let conn = app.conn();
+
+        // The items are removed starting from the last one, so that the positions of the ones
+        // which are yet to be removed don't change, those which aren't shown come after them
+        let mut positions = rows
+            .iter()
+            .filter_map(|row_data| {
+                match db::position_of(conn, &impl_.filter.borrow(), row_data.id()) {
+                    Ok(position) => Some((position, row_data.id())),
+                    Err(why) => {
+                        tracing::error!("Failed to find screenshot in history: {:?}", why);
+                        None
+                    }
+                }
+            })
+            .collect::<Vec<_>>();
+        positions.sort_unstable_by(|a, b| b.cmp(a));
+        positions.dedup();
+
+        let mut deleted_screenshots = vec![];
+        for (position, id) in positions {
+            let screenshot = match db::fetch_screenshot(conn, id) {
+                Ok(screenshot) => screenshot,
+                Err(why) => {
+                    tracing::error!("Failed to fetch screenshot {}: {:?}", id, why);
+                    continue;
+                }
+            };
+            let tags = db::tags_of(conn, id).unwrap_or_default();
+
+            if let Err(why) = db::delete_screenshot(conn, id) {
+                tracing::error!("Failed to remove screenshot from history: {:?}", why);
+                continue;
+            }
+            if let Some(position) = position {
+                impl_.cache.borrow_mut().remove(position as u32);
+                self.items_changed(position as u32, 1, 0);
+            }
+
+            let trashed_files = deletion::dispose_of(deletion::files_of(&screenshot), mode);
+            deleted_screenshots.push(DeletedScreenshot {
+                screenshot,
+                tags,
+                trashed_files,
+            });
+        }
+
+        let n_deleted = deleted_screenshots.len() as u32;
+        let can_undo = mode != DeletionMode::Permanent && n_deleted > 0;
+        impl_.last_deletion.replace(can_undo.then_some(Deletion {
+            screenshots: deleted_screenshots,
+        }));
+
+        drop(app);
+        if n_deleted > 0 {
+            self.emit_by_name("screenshots-deleted", &[&n_deleted, &can_undo])
+                .expect("HistoryModel should have a screenshots-deleted signal");
+        }
+    }
+
+    /// Puts the screenshots deleted by the last call to [`HistoryModel::delete`] back into the
+    /// history, files which were moved to the trash are restored as well
+    pub fn undo_last_deletion(&self) {
+        let impl_ = underlying::ListModel::from_instance(self);
+        let deletion = match impl_.last_deletion.take() {
+            Some(deletion) => deletion,
+            None => {
+                tracing::info!("There is no deletion to undo");
+                return;
+            }
+        };
+        let app = impl_.app.borrow();
+        let conn = app.conn();
+
+        // Restoring starts from the oldest screenshot, which is the one that comes last
+        for deleted in deletion.screenshots.iter().rev() {
+            for file in &deleted.trashed_files {
+                if let Err(why) = deletion::restore_from_trash(file) {
+                    tracing::error!("Failed to restore {:?} from the trash: {}", file, why);
+                }
+            }
+
+            if let Err(why) = db::restore_screenshot(conn, &deleted.screenshot, &deleted.tags) {
+                tracing::error!("Failed to put screenshot back into the history: {:?}", why);
+                continue;
+            }
+            // It may not show up if the filter changed since it was deleted
+            self.show_if_filtered_in(conn, deleted.screenshot.id);
+        }
+    }
+
+    /// Returns the screenshots which `policy` says should be deleted, from newest to oldest
+    pub fn expired_screenshots(&self, policy: &RetentionPolicy) -> Vec<RowData> {
+        let impl_ = underlying::ListModel::from_instance(self);
+
+        let screenshots = match db::all_screenshots(impl_.app.borrow().conn()) {
+            Ok(screenshots) => screenshots,
+            Err(why) => {
+                tracing::error!("Failed to fetch the screenshots: {:?}", why);
+                return vec![];
+            }
+        };
+
+        policy
+            .expired(&screenshots, chrono::Local::now())
+            .into_iter()
+            .cloned()
+            .map(RowData::new)
+            .collect()
+    }
+
+    /// Deletes the screenshots which `policy` says should be deleted, their files are deleted
+    /// permanently
+    pub fn apply_retention_policy(&self, policy: &RetentionPolicy) {
+        let expired = self.expired_screenshots(policy);
+        if expired.is_empty() {
+            return;
+        }
+
+        tracing::info!("Cleaning up {} screenshots", expired.len());
+        self.delete(&expired, DeletionMode::Permanent);
+    }
+
+    /// Returns the screenshots whose image isn't where the history says it is, regardless of
+    /// whether the filter lets them through
+    pub fn missing_screenshots(&self) -> Vec<RowData> {
+        let impl_ = underlying::ListModel::from_instance(self);
+
+        let screenshots = match db::all_screenshots(impl_.app.borrow().conn()) {
+            Ok(screenshots) => screenshots,
+            Err(why) => {
+                tracing::error!("Failed to fetch the screenshots: {:?}", why);
+                return vec![];
+            }
+        };
+
+        screenshots
+            .into_iter()
+            .filter(
+                |screenshot| matches!(&screenshot.path, Some(path) if !Path::new(path).exists()),
+            )
+            .map(RowData::new)
+            .collect()
+    }
+
+    /// Points each screenshot in `rows` at its image in `index`, see [`integrity::Index::find`]
+    pub fn relink(&self, rows: &[RowData], index: &integrity::Index) -> integrity::Summary {
+        let impl_ = underlying::ListModel::from_instance(self);
+        let app = impl_.app.borrow();
+        let conn = app.conn();
+
+        let mut summary = integrity::Summary::default();
+        for row_data in rows {
+            let (path, hash) = (row_data.path().unwrap_or_default(), row_data.hash());
+            let lost = integrity::Lost {
+                path: Path::new(&path),
+                hash: hash.as_deref(),
+                size: row_data.size(),
+                dimensions: row_data.dimensions(),
+            };
+            let new_path = match index.find(&lost) {
+                Some(new_path) => new_path.to_string_lossy(),
+                None => {
+                    if !lost.is_recognisable() {
+                        summary.unmatchable += 1;
+                    }
+                    continue;
+                }
+            };
+            if let Err(why) = db::set_path(conn, row_data.id(), &new_path) {
+                tracing::error!("Failed to relink screenshot: {:?}", why);
+                continue;
+            }
+            row_data
+                .set_property("path", new_path.as_ref())
+                .expect("RowData should have a path property");
+            summary.relinked += 1;
+
+            // The grid only picks up the new path when the item is bound again
+            match db::position_of(conn, &impl_.filter.borrow(), row_data.id()) {
+                Ok(Some(position)) => {
+                    let mut cache = impl_.cache.borrow_mut();
+                    cache.remove(position as u32);
+                    cache.insert(position as u32, row_data.clone());
+                    drop(cache);
+                    self.items_changed(position as u32, 1, 1);
+                }
+                Ok(None) => {}
+                Err(why) => tracing::error!("Failed to find screenshot in history: {:?}", why),
+            }
+        }
+
+        summary
+    }
+
+    /// `callback` is called with the number of screenshots that were deleted and whether the
+    /// deletion can be undone, whenever screenshots are deleted from the history
+    pub fn connect_screenshots_deleted<F: Fn(&Self, u32, bool) + 'static>(
         &self,
-        conn: &SqliteConnection,
-        path_: Option<String>,
-        time_: String,
-        url_: Option<String>,
-    ) {
-        if let Err(why) = db::add_screenshot_to_history(conn, path_, time_, url_) {
-            tracing::error!("Failed to add screenshot to history: {:?}", why);
+        callback: F,
+    ) -> glib::SignalHandlerId {
+        self.connect_local("screenshots-deleted", false, move |values| {
+            let this = values[0].get::<Self>().unwrap();
+            let n_deleted = values[1].get::<u32>().unwrap();
+            let can_undo = values[2].get::<bool>().unwrap();
+            callback(&this, n_deleted, can_undo);
+
+            None
+        })
+        .expect("HistoryModel should have a screenshots-deleted signal")
+    }
+
+    /// Whether the screenshot is still in the history, regardless of whether the filter lets it
+    /// through
+    pub fn contains(&self, row_data: &RowData) -> bool {
+        let impl_ = underlying::ListModel::from_instance(self);
+
+        db::fetch_screenshot(impl_.app.borrow().conn(), row_data.id()).is_ok()
+    }
+
+    /// Marks the screenshot as a favourite, or stops it from being one
+    pub fn set_favourite(&self, row_data: &RowData, favourite: bool) {
+        let changed = self.change_screenshot(row_data, |conn| {
+            db::set_favourite(conn, row_data.id(), favourite)
+        });
+
+        if changed {
+            row_data
+                .set_property("is-favourite", favourite)
+                .expect("RowData should have an is-favourite property");
+        }
+    }
+
+    /// Returns the tags of the screenshot, sorted by name
+    pub fn tags_of(&self, row_data: &RowData) -> Vec<String> {
+        let impl_ = underlying::ListModel::from_instance(self);
+
+        db::tags_of(impl_.app.borrow().conn(), row_data.id()).unwrap_or_else(|why| {
+            tracing::error!("Failed to fetch the tags of a screenshot: {:?}", why);
+            vec![]
+        })
+    }
+
+    /// Returns all tags that are used by at least one screenshot, sorted by name
+    pub fn all_tags(&self) -> Vec<String> {
+        let impl_ = underlying::ListModel::from_instance(self);
+
+        db::all_tags(impl_.app.borrow().conn()).unwrap_or_else(|why| {
+            tracing::error!("Failed to fetch the tags: {:?}", why);
+            vec![]
+        })
+    }
+
+    pub fn add_tag(&self, row_data: &RowData, tag: &str) {
+        self.change_screenshot(row_data, |conn| db::add_tag(conn, row_data.id(), tag));
+    }
+
+    pub fn remove_tag(&self, row_data: &RowData, tag: &str) {
+        self.change_screenshot(row_data, |conn| db::remove_tag(conn, row_data.id(), tag));
+    }
+
+    /// Returns the filter which decides what screenshots are shown
+    pub fn filter(&self) -> db::Filter {
+        let impl_ = underlying::ListModel::from_instance(self);
+
+        impl_.filter.borrow().clone()
+    }
+
+    /// Only show the screenshots which match `filter` from now on
+    pub fn set_filter(&self, filter: db::Filter) {
+        let impl_ = underlying::ListModel::from_instance(self);
+        if *impl_.filter.borrow() == filter {
             return;
         }
+
+        let old_n_items = self.n_items();
+        impl_.filter.replace(filter);
+        impl_.cache.borrow_mut().invalidate();
+        self.items_changed(0, old_n_items, self.n_items())
+    }
+
+    /// Applies `change`, which changes the screenshot in `row_data`, to the database. The
+    /// screenshot is shown or hidden afterwards if the change made the filter decide differently
+    /// about it.
+    ///
+    /// # Returns
+    /// Whether the change was successful
+    fn change_screenshot(
+        &self,
+        row_data: &RowData,
+        change: impl FnOnce(&SqliteConnection) -> QueryResult<()>,
+    ) -> bool {
+        let impl_ = underlying::ListModel::from_instance(self);
+        let app = impl_.app.borrow();
+        let conn = app.conn();
+
+        let old_position = db::position_of(conn, &impl_.filter.borrow(), row_data.id());
+        if let Err(why) = change(conn) {
+            tracing::error!("Failed to change a screenshot in the history: {:?}", why);
+            return false;
+        }
+
+        match old_position {
+            Ok(Some(position)) => {
+                let new_position = db::position_of(conn, &impl_.filter.borrow(), row_data.id());
+                if let Ok(None) = new_position {
+                    impl_.cache.borrow_mut().remove(position as u32);
+                    self.items_changed(position as u32, 1, 0);
+                }
+            }
+            Ok(None) => self.show_if_filtered_in(conn, row_data.id()),
+            Err(why) => tracing::error!("Failed to find screenshot in history: {:?}", why),
+        }
+
+        true
+    }
+
+    /// Tells the views about the screenshot with the id `id`, which was just added to the
+    /// database, if the filter lets it through
+    fn show_if_filtered_in(&self, conn: &SqliteConnection, id: i32) {
         let impl_ = underlying::ListModel::from_instance(self);
-        impl_.screenshots.borrow_mut().clear();
-        self.items_changed(0, 0, 1)
+
+        let screenshot = db::position_of(conn, &impl_.filter.borrow(), id)
+            .and_then(|position| Ok((position, db::fetch_screenshot(conn, id)?)));
+        match screenshot {
+            Ok((Some(position), screenshot)) => {
+                impl_
+                    .cache
+                    .borrow_mut()
+                    .insert(position as u32, RowData::new(screenshot));
+                self.items_changed(position as u32, 0, 1);
+            }
+            Ok((None, _)) => {}
+            Err(why) => tracing::error!("Failed to find screenshot in history: {:?}", why),
+        }
     }
 }
 
+fn export_entries(
+    conn: &SqliteConnection,
+    screenshots: Vec<Screenshot>,
+) -> QueryResult<Vec<export::Entry>> {
+    screenshots
+        .into_iter()
+        .map(|screenshot| {
+            let tags = db::tags_of(conn, screenshot.id)?;
+            Ok(export::Entry::new(screenshot, tags))
+        })
+        .collect()
+}
+
 mod underlying {
     use std::cell::RefCell;
 
     use gtk4::{
         gio,
-        glib::{self, Object, ParamSpec, StaticType, ToValue, Value},
+        glib::{self, subclass::Signal, Object, ParamSpec, StaticType, ToValue, Value},
         prelude::*,
         subclass::prelude::*,
     };
     use once_cell::sync::Lazy;
 
-    use super::rowdata::RowData;
+    use super::{cache::Cache, deletion::Deletion, rowdata::RowData};
     use crate::{db, kcshot::KCShot};
 
     #[derive(Default)]
     pub struct ListModel {
-        app: RefCell<KCShot>,
-        pub(super) screenshots: RefCell<Vec<RowData>>,
+        pub(super) app: RefCell<KCShot>,
+        pub(super) cache: RefCell<Cache>,
+        pub(super) filter: RefCell<db::Filter>,
+        pub(super) last_deletion: RefCell<Option<Deletion>>,
     }
 
     #[glib::object_subclass]
@@ -71,16 +535,12 @@ mod underlying {
         }
 
         fn n_items(&self, _list_model: &Self::Type) -> u32 {
-            let n_items = db::number_of_history_itms(self.app.borrow().conn());
+            let n_items = self
+                .cache
+                .borrow_mut()
+                .len(self.app.borrow().conn(), &self.filter.borrow());
             match n_items {
-                Ok(n_items) => {
-                    assert!(
-                        0 <= n_items && n_items <= u32::MAX as i64,
-                        "n_items was {} which is not within [0, i32::MAX]",
-                        n_items
-                    );
-                    n_items as u32
-                }
+                Ok(n_items) => n_items,
                 Err(why) => {
                     tracing::error!("Failed to get number of screenshots in history: {:?}", why);
                     panic!() // yolo
@@ -90,49 +550,39 @@ mod underlying {
 
         #[tracing::instrument(skip(self))]
         fn item(&self, _: &Self::Type, position: u32) -> Option<glib::Object> {
-            let last_fetched_screenshot_index = {
-                let len = self.screenshots.borrow().len();
-                if len > 0 {
-                    len - 1
-                } else {
-                    0
+            let row_data = self.cache.borrow_mut().get(
+                self.app.borrow().conn(),
+                &self.filter.borrow(),
+                position,
+            );
+            match row_data {
+                Ok(row_data) => row_data.map(|row_data| row_data.upcast()),
+                Err(why) => {
+                    tracing::error!(
+                        "Failed to fetch the screenshot at {} from the database: {:?}",
+                        position,
+                        why
+                    );
+                    None
                 }
-            };
-
-            tracing::info!("{}", last_fetched_screenshot_index);
-
-            if position as usize > last_fetched_screenshot_index
-                || last_fetched_screenshot_index == 0
-            {
-                tracing::info!("entered");
-                const COUNT: i64 = 15;
-                let new_screenshots = db::fetch_screenshots(
-                    self.app.borrow().conn(),
-                    last_fetched_screenshot_index as i64,
-                    COUNT,
-                );
-                let new_screenshots = match new_screenshots {
-                    Ok(n) => n,
-                    Err(why) => {
-                        tracing::error!("Encountered error: {:?}\n\twhile trying to fetch {} items from the database,\n\tstarting at index: {}", why, COUNT, last_fetched_screenshot_index);
-                        return None;
-                    }
-                };
-
-                self.screenshots
-                    .borrow_mut()
-                    .extend(new_screenshots.into_iter().map(RowData::new));
             }
-
-            self.screenshots
-                .borrow()
-                .get(position as usize)
-                .cloned()
-                .map(|o| o.upcast())
         }
     }
 
     impl ObjectImpl for ListModel {
+        fn signals() -> &'static [Signal] {
+            static SIGNALS: Lazy<Vec<Signal>> = Lazy::new(|| {
+                vec![Signal::builder(
+                    "screenshots-deleted",
+                    &[u32::static_type().into(), bool::static_type().into()],
+                    <()>::static_type().into(),
+                )
+                .build()]
+            });
+
+            SIGNALS.as_ref()
+        }
+
         fn properties() -> &'static [ParamSpec] {
             static PROPERTIES: Lazy<Vec<ParamSpec>> = Lazy::new(|| {
                 vec![ParamSpec::new_object(
@@ -0,0 +1,93 @@
+use diesel::{QueryResult, SqliteConnection};
+
+use super::rowdata::RowData;
+use crate::db::{self, Filter};
+
+/// How many screenshots are fetched from the database at once
+const PAGE_SIZE: usize = 32;
+
+/// The screenshots shown by the model which were already fetched from the database, along with
+/// how many there are in total. Screenshots are fetched a page at a time, whichever page holds
+/// the one that is asked for, so the grid can jump around without fetching everything in between.
+#[derive(Default)]
+pub(super) struct Cache {
+    /// Has a slot for each screenshot which the filter lets through, `None` until the screenshot
+    /// was fetched. The whole thing is `None` when we don't even know how many there are.
+    screenshots: Option<Vec<Option<RowData>>>,
+}
+
+impl Cache {
+    /// Forgets everything, the next access fetches from the database again
+    pub(super) fn invalidate(&mut self) {
+        self.screenshots = None;
+    }
+
+    pub(super) fn len(&mut self, conn: &SqliteConnection, filter: &Filter) -> QueryResult<u32> {
+        Ok(self.slots(conn, filter)?.len() as u32)
+    }
+
+    pub(super) fn get(
+        &mut self,
+        conn: &SqliteConnection,
+        filter: &Filter,
+        position: u32,
+    ) -> QueryResult<Option<RowData>> {
+        let position = position as usize;
+        let slots = self.slots(conn, filter)?;
+        if position >= slots.len() {
+            return Ok(None);
+        }
+        if let Some(row_data) = &slots[position] {
+            return Ok(Some(row_data.clone()));
+        }
+
+        let page_start = position - position % PAGE_SIZE;
+        let previous_id = page_start
+            .checked_sub(1)
+            .and_then(|previous| slots[previous].as_ref())
+            .map(RowData::id);
+        let screenshots = match previous_id {
+            Some(previous_id) => {
+                db::fetch_screenshots_after(conn, filter, previous_id, PAGE_SIZE as i64)?
+            }
+            None => db::fetch_screenshots(conn, filter, page_start as i64, PAGE_SIZE as i64)?,
+        };
+
+        // Screenshots which were fetched already may be shown somewhere, so those are kept
+        for (slot, screenshot) in slots[page_start..].iter_mut().zip(screenshots) {
+            if slot.is_none() {
+                *slot = Some(RowData::new(screenshot));
+            }
+        }
+
+        Ok(slots[position].clone())
+    }
+
+    /// Makes room for a screenshot which now shows up at `position`, does nothing if we don't
+    /// know anything yet
+    pub(super) fn insert(&mut self, position: u32, row_data: RowData) {
+        if let Some(slots) = &mut self.screenshots {
+            slots.insert(position as usize, Some(row_data));
+        }
+    }
+
+    /// Forgets about the screenshot at `position`, which no longer shows up
+    pub(super) fn remove(&mut self, position: u32) {
+        if let Some(slots) = &mut self.screenshots {
+            slots.remove(position as usize);
+        }
+    }
+
+    fn slots(
+        &mut self,
+        conn: &SqliteConnection,
+        filter: &Filter,
+    ) -> QueryResult<&mut Vec<Option<RowData>>> {
+        if self.screenshots.is_none() {
+            let len = db::number_of_history_itms(conn, filter)?;
+            self.screenshots = Some(vec![None; len as usize]);
+        }
+
+        Ok(self.screenshots.as_mut().unwrap())
+    }
+}
@@ -0,0 +1,127 @@
+use std::path::{Path, PathBuf};
+
+use gtk4::{gio, prelude::*};
+
+use crate::{db::models::Screenshot, editor::Project};
+
+/// What happens to the files of screenshots which are deleted from the history
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeletionMode {
+    /// The files are left where they are, only the history forgets about them
+    KeepFiles,
+    /// The files are moved to the trash, from where they can be restored
+    Trash,
+    /// The files are deleted, this can't be undone
+    Permanent,
+}
+
+/// Everything needed to undo a deletion
+pub(super) struct Deletion {
+    pub(super) screenshots: Vec<DeletedScreenshot>,
+}
+
+pub(super) struct DeletedScreenshot {
+    pub(super) screenshot: Screenshot,
+    pub(super) tags: Vec<String>,
+    /// The paths the files which were moved to the trash had before that
+    pub(super) trashed_files: Vec<PathBuf>,
+}
+
+/// Returns the files which belong to `screenshot` and exist: the image and, if it has one, the
+/// files of its project
+pub(super) fn files_of(screenshot: &Screenshot) -> Vec<PathBuf> {
+    let mut files = vec![];
+
+    if let Some(path) = &screenshot.path {
+        files.push(PathBuf::from(path));
+    }
+    if let Some(project_path) = &screenshot.project_path {
+        match Project::files(Path::new(project_path)) {
+            Ok(project_files) => files.extend(project_files),
+            Err(why) => {
+                tracing::warn!(
+                    "Failed to find the files of project {}: {}",
+                    project_path,
+                    why
+                );
+                files.push(PathBuf::from(project_path));
+            }
+        }
+    }
+
+    files.retain(|file| file.exists());
+    files
+}
+
+/// Gets rid of `files` as `mode` says to
+///
+/// # Returns
+/// The files which were moved to the trash
+pub(super) fn dispose_of(files: Vec<PathBuf>, mode: DeletionMode) -> Vec<PathBuf> {
+    match mode {
+        DeletionMode::KeepFiles => vec![],
+        DeletionMode::Trash => files
+            .into_iter()
+            .filter(
+                |file| match gio::File::for_path(file).trash(gio::NONE_CANCELLABLE) {
+                    Ok(()) => true,
+                    Err(why) => {
+                        tracing::error!("Failed to move {:?} to the trash: {}", file, why);
+                        false
+                    }
+                },
+            )
+            .collect(),
+        DeletionMode::Permanent => {
+            for file in files {
+                if let Err(why) = std::fs::remove_file(&file) {
+                    tracing::error!("Failed to delete {:?}: {}", file, why);
+                }
+            }
+            vec![]
+        }
+    }
+}
+
+/// Moves the file which was at `original_path` before being trashed back there, if it was trashed
+/// more than once the most recently trashed one is restored
+pub(super) fn restore_from_trash(original_path: &Path) -> Result<(), gtk4::glib::Error> {
+    let trash = gio::File::for_uri("trash:///");
+    let children = trash.enumerate_children(
+        "standard::name,trash::orig-path,trash::deletion-date",
+        gio::FileQueryInfoFlags::NONE,
+        gio::NONE_CANCELLABLE,
+    )?;
+
+    let mut newest = None;
+    for info in children.clone() {
+        let info = info?;
+        let orig_path = info.attribute_byte_string("trash::orig-path");
+        if orig_path.as_deref().map(Path::new) != Some(original_path) {
+            continue;
+        }
+
+        // The deletion date is in ISO 8601, so comparing the strings orders them by time
+        let deletion_date = info
+            .attribute_string("trash::deletion-date")
+            .map(String::from)
+            .unwrap_or_default();
+        match &newest {
+            Some((newest_date, _)) if *newest_date >= deletion_date => {}
+            _ => newest = Some((deletion_date, children.child(&info))),
+        }
+    }
+
+    match newest {
+        Some((_, trashed_file)) => trashed_file.move_(
+            &gio::File::for_path(original_path),
+            gio::FileCopyFlags::NONE,
+            gio::NONE_CANCELLABLE,
+            None,
+        ),
+        None => Err(gtk4::glib::Error::new(
+            gio::IOErrorEnum::NotFound,
+            &format!("{:?} is not in the trash", original_path),
+        )),
+    }
+}
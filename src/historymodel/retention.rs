@@ -0,0 +1,221 @@
+use chrono::{DateTime, Duration, Local};
+use gtk4::{gio, prelude::*};
+
+use crate::db::models::Screenshot;
+
+/// How often the retention policy is applied while kcshot is running
+pub const CLEANUP_INTERVAL_SECONDS: u32 = 60 * 60;
+
+/// Decides which screenshots get deleted automatically, a screenshot is deleted as soon as any of
+/// the limits is exceeded
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    /// Only this many of the newest screenshots are kept
+    pub keep_last: Option<usize>,
+    /// Screenshots older than this are deleted
+    pub max_age: Option<Duration>,
+    /// Once the files of the newest screenshots take up this many bytes, the older ones are deleted
+    pub max_total_size: Option<u64>,
+    /// Favourites are never deleted, nor do they count towards the limits
+    pub keep_favourites: bool,
+}
+
+impl RetentionPolicy {
+    /// Reads the policy from the settings, a limit of 0 means that there is no such limit
+    pub fn from_settings(settings: &gio::Settings) -> Self {
+        let limit = |key: &str| match settings.int(key) {
+            limit if limit > 0 => Some(limit as u64),
+            _ => None,
+        };
+
+        Self {
+            keep_last: limit("retention-keep-last").map(|n| n as usize),
+            max_age: limit("retention-max-age-days").map(|days| Duration::days(days as i64)),
+            max_total_size: limit("retention-max-total-size-mb").map(|mb| mb * 1024 * 1024),
+            keep_favourites: settings.boolean("retention-keep-favourites"),
+        }
+    }
+
+    /// Whether the policy would ever delete anything
+    pub fn is_enabled(&self) -> bool {
+        self.keep_last.is_some() || self.max_age.is_some() || self.max_total_size.is_some()
+    }
+
+    /// Picks the screenshots which the policy says should be deleted out of `screenshots`, which
+    /// have to be sorted from newest to oldest
+    pub fn expired<'a>(
+        &self,
+        screenshots: &'a [Screenshot],
+        now: DateTime<Local>,
+    ) -> Vec<&'a Screenshot> {
+        if !self.is_enabled() {
+            return vec![];
+        }
+
+        let mut kept = 0;
+        let mut total_size = 0;
+        // Once a screenshot doesn't fit in the size budget, the older ones don't either, even if
+        // they are smaller, since it's the newest screenshots which are kept
+        let mut over_budget = false;
+        screenshots
+            .iter()
+            .filter(|screenshot| !(self.keep_favourites && screenshot.is_favourite))
+            .filter(|screenshot| {
+                // Only the screenshots which are kept count towards the total size
+                let size = size_of(screenshot);
+                let too_many = matches!(self.keep_last, Some(keep_last) if kept >= keep_last);
+                over_budget = over_budget
+                    || matches!(self.max_total_size, Some(max) if total_size + size > max);
+                let too_old = match (self.max_age, DateTime::parse_from_rfc3339(&screenshot.time)) {
+                    (Some(max_age), Ok(time)) => now.signed_duration_since(time) > max_age,
+                    _ => false,
+                };
+
+                let expired = too_many || over_budget || too_old;
+                if !expired {
+                    kept += 1;
+                    total_size += size;
+                }
+                expired
+            })
+            .collect()
+    }
+}
+
+/// Returns how many bytes the image of the screenshot takes up, screenshots from before we kept
+/// track of that have their file looked at
+fn size_of(screenshot: &Screenshot) -> u64 {
+    match (screenshot.size, &screenshot.path) {
+        (Some(size), _) => size as u64,
+        (None, Some(path)) => std::fs::metadata(path).map_or(0, |metadata| metadata.len()),
+        (None, None) => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn screenshot(id: i32, time: &str, size: i64, is_favourite: bool) -> Screenshot {
+        Screenshot {
+            id,
+            path: None,
+            time: time.to_owned(),
+            url: None,
+            project_path: None,
+            width: None,
+            height: None,
+            size: Some(size),
+            format: None,
+            hash: None,
+            capture_mode: None,
+            window_title: None,
+            window_class: None,
+            monitor: None,
+            is_favourite,
+            taken_at: crate::db::taken_at(time),
+        }
+    }
+
+    /// Screenshots with ids from `n` down to 1, taken an hour apart from newest to oldest
+    fn screenshots(n: i32) -> Vec<Screenshot> {
+        (1..=n)
+            .rev()
+            .map(|id| screenshot(id, &format!("2022-02-01T{:02}:00:00+00:00", id), 1, false))
+            .collect()
+    }
+
+    fn now() -> DateTime<Local> {
+        DateTime::parse_from_rfc3339("2022-02-01T12:00:00+00:00")
+            .unwrap()
+            .with_timezone(&Local)
+    }
+
+    fn expired_ids(policy: RetentionPolicy, screenshots: &[Screenshot]) -> Vec<i32> {
+        policy
+            .expired(screenshots, now())
+            .into_iter()
+            .map(|screenshot| screenshot.id)
+            .collect()
+    }
+
+    #[test]
+    fn disabled_policies_expire_nothing() {
+        let policy = RetentionPolicy {
+            keep_favourites: true,
+            ..Default::default()
+        };
+        assert!(!policy.is_enabled());
+        assert_eq!(expired_ids(policy, &screenshots(4)), Vec::<i32>::new());
+    }
+
+    #[test]
+    fn keep_last_keeps_the_newest() {
+        let policy = RetentionPolicy {
+            keep_last: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(expired_ids(policy, &screenshots(4)), [2, 1]);
+        assert_eq!(expired_ids(policy, &screenshots(2)), Vec::<i32>::new());
+    }
+
+    #[test]
+    fn max_age_compares_times_taken_at_different_offsets() {
+        let policy = RetentionPolicy {
+            max_age: Some(Duration::days(1)),
+            ..Default::default()
+        };
+        // 23, 23.5 and 24.5 hours before `now()`, which the strings don't sort by
+        let screenshots = [
+            screenshot(3, "2022-01-31T08:00:00-05:00", 1, false),
+            screenshot(2, "2022-01-31T12:30:00+00:00", 1, false),
+            screenshot(1, "2022-01-31T13:30:00+02:00", 1, false),
+        ];
+        assert_eq!(expired_ids(policy, &screenshots), [1]);
+    }
+
+    #[test]
+    fn max_total_size_expires_everything_older_than_the_budget() {
+        let policy = RetentionPolicy {
+            max_total_size: Some(100),
+            ..Default::default()
+        };
+        let screenshots = [
+            screenshot(4, "2022-02-01T04:00:00+00:00", 40, false),
+            screenshot(3, "2022-02-01T03:00:00+00:00", 70, false),
+            // Would still fit next to the first one, but is older than one which doesn't
+            screenshot(2, "2022-02-01T02:00:00+00:00", 10, false),
+            screenshot(1, "2022-02-01T01:00:00+00:00", 5, false),
+        ];
+        assert_eq!(expired_ids(policy, &screenshots), [3, 2, 1]);
+
+        let policy = RetentionPolicy {
+            max_total_size: Some(120),
+            ..policy
+        };
+        assert_eq!(expired_ids(policy, &screenshots), [1]);
+    }
+
+    #[test]
+    fn favourites_are_kept_and_not_counted_if_asked_to() {
+        let screenshots = [
+            screenshot(4, "2022-02-01T04:00:00+00:00", 1, true),
+            screenshot(3, "2022-02-01T03:00:00+00:00", 1, false),
+            screenshot(2, "2022-02-01T02:00:00+00:00", 1, false),
+            screenshot(1, "2021-01-01T00:00:00+00:00", 1000, true),
+        ];
+        let policy = RetentionPolicy {
+            keep_last: Some(1),
+            max_age: Some(Duration::days(1)),
+            max_total_size: Some(100),
+            keep_favourites: true,
+        };
+        assert_eq!(expired_ids(policy, &screenshots), [2]);
+
+        let policy = RetentionPolicy {
+            keep_favourites: false,
+            ..policy
+        };
+        assert_eq!(expired_ids(policy, &screenshots), [3, 2, 1]);
+    }
+}
@@ -13,34 +13,157 @@ impl RowData {
     #[allow(clippy::new_without_default)]
     pub fn new(screenshot: Screenshot) -> Self {
         let Screenshot {
-            path, time, url, ..
+            id,
+            path,
+            time,
+            url,
+            project_path,
+            width,
+            height,
+            size,
+            format,
+            hash,
+            capture_mode,
+            window_title,
+            window_class,
+            monitor,
+            is_favourite,
+            taken_at: _,
         } = screenshot;
         glib::Object::new(&[
+            ("id", &id.to_value()),
             ("path", &path.to_value()),
             ("time", &time.to_value()),
             ("url", &url.to_value()),
+            ("project-path", &project_path.to_value()),
+            ("width", &width.unwrap_or(0).to_value()),
+            ("height", &height.unwrap_or(0).to_value()),
+            ("size", &size.unwrap_or(0).to_value()),
+            ("format", &format.to_value()),
+            ("hash", &hash.to_value()),
+            ("capture-mode", &capture_mode.to_value()),
+            ("window-title", &window_title.to_value()),
+            ("window-class", &window_class.to_value()),
+            ("monitor", &monitor.to_value()),
+            ("is-favourite", &is_favourite.to_value()),
         ])
         .unwrap()
     }
 
+    /// The id of the screenshot in the database
+    pub fn id(&self) -> i32 {
+        let this = underlying::RowData::from_instance(self);
+
+        this.id.get()
+    }
+
+    pub fn is_favourite(&self) -> bool {
+        let this = underlying::RowData::from_instance(self);
+
+        this.is_favourite.get()
+    }
+
     pub fn path(&self) -> Option<String> {
         let this = underlying::RowData::from_instance(self);
 
         this.path.borrow().clone()
     }
+
+    pub fn time(&self) -> String {
+        let this = underlying::RowData::from_instance(self);
+
+        this.time.borrow().clone()
+    }
+
+    pub fn url(&self) -> Option<String> {
+        let this = underlying::RowData::from_instance(self);
+
+        this.url.borrow().clone()
+    }
+
+    pub fn project_path(&self) -> Option<String> {
+        let this = underlying::RowData::from_instance(self);
+
+        this.project_path.borrow().clone()
+    }
+
+    /// The dimensions of the screenshot, `None` if they aren't known
+    pub fn dimensions(&self) -> Option<(i32, i32)> {
+        let this = underlying::RowData::from_instance(self);
+
+        match (this.width.get(), this.height.get()) {
+            (0, 0) => None,
+            dimensions => Some(dimensions),
+        }
+    }
+
+    /// The size of the file in bytes, `None` if it isn't known
+    pub fn size(&self) -> Option<i64> {
+        let this = underlying::RowData::from_instance(self);
+
+        Some(this.size.get()).filter(|&size| size != 0)
+    }
+
+    pub fn format(&self) -> Option<String> {
+        let this = underlying::RowData::from_instance(self);
+
+        this.format.borrow().clone()
+    }
+
+    pub fn hash(&self) -> Option<String> {
+        let this = underlying::RowData::from_instance(self);
+
+        this.hash.borrow().clone()
+    }
+
+    pub fn capture_mode(&self) -> Option<String> {
+        let this = underlying::RowData::from_instance(self);
+
+        this.capture_mode.borrow().clone()
+    }
+
+    pub fn window_title(&self) -> Option<String> {
+        let this = underlying::RowData::from_instance(self);
+
+        this.window_title.borrow().clone()
+    }
+
+    pub fn window_class(&self) -> Option<String> {
+        let this = underlying::RowData::from_instance(self);
+
+        this.window_class.borrow().clone()
+    }
+
+    pub fn monitor(&self) -> Option<String> {
+        let this = underlying::RowData::from_instance(self);
+
+        this.monitor.borrow().clone()
+    }
 }
 
 mod underlying {
-    use std::cell::RefCell;
+    use std::cell::{Cell, RefCell};
 
     use gtk4::glib::{self, subclass::prelude::*, ToValue};
     use once_cell::sync::Lazy;
 
     #[derive(Default, Debug)]
     pub struct RowData {
+        pub(super) id: Cell<i32>,
         pub(super) path: RefCell<Option<String>>,
         pub(super) time: RefCell<String>,
         pub(super) url: RefCell<Option<String>>,
+        pub(super) project_path: RefCell<Option<String>>,
+        pub(super) width: Cell<i32>,
+        pub(super) height: Cell<i32>,
+        pub(super) size: Cell<i64>,
+        pub(super) format: RefCell<Option<String>>,
+        pub(super) hash: RefCell<Option<String>>,
+        pub(super) capture_mode: RefCell<Option<String>>,
+        pub(super) window_title: RefCell<Option<String>>,
+        pub(super) window_class: RefCell<Option<String>>,
+        pub(super) monitor: RefCell<Option<String>>,
+        pub(super) is_favourite: Cell<bool>,
     }
 
     #[glib::object_subclass]
@@ -54,6 +177,15 @@ mod underlying {
         fn properties() -> &'static [glib::ParamSpec] {
             static PROPERTIES: Lazy<Vec<glib::ParamSpec>> = Lazy::new(|| {
                 vec![
+                    glib::ParamSpec::new_int(
+                        "id",
+                        "ID",
+                        "ID",
+                        0,
+                        i32::MAX,
+                        0,
+                        glib::ParamFlags::READWRITE,
+                    ),
                     glib::ParamSpec::new_string(
                         "path",
                         "Path",
@@ -75,6 +207,89 @@ mod underlying {
                         None,
                         glib::ParamFlags::READWRITE,
                     ),
+                    glib::ParamSpec::new_string(
+                        "project-path",
+                        "Project path",
+                        "Project path",
+                        None,
+                        glib::ParamFlags::READWRITE,
+                    ),
+                    glib::ParamSpec::new_int(
+                        "width",
+                        "Width",
+                        "Width",
+                        0,
+                        i32::MAX,
+                        0,
+                        glib::ParamFlags::READWRITE,
+                    ),
+                    glib::ParamSpec::new_int(
+                        "height",
+                        "Height",
+                        "Height",
+                        0,
+                        i32::MAX,
+                        0,
+                        glib::ParamFlags::READWRITE,
+                    ),
+                    glib::ParamSpec::new_int64(
+                        "size",
+                        "Size",
+                        "Size in bytes",
+                        0,
+                        i64::MAX,
+                        0,
+                        glib::ParamFlags::READWRITE,
+                    ),
+                    glib::ParamSpec::new_string(
+                        "format",
+                        "Format",
+                        "Format",
+                        None,
+                        glib::ParamFlags::READWRITE,
+                    ),
+                    glib::ParamSpec::new_string(
+                        "hash",
+                        "Hash",
+                        "Hash",
+                        None,
+                        glib::ParamFlags::READWRITE,
+                    ),
+                    glib::ParamSpec::new_string(
+                        "capture-mode",
+                        "Capture mode",
+                        "Capture mode",
+                        None,
+                        glib::ParamFlags::READWRITE,
+                    ),
+                    glib::ParamSpec::new_string(
+                        "window-title",
+                        "Window title",
+                        "Window title",
+                        None,
+                        glib::ParamFlags::READWRITE,
+                    ),
+                    glib::ParamSpec::new_string(
+                        "window-class",
+                        "Window class",
+                        "Window class",
+                        None,
+                        glib::ParamFlags::READWRITE,
+                    ),
+                    glib::ParamSpec::new_string(
+                        "monitor",
+                        "Monitor",
+                        "Monitor",
+                        None,
+                        glib::ParamFlags::READWRITE,
+                    ),
+                    glib::ParamSpec::new_boolean(
+                        "is-favourite",
+                        "Is favourite",
+                        "Is favourite",
+                        false,
+                        glib::ParamFlags::READWRITE,
+                    ),
                 ]
             });
 
@@ -83,9 +298,21 @@ mod underlying {
 
         fn property(&self, _obj: &Self::Type, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
             match pspec.name() {
+                "id" => self.id.get().to_value(),
                 "path" => self.path.borrow().to_value(),
                 "time" => self.time.borrow().to_value(),
                 "url" => self.url.borrow().to_value(),
+                "project-path" => self.project_path.borrow().to_value(),
+                "width" => self.width.get().to_value(),
+                "height" => self.height.get().to_value(),
+                "size" => self.size.get().to_value(),
+                "format" => self.format.borrow().to_value(),
+                "hash" => self.hash.borrow().to_value(),
+                "capture-mode" => self.capture_mode.borrow().to_value(),
+                "window-title" => self.window_title.borrow().to_value(),
+                "window-class" => self.window_class.borrow().to_value(),
+                "monitor" => self.monitor.borrow().to_value(),
+                "is-favourite" => self.is_favourite.get().to_value(),
                 name => panic!("Tried to get property {} which does not exist", name),
             }
         }
@@ -98,6 +325,10 @@ mod underlying {
             pspec: &glib::ParamSpec,
         ) {
             match pspec.name() {
+                "id" => {
+                    let id = value.get().unwrap();
+                    self.id.set(id);
+                }
                 "path" => {
                     let path = value.get::<Option<String>>().unwrap();
                     self.path.replace(path);
@@ -110,6 +341,50 @@ mod underlying {
                     let url = value.get::<Option<String>>().unwrap();
                     self.url.replace(url);
                 }
+                "project-path" => {
+                    let project_path = value.get::<Option<String>>().unwrap();
+                    self.project_path.replace(project_path);
+                }
+                "width" => {
+                    let width = value.get().unwrap();
+                    self.width.set(width);
+                }
+                "height" => {
+                    let height = value.get().unwrap();
+                    self.height.set(height);
+                }
+                "size" => {
+                    let size = value.get().unwrap();
+                    self.size.set(size);
+                }
+                "format" => {
+                    let format = value.get::<Option<String>>().unwrap();
+                    self.format.replace(format);
+                }
+                "hash" => {
+                    let hash = value.get::<Option<String>>().unwrap();
+                    self.hash.replace(hash);
+                }
+                "capture-mode" => {
+                    let capture_mode = value.get::<Option<String>>().unwrap();
+                    self.capture_mode.replace(capture_mode);
+                }
+                "window-title" => {
+                    let window_title = value.get::<Option<String>>().unwrap();
+                    self.window_title.replace(window_title);
+                }
+                "window-class" => {
+                    let window_class = value.get::<Option<String>>().unwrap();
+                    self.window_class.replace(window_class);
+                }
+                "monitor" => {
+                    let monitor = value.get::<Option<String>>().unwrap();
+                    self.monitor.replace(monitor);
+                }
+                "is-favourite" => {
+                    let is_favourite = value.get().unwrap();
+                    self.is_favourite.set(is_favourite);
+                }
                 name => {
                     tracing::warn!(
                         "Tried setting property {} which does not exist on this object: {:?}",
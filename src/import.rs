@@ -0,0 +1,196 @@
+//! Importing screenshots which are already on disk, e.g. those taken with ShareX or flameshot,
+//! into the history
+
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Local};
+use gtk4::gdk_pixbuf::Pixbuf;
+use serde::Deserialize;
+
+use crate::{
+    db::{self, models::NewScreenshot},
+    editor::Project,
+    metadata::{self, CaptureMode},
+};
+
+/// Files with other extensions aren't even looked at
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "gif", "bmp", "webp", "tif", "tiff"];
+
+/// The screenshots which were found in a folder
+#[derive(Debug, Default)]
+pub struct Scan {
+    /// Sorted from oldest to newest
+    pub screenshots: Vec<NewScreenshot>,
+    /// How many image files couldn't be read
+    pub failed: usize,
+}
+
+/// What came out of importing a folder
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Summary {
+    pub imported: usize,
+    /// How many screenshots were skipped because they already are in the history
+    pub duplicates: usize,
+    pub failed: usize,
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Imported {} screenshots, skipped {} which were already in the history",
+            self.imported, self.duplicates
+        )?;
+        if self.failed > 0 {
+            write!(f, ", failed to read {} files", self.failed)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// An entry of ShareX's `History.json`, only the fields we care about are read
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct ShareXEntry {
+    file_name: Option<String>,
+    date_time: Option<String>,
+    #[serde(rename = "URL")]
+    url: Option<String>,
+    tags: Option<ShareXTags>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct ShareXTags {
+    window_title: Option<String>,
+    process_name: Option<String>,
+}
+
+/// Looks for images in `folder` and its subfolders and reads what we want to know about them,
+/// this hashes every image so it takes a while for big folders. If the folder has a ShareX
+/// `History.json`, the URLs, times and windows recorded in it are used.
+pub fn scan(folder: &Path) -> io::Result<Scan> {
+    let mut files = vec![];
+    find_images(folder, &mut files)?;
+    let sharex_history = read_sharex_history(folder);
+
+    let mut scan = Scan::default();
+    for file in files {
+        let file_name = file.file_name().map(OsString::from).unwrap_or_default();
+        match read_screenshot(&file, sharex_history.get(&file_name)) {
+            Ok(screenshot) => scan.screenshots.push(screenshot),
+            Err(why) => {
+                tracing::warn!("Failed to import {:?}: {}", file, why);
+                scan.failed += 1;
+            }
+        }
+    }
+    scan.screenshots
+        .sort_by_key(|screenshot| DateTime::parse_from_rfc3339(&screenshot.time).ok());
+
+    Ok(scan)
+}
+
+/// Pushes the paths of the images in `folder` and its subfolders onto `files`, the images are
+/// recognised by their extension. The base images of projects are left out, since they're part of
+/// the screenshot the project was saved with.
+pub fn find_images(folder: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(folder)? {
+        let entry = entry?;
+        let path = entry.path();
+        // Symlinks aren't followed, so that we can't end up going in circles
+        let file_type = entry.file_type()?;
+
+        if file_type.is_dir() {
+            if let Err(why) = find_images(&path, files) {
+                tracing::warn!("Failed to look for images in {:?}: {}", path, why);
+            }
+        } else if file_type.is_file()
+            && has_image_extension(&path)
+            && !Project::is_base_image(&path)
+        {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+fn has_image_extension(path: &Path) -> bool {
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase());
+
+    matches!(extension, Some(extension) if IMAGE_EXTENSIONS.contains(&extension.as_str()))
+}
+
+/// Reads ShareX's `History.json` from `folder` if there is one, the entries are keyed by their
+/// file name since the folder may have been moved since ShareX wrote the paths
+fn read_sharex_history(folder: &Path) -> HashMap<OsString, ShareXEntry> {
+    let path = folder.join("History.json");
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(why) if why.kind() == io::ErrorKind::NotFound => return HashMap::new(),
+        Err(why) => {
+            tracing::warn!("Failed to read {:?}: {}", path, why);
+            return HashMap::new();
+        }
+    };
+
+    // ShareX appends `{...},` for each entry, so the file is only an array once it's wrapped
+    let entries = contents
+        .trim()
+        .trim_start_matches('\u{feff}')
+        .trim_end_matches(',');
+    let entries = if entries.starts_with('[') {
+        serde_json::from_str::<Vec<ShareXEntry>>(entries)
+    } else {
+        serde_json::from_str::<Vec<ShareXEntry>>(&format!("[{}]", entries))
+    };
+    match entries {
+        Ok(entries) => entries
+            .into_iter()
+            .filter_map(|entry| Some((entry.file_name.clone()?.into(), entry)))
+            .collect(),
+        Err(why) => {
+            tracing::warn!("Failed to parse {:?}: {}", path, why);
+            HashMap::new()
+        }
+    }
+}
+
+fn read_screenshot(path: &Path, sharex_entry: Option<&ShareXEntry>) -> io::Result<NewScreenshot> {
+    let (format, width, height) = Pixbuf::file_info(path)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Not a supported image"))?;
+    let file_metadata = fs::metadata(path)?;
+
+    let modified_time = DateTime::<Local>::from(file_metadata.modified()?);
+    let tags = sharex_entry.and_then(|entry| entry.tags.as_ref());
+    let time = sharex_entry
+        .and_then(|entry| entry.date_time.as_deref())
+        .and_then(|time| DateTime::parse_from_rfc3339(time).ok())
+        .map_or_else(|| modified_time.to_rfc3339(), |time| time.to_rfc3339());
+
+    Ok(NewScreenshot {
+        path: Some(path.to_string_lossy().into_owned()),
+        taken_at: db::taken_at(&time),
+        time,
+        url: sharex_entry.and_then(|entry| entry.url.clone()),
+        project_path: None,
+        width: Some(width),
+        height: Some(height),
+        size: Some(file_metadata.len() as i64),
+        format: format.name().map(String::from),
+        hash: Some(metadata::hash_file(path)?),
+        capture_mode: Some(CaptureMode::Imported.as_str().into()),
+        window_title: tags.and_then(|tags| tags.window_title.clone()),
+        window_class: tags.and_then(|tags| tags.process_name.clone()),
+        monitor: None,
+    })
+}
@@ -0,0 +1,121 @@
+//! Repairing the history when the files of screenshots were moved somewhere else
+
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use gtk4::gdk_pixbuf::Pixbuf;
+
+use crate::{import, metadata};
+
+/// The images in a folder, indexed so that the screenshots whose files were moved there can be
+/// found again
+#[derive(Debug, Default)]
+pub struct Index {
+    by_hash: HashMap<String, PathBuf>,
+    /// For screenshots from before we kept track of hashes, they are recognised by their file
+    /// name and their size or dimensions instead
+    by_name: HashMap<OsString, Vec<Candidate>>,
+}
+
+#[derive(Debug)]
+struct Candidate {
+    path: PathBuf,
+    size: u64,
+    dimensions: Option<(i32, i32)>,
+}
+
+/// What the history knows about a screenshot whose file is missing
+#[derive(Clone, Copy, Debug)]
+pub struct Lost<'a> {
+    pub path: &'a Path,
+    pub hash: Option<&'a str>,
+    pub size: Option<i64>,
+    pub dimensions: Option<(i32, i32)>,
+}
+
+impl Lost<'_> {
+    /// Screenshots without a hash, a size and dimensions can't be told apart from any other image
+    /// with the same file name
+    pub fn is_recognisable(&self) -> bool {
+        self.hash.is_some() || self.size.is_some() || self.dimensions.is_some()
+    }
+}
+
+/// What came out of relinking the missing screenshots
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Summary {
+    pub relinked: usize,
+    /// How many of the screenshots which weren't found never could have been, see
+    /// [`Lost::is_recognisable`]
+    pub unmatchable: usize,
+}
+
+/// Finds the images in `folder` and its subfolders and indexes them, this hashes every image so
+/// it takes a while for big folders
+pub fn index(folder: &Path) -> io::Result<Index> {
+    let mut files = vec![];
+    import::find_images(folder, &mut files)?;
+
+    let mut index = Index::default();
+    for file in files {
+        match metadata::hash_file(&file) {
+            Ok(hash) => {
+                index.by_hash.entry(hash).or_insert_with(|| file.clone());
+            }
+            Err(why) => {
+                tracing::warn!("Failed to hash {:?}: {}", file, why);
+                continue;
+            }
+        }
+
+        let size = match fs::metadata(&file) {
+            Ok(metadata) => metadata.len(),
+            Err(why) => {
+                tracing::warn!("Failed to read the size of {:?}: {}", file, why);
+                continue;
+            }
+        };
+        if let Some(file_name) = file.file_name() {
+            index
+                .by_name
+                .entry(file_name.to_owned())
+                .or_default()
+                .push(Candidate {
+                    dimensions: Pixbuf::file_info(&file).map(|(_, width, height)| (width, height)),
+                    path: file,
+                    size,
+                });
+        }
+    }
+
+    Ok(index)
+}
+
+impl Index {
+    /// Finds the image of `lost` by its hash, or, when the history doesn't know the hash, by its
+    /// file name along with its size or, failing that, its dimensions. Images that are only
+    /// recognised by their name have to be the only ones which look like that.
+    pub fn find(&self, lost: &Lost) -> Option<&Path> {
+        if let Some(hash) = lost.hash {
+            return self.by_hash.get(hash).map(PathBuf::as_path);
+        }
+
+        let candidates = self.by_name.get(lost.path.file_name()?)?;
+        let mut matches =
+            candidates
+                .iter()
+                .filter(|candidate| match (lost.size, lost.dimensions) {
+                    (Some(size), _) => candidate.size as i64 == size,
+                    (None, Some(dimensions)) => candidate.dimensions == Some(dimensions),
+                    (None, None) => false,
+                });
+        match (matches.next(), matches.next()) {
+            (Some(candidate), None) => Some(&candidate.path),
+            _ => None,
+        }
+    }
+}
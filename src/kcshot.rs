@@ -22,28 +22,56 @@ impl KCShot {
         .expect("Failed to create KCShot")
     }
 
+    /// Returns the connection to the history database
+    ///
+    /// # Panics
+    /// If the database couldn't be opened, we do not activate the application in that case, so
+    /// this can't happen inside of windows
     pub fn conn(&self) -> &SqliteConnection {
         let impl_ = underlying::KCShot::from_instance(self);
         impl_.database_connection.get().unwrap()
     }
+
+    /// Returns the model of the history, which is shared by all windows
+    ///
+    /// # Panics
+    /// If the database couldn't be opened, see [`KCShot::conn`]
+    pub fn history_model(&self) -> &HistoryModel {
+        let impl_ = underlying::KCShot::from_instance(self);
+        impl_.history_model.get().unwrap()
+    }
 }
 
 pub fn build_ui(app: &KCShot) {
     let instance = underlying::KCShot::from_instance(app);
 
     let take_screenshot = *instance.take_screenshot.borrow();
+    let capture_last_region = *instance.capture_last_region.borrow();
+    let edit_file = instance.edit_file.take();
+    let edit_clipboard = *instance.edit_clipboard.borrow();
     let show_main_window = *instance.show_main_window.borrow();
 
-    let history_model = HistoryModel::new(app.upcast_ref());
-    let window = appwindow::AppWindow::new(app, &history_model);
+    let history_model = app.history_model();
+    let window = appwindow::AppWindow::new(app, history_model);
 
     if take_screenshot {
         instance.take_screenshot.replace(false);
-        let window = editor::EditorWindow::new(app.upcast_ref(), &history_model);
+        let window = editor::EditorWindow::new(app.upcast_ref(), history_model);
         window.set_decorated(false);
         window.fullscreen();
 
         window.show()
+    } else if let Some(path) = edit_file {
+        match editor::EditorWindow::from_file(app.upcast_ref(), history_model, &path) {
+            Ok(window) => window.show(),
+            Err(why) => tracing::error!("Failed to open {:?} in the editor: {}", path, why),
+        }
+    } else if edit_clipboard {
+        instance.edit_clipboard.replace(false);
+        editor::edit_clipboard_image(app.upcast_ref(), history_model);
+    } else if capture_last_region {
+        instance.capture_last_region.replace(false);
+        editor::capture_last_region(app, history_model);
     } else if show_main_window {
         instance.show_main_window.replace(false);
 
@@ -52,22 +80,31 @@ pub fn build_ui(app: &KCShot) {
 }
 
 mod underlying {
-    use std::{cell::RefCell, ffi::OsString};
+    use std::{cell::RefCell, ffi::OsString, io, path::PathBuf};
 
     use diesel::SqliteConnection;
     use gtk4::{
         gio::{self, prelude::*},
-        glib,
+        glib::{self, clone, translate::ToGlibPtr},
         subclass::prelude::*,
     };
+    use libc::c_char;
     use once_cell::sync::{Lazy, OnceCell};
 
-    use crate::db;
+    use crate::{
+        db, export,
+        historymodel::{HistoryModel, RetentionPolicy, CLEANUP_INTERVAL_SECONDS},
+        import,
+    };
 
     pub struct KCShot {
         pub(super) show_main_window: RefCell<bool>,
         pub(super) take_screenshot: RefCell<bool>,
+        pub(super) capture_last_region: RefCell<bool>,
+        pub(super) edit_file: RefCell<Option<PathBuf>>,
+        pub(super) edit_clipboard: RefCell<bool>,
         pub(super) database_connection: OnceCell<SqliteConnection>,
+        pub(super) history_model: OnceCell<HistoryModel>,
     }
 
     impl Default for KCShot {
@@ -75,7 +112,11 @@ mod underlying {
             Self {
                 show_main_window: RefCell::new(true),
                 take_screenshot: RefCell::new(false),
+                capture_last_region: RefCell::new(false),
+                edit_file: RefCell::new(None),
+                edit_clipboard: RefCell::new(false),
                 database_connection: Default::default(),
+                history_model: Default::default(),
             }
         }
     }
@@ -85,7 +126,11 @@ mod underlying {
             f.debug_struct("KCShot")
                 .field("show_main_window", &self.show_main_window)
                 .field("take_screenshot", &self.take_screenshot)
+                .field("capture_last_region", &self.capture_last_region)
+                .field("edit_file", &self.edit_file)
+                .field("edit_clipboard", &self.edit_clipboard)
                 .field("database_connection", &"<sqlite connection>")
+                .field("history_model", &self.history_model)
                 .finish()
         }
     }
@@ -97,18 +142,7 @@ mod underlying {
         type ParentType = gtk4::Application;
     }
 
-    impl ObjectImpl for KCShot {
-        fn constructed(&self, _: &Self::Type) {
-            tracing::info!("Entered KCShot::constructed");
-            let res = self
-                .database_connection
-                .set(db::open(&database_path()).unwrap());
-
-            if res.is_err() {
-                tracing::error!("Failed setting self.database_connection");
-            }
-        }
-    }
+    impl ObjectImpl for KCShot {}
 
     const LONG: usize = 1;
 
@@ -118,6 +152,20 @@ mod underlying {
     static NO_WINDOW_FLAGS_OS: Lazy<Vec<OsString>> =
         Lazy::new(|| vec!["-n".into(), "--no-window".into()]);
     const NO_WINDOW_FLAGS: &[&str] = &["-n", "--no-window"];
+    static LAST_REGION_FLAGS_OS: Lazy<Vec<OsString>> =
+        Lazy::new(|| vec!["-l".into(), "--last-region".into()]);
+    const LAST_REGION_FLAGS: &[&str] = &["-l", "--last-region"];
+    static EDIT_FLAGS_OS: Lazy<Vec<OsString>> = Lazy::new(|| vec!["-e".into(), "--edit".into()]);
+    const EDIT_FLAGS: &[&str] = &["-e", "--edit"];
+    static CLIPBOARD_FLAGS_OS: Lazy<Vec<OsString>> =
+        Lazy::new(|| vec!["-c".into(), "--clipboard".into()]);
+    const CLIPBOARD_FLAGS: &[&str] = &["-c", "--clipboard"];
+    static IMPORT_FLAGS_OS: Lazy<Vec<OsString>> =
+        Lazy::new(|| vec!["-i".into(), "--import".into()]);
+    const IMPORT_FLAGS: &[&str] = &["-i", "--import"];
+    static EXPORT_FLAGS_OS: Lazy<Vec<OsString>> =
+        Lazy::new(|| vec!["-x".into(), "--export".into()]);
+    const EXPORT_FLAGS: &[&str] = &["-x", "--export"];
 
     impl ApplicationImpl for KCShot {
         // This is called in the primary instance
@@ -126,15 +174,43 @@ mod underlying {
             app: &Self::Type,
             command_line: &gio::ApplicationCommandLine,
         ) -> i32 {
-            tracing::info!("hm");
+            if self.database_connection.get().is_none() {
+                printerr(
+                    command_line,
+                    "kcshot: Couldn't open the history database, see the log for details",
+                );
+                return 1;
+            }
+
             let mut show_main_window = true;
-            for argument in command_line.arguments() {
+            let mut arguments = command_line.arguments().into_iter();
+            while let Some(argument) = arguments.next() {
                 if NO_WINDOW_FLAGS_OS.contains(&argument) {
                     show_main_window = false;
                     self.take_screenshot.replace(false);
+                    self.capture_last_region.replace(false);
                 } else if SCREENSHOT_FLAGS_OS.contains(&argument) {
                     self.take_screenshot.replace(true);
                     show_main_window = false;
+                } else if LAST_REGION_FLAGS_OS.contains(&argument) {
+                    self.capture_last_region.replace(true);
+                    show_main_window = false;
+                } else if EDIT_FLAGS_OS.contains(&argument) {
+                    // `local_command_line` made sure that a path follows the flag
+                    let path = absolute_path(command_line, arguments.next().unwrap_or_default());
+                    self.edit_file.replace(Some(path));
+                    show_main_window = false;
+                } else if CLIPBOARD_FLAGS_OS.contains(&argument) {
+                    self.edit_clipboard.replace(true);
+                    show_main_window = false;
+                } else if IMPORT_FLAGS_OS.contains(&argument) {
+                    // `local_command_line` made sure that a path follows the flag
+                    let path = absolute_path(command_line, arguments.next().unwrap_or_default());
+                    return import_folder(app, command_line, path);
+                } else if EXPORT_FLAGS_OS.contains(&argument) {
+                    // `local_command_line` made sure that a path follows the flag
+                    let path = absolute_path(command_line, arguments.next().unwrap_or_default());
+                    return export_history(app, command_line, path);
                 }
             }
             self.show_main_window.replace(show_main_window);
@@ -161,6 +237,15 @@ Help Options:
 Application Options:
   -n, --no-window      Don't show any windows
   -s, --screenshot     Take a screenshot (mutually exclusive with -n)
+  -l, --last-region    Capture the last cropped region without showing the editor
+                       (mutually exclusive with -n and -s)
+  -e, --edit FILE      Open FILE in the editor (mutually exclusive with -n, -s and -l)
+  -c, --clipboard      Open the image in the clipboard in the editor
+                       (mutually exclusive with -n, -s, -l and -e)
+  -i, --import FOLDER  Add the screenshots in FOLDER to the history, without showing any windows
+                       (mutually exclusive with -n, -s, -l, -e and -c)
+  -x, --export FILE    Export the whole history to FILE, which has to end in .json, .csv or .zip
+                       (mutually exclusive with -n, -s, -l, -e, -c and -i)
 "#,
                 prog_name
             );
@@ -174,11 +259,60 @@ Application Options:
 
             let take_screenshot = arguments.iter().any(|os| SCREENSHOT_FLAGS_OS.contains(os));
             let no_window = arguments.iter().any(|os| NO_WINDOW_FLAGS_OS.contains(os));
+            let last_region = arguments.iter().any(|os| LAST_REGION_FLAGS_OS.contains(os));
+            let edit_position = arguments.iter().position(|os| EDIT_FLAGS_OS.contains(os));
+            let clipboard = arguments.iter().any(|os| CLIPBOARD_FLAGS_OS.contains(os));
+            let import_position = arguments.iter().position(|os| IMPORT_FLAGS_OS.contains(os));
+            let export_position = arguments.iter().position(|os| EXPORT_FLAGS_OS.contains(os));
+
+            if let Some(position) = edit_position {
+                if position + 1 >= arguments.len() {
+                    eprintln!(
+                        "{}: {} expects a path to an image\n{}",
+                        prog_name, EDIT_FLAGS[LONG], usage
+                    );
+                    return Some(1);
+                }
+            }
+            if let Some(position) = import_position {
+                if position + 1 >= arguments.len() {
+                    eprintln!(
+                        "{}: {} expects a path to a folder\n{}",
+                        prog_name, IMPORT_FLAGS[LONG], usage
+                    );
+                    return Some(1);
+                }
+            }
+            if let Some(position) = export_position {
+                if position + 1 >= arguments.len() {
+                    eprintln!(
+                        "{}: {} expects a path to export to\n{}",
+                        prog_name, EXPORT_FLAGS[LONG], usage
+                    );
+                    return Some(1);
+                }
+            }
 
-            if take_screenshot && no_window {
+            let mutually_exclusive = [
+                (take_screenshot, SCREENSHOT_FLAGS[LONG]),
+                (no_window, NO_WINDOW_FLAGS[LONG]),
+                (last_region, LAST_REGION_FLAGS[LONG]),
+                (edit_position.is_some(), EDIT_FLAGS[LONG]),
+                (clipboard, CLIPBOARD_FLAGS[LONG]),
+                (import_position.is_some(), IMPORT_FLAGS[LONG]),
+                (export_position.is_some(), EXPORT_FLAGS[LONG]),
+            ];
+            let passed = mutually_exclusive
+                .iter()
+                .filter(|(passed, _)| *passed)
+                .map(|(_, flag)| *flag)
+                .collect::<Vec<_>>();
+            if passed.len() > 1 {
                 eprintln!(
-                    "{}: {} and {} are mutually exclusive\n{}",
-                    prog_name, SCREENSHOT_FLAGS[LONG], NO_WINDOW_FLAGS[LONG], usage
+                    "{}: {} are mutually exclusive\n{}",
+                    prog_name,
+                    passed.join(" and "),
+                    usage
                 );
                 return Some(1);
             }
@@ -189,8 +323,29 @@ Application Options:
         fn startup(&self, application: &Self::Type) {
             self.parent_startup(application);
 
+            let conn = db::database_path().and_then(|path| {
+                tracing::info!("Opening database at {:?}", path);
+                db::open(&path.to_string_lossy())
+            });
+            match conn {
+                Ok(conn) => {
+                    if self.database_connection.set(conn).is_err() {
+                        tracing::error!("Failed setting self.database_connection");
+                    }
+                }
+                Err(why) => tracing::error!("Couldn't open the history database: {}", why),
+            }
+
             let settings = gio::Settings::new("kc.kcshot");
 
+            if self.database_connection.get().is_some() {
+                let history_model = HistoryModel::new(application);
+                schedule_cleanup(&history_model, &settings);
+                if self.history_model.set(history_model).is_err() {
+                    tracing::error!("Failed setting self.history_model");
+                }
+            }
+
             if settings.string("saved-screenshots-path").is_empty() {
                 #[cfg(not(feature = "xdg"))]
                 let default_folder = std::env::current_dir().unwrap();
@@ -213,12 +368,149 @@ Application Options:
 
     impl GtkApplicationImpl for KCShot {}
 
-    /// FIXME: Make this not return a hardcoded path in the current directory
-    fn database_path() -> String {
-        std::env::current_dir()
-            .unwrap()
-            .join("history.db")
-            .to_string_lossy()
-            .into()
+    /// Turns a path passed on the command line into an absolute one, relative paths are relative
+    /// to the directory kcshot was started from, which isn't necessarily ours
+    fn absolute_path(command_line: &gio::ApplicationCommandLine, path: OsString) -> PathBuf {
+        let path = PathBuf::from(path);
+        match command_line.cwd() {
+            Some(cwd) if path.is_relative() => cwd.join(path),
+            _ => path,
+        }
+    }
+
+    /// Imports the screenshots in `folder` into the history. Scanning the folder hashes every
+    /// image, so it happens on another thread and the application and `command_line` are held
+    /// until it's done.
+    ///
+    /// # Returns
+    /// The exit status, unless the import fails after this returns
+    fn import_folder(
+        app: &super::KCShot,
+        command_line: &gio::ApplicationCommandLine,
+        folder: PathBuf,
+    ) -> i32 {
+        let (sender, receiver) =
+            glib::MainContext::channel::<io::Result<import::Scan>>(glib::PRIORITY_DEFAULT);
+        let scanned_folder = folder.clone();
+        std::thread::spawn(move || {
+            let _ = sender.send(import::scan(&scanned_folder));
+        });
+
+        app.hold();
+        receiver.attach(
+            None,
+            clone!(@strong app, @strong command_line => move |scan| {
+                match scan {
+                    Ok(scan) => {
+                        let summary = app.history_model().import(scan);
+                        print(&command_line, &format!("kcshot: {}", summary));
+                    }
+                    Err(why) => {
+                        printerr(
+                            &command_line,
+                            &format!("kcshot: Failed to import {:?}: {}", folder, why),
+                        );
+                        command_line.set_exit_status(1);
+                    }
+                }
+                app.release();
+                glib::Continue(false)
+            }),
+        );
+
+        0
+    }
+
+    /// Prints `message` to the standard output of the process which `command_line` came from.
+    /// `g_application_command_line_print` takes a format string, so gio doesn't bind it.
+    fn print(command_line: &gio::ApplicationCommandLine, message: &str) {
+        unsafe {
+            gio::ffi::g_application_command_line_print(
+                command_line.to_glib_none().0,
+                b"%s\n\0".as_ptr() as *const c_char,
+                ToGlibPtr::<*const c_char>::to_glib_none(message).0,
+            );
+        }
+    }
+
+    /// Like [`print`], to the standard error instead
+    fn printerr(command_line: &gio::ApplicationCommandLine, message: &str) {
+        unsafe {
+            gio::ffi::g_application_command_line_printerr(
+                command_line.to_glib_none().0,
+                b"%s\n\0".as_ptr() as *const c_char,
+                ToGlibPtr::<*const c_char>::to_glib_none(message).0,
+            );
+        }
+    }
+
+    /// Exports the whole history to `path`. Archives copy every image, so the export is written on
+    /// another thread and the application and `command_line` are held until it's done.
+    ///
+    /// # Returns
+    /// The exit status, unless the export fails after this returns
+    fn export_history(
+        app: &super::KCShot,
+        command_line: &gio::ApplicationCommandLine,
+        path: PathBuf,
+    ) -> i32 {
+        let entries = match app
+            .history_model()
+            .export_entries_matching(&db::Filter::default())
+        {
+            Ok(entries) => entries,
+            Err(why) => {
+                printerr(
+                    command_line,
+                    &format!("kcshot: Failed to export to {:?}: {}", path, why),
+                );
+                return 1;
+            }
+        };
+
+        let (sender, receiver) =
+            glib::MainContext::channel::<Result<usize, export::Error>>(glib::PRIORITY_DEFAULT);
+        let exported_path = path.clone();
+        std::thread::spawn(move || {
+            let _ = sender.send(export::export(entries, &exported_path));
+        });
+
+        app.hold();
+        receiver.attach(
+            None,
+            clone!(@strong app, @strong command_line => move |res| {
+                match res {
+                    Ok(n_exported) => print(
+                        &command_line,
+                        &format!("kcshot: Exported {} screenshots to {:?}", n_exported, path),
+                    ),
+                    Err(why) => {
+                        printerr(
+                            &command_line,
+                            &format!("kcshot: Failed to export to {:?}: {}", path, why),
+                        );
+                        command_line.set_exit_status(1);
+                    }
+                }
+                app.release();
+                glib::Continue(false)
+            }),
+        );
+
+        0
+    }
+
+    /// Cleans up the history according to the retention policy right away, and then every
+    /// [`CLEANUP_INTERVAL_SECONDS`] for as long as kcshot runs
+    fn schedule_cleanup(history_model: &HistoryModel, settings: &gio::Settings) {
+        history_model.apply_retention_policy(&RetentionPolicy::from_settings(settings));
+
+        glib::timeout_add_seconds_local(
+            CLEANUP_INTERVAL_SECONDS,
+            clone!(@weak history_model, @strong settings => @default-return glib::Continue(false), move || {
+                history_model.apply_retention_policy(&RetentionPolicy::from_settings(&settings));
+                glib::Continue(true)
+            }),
+        );
     }
 }
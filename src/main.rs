@@ -1,13 +1,19 @@
 #[macro_use]
 extern crate diesel;
+#[macro_use]
+extern crate diesel_migrations;
 
 use gtk4::prelude::*;
 
 mod appwindow;
 mod db;
 mod editor;
+mod export;
 mod historymodel;
+mod import;
+mod integrity;
 mod kcshot;
+mod metadata;
 mod postcapture;
 
 use kcshot::KCShot;
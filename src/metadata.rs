@@ -0,0 +1,89 @@
+//! Information about screenshots which gets stored alongside them in the history
+
+use std::{fmt, fs::File, io, io::Read, path::Path, str::FromStr};
+
+use gtk4::glib;
+
+/// How a screenshot came to be
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CaptureMode {
+    /// A region of the screen was cropped
+    Region,
+    /// A single window was captured
+    Window,
+    /// The whole screen was captured
+    Screen,
+    /// The image was taken from the clipboard
+    Clipboard,
+    /// The image was opened from a file
+    File,
+    /// The image was already on disk and got imported into the history
+    Imported,
+}
+
+impl CaptureMode {
+    /// The string under which the mode is stored in the database
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CaptureMode::Region => "region",
+            CaptureMode::Window => "window",
+            CaptureMode::Screen => "screen",
+            CaptureMode::Clipboard => "clipboard",
+            CaptureMode::File => "file",
+            CaptureMode::Imported => "imported",
+        }
+    }
+}
+
+impl fmt::Display for CaptureMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("Unknown capture mode: {0}")]
+pub struct UnknownCaptureMode(String);
+
+impl FromStr for CaptureMode {
+    type Err = UnknownCaptureMode;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "region" => Ok(CaptureMode::Region),
+            "window" => Ok(CaptureMode::Window),
+            "screen" => Ok(CaptureMode::Screen),
+            "clipboard" => Ok(CaptureMode::Clipboard),
+            "file" => Ok(CaptureMode::File),
+            "imported" => Ok(CaptureMode::Imported),
+            _ => Err(UnknownCaptureMode(s.into())),
+        }
+    }
+}
+
+/// The window which had focus when a screenshot was taken
+#[derive(Clone, Debug, Default)]
+pub struct WindowInfo {
+    pub title: Option<String>,
+    /// The class part of `WM_CLASS`, i.e. not the instance name
+    pub class: Option<String>,
+}
+
+/// Computes the SHA-256 of the file at `path`, returned as a lowercase hex string
+pub fn hash_file(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut checksum =
+        glib::Checksum::new(glib::ChecksumType::Sha256).expect("glib should support SHA-256");
+    let mut buffer = [0u8; 64 * 1024];
+
+    loop {
+        match file.read(&mut buffer)? {
+            0 => break,
+            read => checksum.update(&buffer[..read]),
+        }
+    }
+
+    Ok(checksum
+        .string()
+        .expect("The checksum's string should be available"))
+}
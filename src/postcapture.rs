@@ -1,3 +1,5 @@
+use std::path::Path;
+
 use diesel::SqliteConnection;
 use gtk4::{
     gdk::{self, prelude::*},
@@ -5,10 +7,28 @@ use gtk4::{
     gio,
 };
 
-use crate::historymodel::HistoryModel;
+use crate::{
+    db::{self, models::NewScreenshot},
+    editor::Project,
+    historymodel::HistoryModel,
+    metadata::{self, CaptureMode, WindowInfo},
+};
+
+/// A screenshot which is ready to be handed to a [`PostCaptureAction`], together with what we know
+/// about how it was taken
+pub struct Capture<'a> {
+    pub pixbuf: Pixbuf,
+    /// The editor's state, if the screenshot went through the editor
+    pub project: Option<&'a Project>,
+    pub mode: CaptureMode,
+    /// The window which had focus when the screenshot was taken
+    pub window: Option<WindowInfo>,
+    /// The connector name of the monitor the screenshot was taken on
+    pub monitor: Option<String>,
+}
 
 pub trait PostCaptureAction {
-    fn handle(&self, history_model: &HistoryModel, conn: &SqliteConnection, pixbuf: Pixbuf);
+    fn handle(&self, history_model: &HistoryModel, conn: &SqliteConnection, capture: Capture);
 }
 
 pub fn current_action() -> &'static dyn PostCaptureAction {
@@ -21,7 +41,14 @@ pub fn current_action() -> &'static dyn PostCaptureAction {
 struct SaveAndCopy;
 
 impl PostCaptureAction for SaveAndCopy {
-    fn handle(&self, history_model: &HistoryModel, conn: &SqliteConnection, pixbuf: Pixbuf) {
+    fn handle(&self, history_model: &HistoryModel, conn: &SqliteConnection, capture: Capture) {
+        let Capture {
+            pixbuf,
+            project,
+            mode,
+            window,
+            monitor,
+        } = capture;
         let now = chrono::Local::now();
 
         let settings = gio::Settings::new("kc.kcshot");
@@ -33,11 +60,21 @@ impl PostCaptureAction for SaveAndCopy {
         };
 
         let res = pixbuf.savev(&path, "png", &[]);
+        let saved = res.is_ok();
 
-        match res {
-            Ok(_) => {}
-            Err(why) => tracing::error!("Failed to save screenshot to file: {}", why),
-        }
+        let project_path = match res {
+            Ok(_) => project.and_then(|project| match project.save_next_to(Path::new(&path)) {
+                Ok(project_path) => Some(project_path.to_string_lossy().into_owned()),
+                Err(why) => {
+                    tracing::error!("Failed to save project: {}", why);
+                    None
+                }
+            }),
+            Err(why) => {
+                tracing::error!("Failed to save screenshot to file: {}", why);
+                None
+            }
+        };
 
         let display = match gdk::Display::default() {
             Some(display) => display,
@@ -50,6 +87,44 @@ impl PostCaptureAction for SaveAndCopy {
 
         clipboard.set_texture(&gdk::Texture::for_pixbuf(&pixbuf));
 
-        history_model.add_item_to_history(conn, Some(path), now.to_rfc3339(), None)
+        let (size, hash) = if saved {
+            let size = match std::fs::metadata(&path) {
+                Ok(file_metadata) => Some(file_metadata.len() as i64),
+                Err(why) => {
+                    tracing::warn!("Failed to get the size of the screenshot: {}", why);
+                    None
+                }
+            };
+            let hash = match metadata::hash_file(Path::new(&path)) {
+                Ok(hash) => Some(hash),
+                Err(why) => {
+                    tracing::warn!("Failed to hash the screenshot: {}", why);
+                    None
+                }
+            };
+            (size, hash)
+        } else {
+            (None, None)
+        };
+        let window = window.unwrap_or_default();
+        let time = now.to_rfc3339();
+
+        let screenshot = NewScreenshot {
+            path: Some(path),
+            taken_at: db::taken_at(&time),
+            time,
+            url: None,
+            project_path,
+            width: Some(pixbuf.width()),
+            height: Some(pixbuf.height()),
+            size,
+            format: Some("png".into()),
+            hash,
+            capture_mode: Some(mode.as_str().into()),
+            window_title: window.title,
+            window_class: window.class,
+            monitor,
+        };
+        history_model.add_item_to_history(conn, &screenshot)
     }
 }